@@ -15,9 +15,10 @@ use tonic::body::Body;
 use tonic::codegen::http::{HeaderValue, Request, Response};
 use tonic::transport::Server;
 use tonic::{async_trait, Status};
+use tonic_middleware::config::{PipelineConfig, Registry};
 use tonic_middleware::{
-    InterceptorFor, Middleware, MiddlewareFor, MiddlewareLayer, RequestInterceptor,
-    RequestInterceptorLayer, ServiceBound,
+    Authorization, AuthorizationInterceptor, FromRequest, InterceptorFor, Metadata, Middleware,
+    Principal, RequestInterceptor, RolePolicy, ServiceBound,
 };
 
 #[tokio::main]
@@ -25,10 +26,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let addr: SocketAddr = "[::1]:50051".parse().unwrap();
 
     let auth_interceptor = AuthInterceptor {
-        auth_service: Arc::new(AuthServiceImpl::default()),
+        auth_service: Arc::new(AuthServiceImpl),
     };
 
-    let metrics_middleware = MetricsMiddleware::default();
+    // Authenticated users may only list their own orders
+    let authorization_interceptor = AuthorizationInterceptor::new(
+        RolePolicy::new().allow("customer", "/estore.OrderService/GetMyOrders"),
+    );
+
+    let metrics_middleware = MetricsMiddleware;
 
     let products_service = Products::default();
     let grpc_products_service = ProductServiceServer::new(products_service);
@@ -36,20 +42,29 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let orders_service = Orders::default();
     let grpc_orders_service = OrderServiceServer::new(orders_service);
 
+    // Interceptors and middlewares applied to all services are described per environment in
+    // `config/<environment>.toml`, custom ones are registered under the name used there.
+    let environment = std::env::var("APP_ENV").unwrap_or_else(|_| "development".to_string());
+    let pipeline = PipelineConfig::load(format!(
+        "{}/config/{}.toml",
+        env!("CARGO_MANIFEST_DIR"),
+        environment
+    ))?;
+    let registry = Registry::new()
+        .register_middleware("metrics", move |_stage| Ok(metrics_middleware.clone()));
+    let layer = registry.build_layer(&pipeline)?;
+
     println!("Grpc server listening on {}", addr);
 
     Server::builder()
-        // Interceptor can be added as a layer so all services will be intercepted
-        // .layer(RequestInterceptorLayer::new(auth_interceptor.clone()))
-        // Middleware can also be added as a layer, so it will apply to all services
-        // .layer(MiddlewareLayer::new(metrics_middleware))
-        // Middleware can be added to individual service
-        .add_service(MiddlewareFor::new(
-            grpc_products_service,
-            metrics_middleware,
+        .layer(layer)
+        .add_service(grpc_products_service)
+        // Interceptor can be added to individual service as well.
+        // Authorization runs after authentication, as the outermost interceptor is executed first.
+        .add_service(InterceptorFor::new(
+            InterceptorFor::new(grpc_orders_service, authorization_interceptor),
+            auth_interceptor,
         ))
-        // Interceptor can be added to individual service as well
-        .add_service(InterceptorFor::new(grpc_orders_service, auth_interceptor))
         // Middlewares and interceptors can be combined, in any order.
         // Outermost will be executed first
         // .add_service(MiddlewareFor::new(InterceptorFor::new(grpc_orders_service.clone(), auth_interceptor.clone()), metrics_middleware.clone()))
@@ -66,23 +81,24 @@ pub struct AuthInterceptor<A: AuthService> {
 #[async_trait]
 impl<A: AuthService> RequestInterceptor for AuthInterceptor<A> {
     async fn intercept(&self, mut req: Request<Body>) -> Result<Request<Body>, Status> {
-        match req.headers().get("authorization").map(|v| v.to_str()) {
-            Some(Ok(token)) => {
-                // Get user id from the token
-                let user_id = self
-                    .auth_service
-                    .verify_token(token)
-                    .await
-                    .map_err(Status::unauthenticated)?;
-
-                // Set user id in header, so it can be used in grpc services through tonic::Request::metadata()
-                let user_id_header_value = HeaderValue::from_str(&user_id.to_string())
-                    .map_err(|_e| Status::internal("Failed to convert user_id to header value"))?;
-                req.headers_mut().insert("user_id", user_id_header_value);
-                Ok(req)
-            }
-            _ => Err(Status::unauthenticated("Unauthenticated")),
-        }
+        let token = Metadata::<Authorization>::from_request(&req)?;
+
+        // Get user id from the token
+        let user_id = self
+            .auth_service
+            .verify_token(&token)
+            .await
+            .map_err(Status::unauthenticated)?;
+
+        // Set user id in header, so it can be used in grpc services through tonic::Request::metadata()
+        let user_id_header_value = HeaderValue::from_str(&user_id.to_string())
+            .map_err(|_e| Status::internal("Failed to convert user_id to header value"))?;
+        req.headers_mut().insert("user_id", user_id_header_value);
+
+        // Make the authenticated user available to authorization
+        req.extensions_mut()
+            .insert(Principal::new(user_id).with_roles(["customer"]));
+        Ok(req)
     }
 }
 
@@ -6,9 +6,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     tonic_prost_build::configure()
         .build_server(true)
+        .type_attribute(".test_services", "#[derive(serde::Serialize)]")
         .include_file("util.rs")
         .out_dir(out_dir.clone())
         .file_descriptor_set_path(out_dir.clone().join("test_services.bin"))
         .compile_protos(&["proto/test_services.proto"], &["proto"])?;
+    tonic_middleware::build::generate_method_options(
+        out_dir.join("test_services.bin"),
+        out_dir.join("method_options.rs"),
+    )?;
     Ok(())
 }
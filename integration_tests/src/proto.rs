@@ -1 +1,6 @@
 include!(concat!(env!("OUT_DIR"), "/util.rs"));
+
+/// Encoded `FileDescriptorSet` of the test services.
+pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("test_services");
+
+include!(concat!(env!("OUT_DIR"), "/method_options.rs"));
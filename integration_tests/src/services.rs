@@ -3,11 +3,19 @@ use crate::proto::test_services::public_service_server::PublicService as GrpcPub
 use crate::proto::test_services::{
     ProtectedMethodRequest, ProtectedMethodResponse, PublicMethodRequest, PublicMethodResponse,
 };
+use http_body::Frame;
+use std::convert::Infallible;
+use std::pin::Pin;
 use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
 use tonic::body::Body;
-use tonic::codegen::http::HeaderValue;
+use tonic::codegen::http::{HeaderMap, HeaderValue};
+use tonic::codegen::Bytes;
 use tonic::{async_trait, Request, Response, Status};
-use tonic_middleware::{Middleware, RequestInterceptor, ServiceBound};
+use tonic_middleware::{
+    interceptor, middleware, CallStats, Middleware, Next, Principal, RequestInterceptor,
+    ServiceBound,
+};
 
 pub static USER_ID_HEADER_KEY: &str = "user_id";
 pub static USER_ID: &str = "user-1";
@@ -164,3 +172,157 @@ impl Flow {
         actions.clone()
     }
 }
+
+/// Copies the id of the authenticated `Principal` into the `user_id` header returned by
+/// `ProtectedService`.
+#[derive(Clone, Default)]
+pub struct PrincipalToHeaderInterceptor;
+
+#[async_trait]
+impl RequestInterceptor for PrincipalToHeaderInterceptor {
+    async fn intercept(
+        &self,
+        mut req: tonic::codegen::http::Request<Body>,
+    ) -> Result<tonic::codegen::http::Request<Body>, Status> {
+        let principal = req
+            .extensions()
+            .get::<Principal>()
+            .ok_or_else(|| Status::unauthenticated("Unauthenticated"))?;
+        let user_id = HeaderValue::from_str(&principal.id)
+            .map_err(|_e| Status::internal("Failed set header value"))?;
+        req.headers_mut().insert(USER_ID_HEADER_KEY, user_id);
+        Ok(req)
+    }
+}
+
+/// Replaces the status in the trailers of successful responses with the given status, simulating
+/// a streaming call failing after messages were sent.
+#[derive(Clone)]
+pub struct TrailerStatusMiddleware {
+    pub status: Arc<Status>,
+}
+
+impl TrailerStatusMiddleware {
+    pub fn new(status: Status) -> Self {
+        Self {
+            status: Arc::new(status),
+        }
+    }
+}
+
+#[async_trait]
+impl<S> Middleware<S> for TrailerStatusMiddleware
+where
+    S: ServiceBound,
+    S::Future: Send,
+{
+    async fn call(
+        &self,
+        req: tonic::codegen::http::Request<Body>,
+        mut service: S,
+    ) -> Result<tonic::codegen::http::Response<Body>, S::Error> {
+        let response = service.call(req).await?;
+        let status = self.status.clone();
+        Ok(response.map(|inner| Body::new(TrailerStatusBody { inner, status })))
+    }
+}
+
+struct TrailerStatusBody {
+    inner: Body,
+    status: Arc<Status>,
+}
+
+impl http_body::Body for TrailerStatusBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        match Pin::new(&mut self.inner).poll_frame(cx) {
+            Poll::Ready(Some(Ok(frame))) if frame.is_trailers() => {
+                let mut trailers = HeaderMap::new();
+                self.status.add_header(&mut trailers).unwrap();
+                Poll::Ready(Some(Ok(Frame::trailers(trailers))))
+            }
+            other => other,
+        }
+    }
+}
+
+/// Completed call recorded by [CompletionRecorder].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub method: String,
+    pub code: tonic::Code,
+    pub has_trailers: bool,
+    pub response_bytes: u64,
+}
+
+/// Records the outcome of every call it observes.
+#[derive(Clone, Default)]
+pub struct CompletionRecorder {
+    pub completions: Arc<Mutex<Vec<Completion>>>,
+}
+
+impl CompletionRecorder {
+    pub fn read_completions(&self) -> Vec<Completion> {
+        self.completions.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl<S> Middleware<S> for CompletionRecorder
+where
+    S: ServiceBound,
+    S::Future: Send,
+{
+    async fn call(
+        &self,
+        req: tonic::codegen::http::Request<Body>,
+        mut service: S,
+    ) -> Result<tonic::codegen::http::Response<Body>, S::Error> {
+        service.call(req).await
+    }
+
+    fn observe_completion(&self) -> bool {
+        true
+    }
+
+    fn on_complete(&self, status: &Status, trailers: Option<&HeaderMap>, stats: &CallStats) {
+        self.completions.lock().unwrap().push(Completion {
+            method: stats.method.clone(),
+            code: status.code(),
+            has_trailers: trailers.is_some(),
+            response_bytes: stats.response_bytes,
+        });
+    }
+}
+
+/// Rejects requests without the `name` header.
+#[interceptor]
+pub async fn require_header(
+    req: tonic::codegen::http::Request<Body>,
+    name: &str,
+) -> Result<tonic::codegen::http::Request<Body>, Status> {
+    if req.headers().contains_key(name) {
+        Ok(req)
+    } else {
+        Err(Status::invalid_argument(format!("Missing {} header", name)))
+    }
+}
+
+/// Adds the `x-served-by` header with the instance name to responses.
+#[middleware(crate = tonic_middleware)]
+pub async fn served_by(
+    req: tonic::codegen::http::Request<Body>,
+    next: Next,
+    instance: &str,
+) -> Result<tonic::codegen::http::Response<Body>, Infallible> {
+    let mut response = next.run(req).await?;
+    if let Ok(value) = HeaderValue::from_str(instance) {
+        response.headers_mut().insert("x-served-by", value);
+    }
+    Ok(response)
+}
@@ -7,11 +7,14 @@ use integration_tests::services::{
     AuthInterceptor, Flow, Interceptor2, Middleware1, ProtectedService, PublicService,
     AUTHORIZATION_HEADER_KEY, TOKEN,
 };
+use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, Issuer, KeyPair, SanType};
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 use tonic::metadata::MetadataValue;
-use tonic::transport::{Channel, Endpoint};
+use tonic::transport::{
+    Certificate, Channel, ClientTlsConfig, Endpoint, Identity, ServerTlsConfig,
+};
 use tonic::Request;
 
 pub static GRPC_SERVER_ADDRS: &str = "[::1]:50051";
@@ -37,6 +40,12 @@ pub struct Services {
     pub channel: Arc<Channel>,
 }
 
+impl Default for Services {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Services {
     pub fn new() -> Self {
         let flow = Arc::new(Flow::default());
@@ -46,7 +55,7 @@ impl Services {
                 .connect_lazy(),
         );
         Self {
-            public_server: Arc::new(PublicServiceServer::new(PublicService::default())),
+            public_server: Arc::new(PublicServiceServer::new(PublicService)),
             public_service_client: Arc::new(PublicServiceClient::new(channel.as_ref().clone())),
             protected_server: Arc::new(ProtectedServiceServer::new(ProtectedService::default())),
             protected_service_client: Arc::new(ProtectedServiceClient::new(
@@ -82,3 +91,67 @@ pub fn mk_public_request() -> Request<PublicMethodRequest> {
 pub async fn sleep() {
     tokio::time::sleep(Duration::from_millis(100)).await;
 }
+
+/// Certificate authority issuing certificates for mutual TLS tests.
+pub struct TestPki {
+    pub ca_certificate: Certificate,
+    issuer: Issuer<'static, KeyPair>,
+}
+
+impl TestPki {
+    pub fn new() -> Self {
+        let mut params = CertificateParams::new(Vec::default()).expect("CA params");
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "tonic-middleware test CA");
+        let key_pair = KeyPair::generate().expect("CA key");
+        let ca = params.self_signed(&key_pair).expect("CA certificate");
+        Self {
+            ca_certificate: Certificate::from_pem(ca.pem()),
+            issuer: Issuer::new(params, key_pair),
+        }
+    }
+
+    pub fn issue(&self, common_name: &str, subject_alt_names: Vec<SanType>) -> Identity {
+        let mut params = CertificateParams::default();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, common_name);
+        params.subject_alt_names = subject_alt_names;
+        let key_pair = KeyPair::generate().expect("Key");
+        let cert = params
+            .signed_by(&key_pair, &self.issuer)
+            .expect("Certificate");
+        Identity::from_pem(cert.pem(), key_pair.serialize_pem())
+    }
+
+    pub fn server_tls_config(&self) -> ServerTlsConfig {
+        let identity = self.issue(
+            "localhost",
+            vec![SanType::DnsName("localhost".try_into().expect("DNS name"))],
+        );
+        ServerTlsConfig::new()
+            .identity(identity)
+            .client_ca_root(self.ca_certificate.clone())
+    }
+
+    pub fn client_channel(&self, identity: Identity) -> Channel {
+        Endpoint::from_shared(format!("https://{}", grpc_server_addr()))
+            .expect("endpoint")
+            .tls_config(
+                ClientTlsConfig::new()
+                    .ca_certificate(self.ca_certificate.clone())
+                    .identity(identity)
+                    .domain_name("localhost"),
+            )
+            .expect("TLS config")
+            .connect_lazy()
+    }
+}
+
+impl Default for TestPki {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -2,14 +2,59 @@ pub mod common;
 
 use integration_tests::proto;
 
-use crate::common::{grpc_server_addr, mk_protected_request, mk_public_request, sleep, Services};
-use crate::proto::test_services::ProtectedMethodRequest;
-use integration_tests::services::{Action, USER_ID};
+use crate::common::{
+    grpc_server_addr, mk_protected_request, mk_public_request, sleep, Services, TestPki,
+};
+use crate::proto::test_services::protected_service_client::ProtectedServiceClient;
+use crate::proto::test_services::protected_service_server::ProtectedServiceServer;
+use crate::proto::test_services::public_service_client::PublicServiceClient;
+use crate::proto::test_services::public_service_server::PublicServiceServer;
+use crate::proto::test_services::{
+    ProtectedMethodRequest, PublicMethodRequest, PublicMethodResponse,
+};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use http_body::Body as _;
+use http_body_util::{BodyExt, Full};
+use integration_tests::services::{
+    require_header, Action, CompletionRecorder, PrincipalToHeaderInterceptor, ProtectedService,
+    PublicService, RequireHeader, ServedBy, TrailerStatusMiddleware, USER_ID,
+};
+use prost::Message;
+use rcgen::SanType;
 use serial_test::serial;
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::oneshot;
+use tonic::body::Body;
+use tonic::codec::CompressionEncoding;
+use tonic::codegen::{http, Bytes, Service};
+use tonic::metadata::MetadataValue;
 use tonic::transport::Server;
-use tonic::Code;
-use tonic_middleware::{InterceptorFor, MiddlewareFor, MiddlewareLayer, RequestInterceptorLayer};
+use tonic::{Code, Status};
+use tonic_health::pb::health_check_response::ServingStatus;
+use tonic_health::pb::health_client::HealthClient;
+use tonic_health::pb::HealthCheckRequest;
+use tonic_middleware::config::{PipelineConfig, Registry};
+use tonic_middleware::{
+    extract_fn, from_fn, interceptor_fn, metadata_key, middleware_fn, verify_audit_log,
+    ApiKeyEntry, ApiKeyInterceptor, AuditMiddleware, Authorization, AuthorizationInterceptor,
+    BoxInterceptor, BoxMiddleware, BufferedRequest, CompressionPolicyMiddleware, CorsMiddleware,
+    Deadline, DrainMiddleware, DynStack, Encoding, Extension, FileAuditSink, GrpcMethod,
+    GrpcMethodExt, GrpcWebMiddleware, HashedApiKey, HealthReportingMiddleware,
+    IdempotencyMiddleware, InMemoryApiKeyStore, InMemoryIdempotencyStore, InMemoryNonceStore,
+    InMemoryTenantResolver, InterceptorFor, MaxMessageSizeInterceptor, Metadata, MetadataPolicy,
+    MethodPath, MiddlewareFor, MiddlewareLayer, Next, OptionValue, PeerAddr,
+    PeerIdentityInterceptor, Principal, RateLimit, RateLimitInterceptor, RedactErrorsMiddleware,
+    Reloadable, RequestInterceptor, RequestInterceptorLayer, RolePolicy, SignatureInterceptor,
+    SigningMiddleware, StatusDetailsExt, StatusEnricherMiddleware, StreamingKind, Tenant,
+    TenantMiddleware, TenantPolicy, API_KEY_HEADER, SIGNATURE_HEADER, SIGNATURE_NONCE_HEADER,
+    SIGNATURE_TIMESTAMP_HEADER, TENANT_ATTRIBUTE, TENANT_HEADER,
+};
+use tonic_types::StatusExt;
+use tower::Layer;
 
 #[tokio::test]
 #[serial]
@@ -418,3 +463,2563 @@ async fn test_interceptor_and_middleware_combined_for_all_services_through_layer
     tx.send(()).unwrap();
     jh.await.unwrap();
 }
+
+#[tokio::test]
+#[serial]
+async fn test_interceptor_and_middleware_wrap_client_channel() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let flow = services.flow.clone();
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .add_service(public_server)
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    // `Channel` is a buffer which must be called only once driven to readiness
+    let channel = InterceptorFor::new(
+        services.channel.as_ref().clone(),
+        services.interceptor2.as_ref().clone(),
+    );
+    let channel = MiddlewareFor::new(channel, services.middleware1.as_ref().clone());
+    let client = PublicServiceClient::new(channel);
+    let calls: Vec<_> = (0..3)
+        .map(|_| {
+            let mut client = client.clone();
+            tokio::spawn(async move { client.public_method(mk_public_request()).await })
+        })
+        .collect();
+    for call in calls {
+        let response = call.await.unwrap().expect("Method response");
+        assert_eq!(response.into_inner().message, "Hello Public!");
+    }
+    assert_eq!(flow.read_actions().len(), 9);
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_max_message_size_interceptor_applies_per_method_limits() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let protected_server = services.protected_server.as_ref().clone();
+    let auth_interceptor = services.auth_interceptor.as_ref().clone();
+    let max_message_size = MaxMessageSizeInterceptor::new(1024 * 1024)
+        .method_limit("/test_services.PublicService/*", 64);
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(RequestInterceptorLayer::new(max_message_size))
+            .add_service(public_server)
+            .add_service(InterceptorFor::new(protected_server, auth_interceptor))
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let mut public_service_client = services.public_service_client.as_ref().clone();
+    public_service_client
+        .public_method(mk_public_request())
+        .await
+        .expect("Public method response");
+
+    let result = public_service_client
+        .public_method(PublicMethodRequest {
+            message: "a".repeat(100),
+        })
+        .await;
+    assert!(result.is_err_and(|e| e.code() == Code::ResourceExhausted));
+
+    let mut request = mk_protected_request();
+    request.get_mut().message = "a".repeat(100);
+    services
+        .protected_service_client
+        .as_ref()
+        .clone()
+        .protected_method(request)
+        .await
+        .expect("Method response");
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_max_message_size_interceptor_caps_bytes_per_call() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let max_message_size = MaxMessageSizeInterceptor::default().max_call_bytes(32);
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .add_service(InterceptorFor::new(public_server, max_message_size))
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let result = services
+        .public_service_client
+        .as_ref()
+        .clone()
+        .public_method(PublicMethodRequest {
+            message: "a".repeat(64),
+        })
+        .await;
+    assert!(result.is_err_and(|e| e.code() == Code::ResourceExhausted));
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_metadata_policy_validates_request_metadata() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let protected_server = services.protected_server.as_ref().clone();
+    let auth_interceptor = services.auth_interceptor.as_ref().clone();
+    let metadata_policy = MetadataPolicy::new()
+        .require("/test_services.ProtectedService/*", "x-request-id")
+        .allowed_values("x-request-id", r"^req-[0-9]+$")
+        .unwrap()
+        .trim("x-request-id");
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(RequestInterceptorLayer::new(metadata_policy))
+            .add_service(public_server)
+            .add_service(InterceptorFor::new(protected_server, auth_interceptor))
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let mut protected_service_client = services.protected_service_client.as_ref().clone();
+
+    let result = protected_service_client
+        .protected_method(mk_protected_request())
+        .await;
+    assert!(result.is_err_and(|e| e.code() == Code::InvalidArgument));
+
+    let mut request = mk_protected_request();
+    request
+        .metadata_mut()
+        .insert("x-request-id", "not-a-request-id".parse().unwrap());
+    let result = protected_service_client.protected_method(request).await;
+    assert!(result.is_err_and(|e| e.code() == Code::InvalidArgument));
+
+    let mut request = mk_protected_request();
+    request
+        .metadata_mut()
+        .insert("x-request-id", " req-42 ".parse().unwrap());
+    protected_service_client
+        .protected_method(request)
+        .await
+        .expect("Method response");
+
+    let mut request = mk_public_request();
+    request
+        .metadata_mut()
+        .insert_bin("trace-bin", MetadataValue::from_bytes(b"trace"));
+    services
+        .public_service_client
+        .as_ref()
+        .clone()
+        .public_method(request)
+        .await
+        .expect("Public method response");
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_api_key_interceptor_authenticates_and_checks_scopes() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let protected_server = services.protected_server.as_ref().clone();
+    let entry = ApiKeyEntry::new(
+        HashedApiKey::new("s3cr3t").unwrap(),
+        Principal::new("partner-1").with_scopes(["public:read"]),
+    );
+    let store: InMemoryApiKeyStore = entry.to_line("partner-1").parse().expect("API key store");
+    let api_key_interceptor = ApiKeyInterceptor::new(store)
+        .require_scope("/test_services.PublicService/PublicMethod", "public:read")
+        .require_scope("/test_services.ProtectedService/*", "protected:write");
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(RequestInterceptorLayer::new(api_key_interceptor))
+            .add_service(public_server)
+            .add_service(protected_server)
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let mut public_service_client = services.public_service_client.as_ref().clone();
+
+    let result = public_service_client
+        .public_method(mk_public_request())
+        .await;
+    assert!(result.is_err_and(|e| e.code() == Code::Unauthenticated));
+
+    let mut request = mk_public_request();
+    request
+        .metadata_mut()
+        .insert(API_KEY_HEADER, "partner-1.wrong".parse().unwrap());
+    let result = public_service_client.public_method(request).await;
+    assert!(result.is_err_and(|e| e.code() == Code::Unauthenticated));
+
+    let mut request = mk_public_request();
+    request
+        .metadata_mut()
+        .insert(API_KEY_HEADER, "partner-1.s3cr3t".parse().unwrap());
+    public_service_client
+        .public_method(request)
+        .await
+        .expect("Public method response");
+
+    let mut request = mk_protected_request();
+    request
+        .metadata_mut()
+        .insert(API_KEY_HEADER, "partner-1.s3cr3t".parse().unwrap());
+    let result = services
+        .protected_service_client
+        .as_ref()
+        .clone()
+        .protected_method(request)
+        .await;
+    assert!(result.is_err_and(|e| e.code() == Code::PermissionDenied));
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_authorization_interceptor_enforces_role_policy() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let protected_server = services.protected_server.as_ref().clone();
+    let mut store = InMemoryApiKeyStore::new();
+    store
+        .insert(
+            "customer",
+            "s3cr3t",
+            Principal::new("customer").with_roles(["customer"]),
+        )
+        .unwrap();
+    let api_key_interceptor = ApiKeyInterceptor::new(store);
+    let policy = RolePolicy::from_toml(
+        r#"
+        [roles]
+        admin = ["*"]
+        customer = ["/test_services.PublicService/*"]
+        "#,
+    )
+    .expect("Role policy");
+    let authorization_interceptor = AuthorizationInterceptor::new(policy);
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(RequestInterceptorLayer::new(api_key_interceptor))
+            .layer(RequestInterceptorLayer::new(authorization_interceptor))
+            .add_service(public_server)
+            .add_service(protected_server)
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let mut request = mk_public_request();
+    request
+        .metadata_mut()
+        .insert(API_KEY_HEADER, "customer.s3cr3t".parse().unwrap());
+    services
+        .public_service_client
+        .as_ref()
+        .clone()
+        .public_method(request)
+        .await
+        .expect("Public method response");
+
+    let mut request = mk_protected_request();
+    request
+        .metadata_mut()
+        .insert(API_KEY_HEADER, "customer.s3cr3t".parse().unwrap());
+    let result = services
+        .protected_service_client
+        .as_ref()
+        .clone()
+        .protected_method(request)
+        .await;
+    assert!(result.is_err_and(|e| e.code() == Code::PermissionDenied));
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_peer_identity_interceptor_extracts_and_allows_client_certificates() {
+    let pki = TestPki::new();
+    let public_server = PublicServiceServer::new(PublicService);
+    let protected_server = ProtectedServiceServer::new(ProtectedService::default());
+    let peer_identity_interceptor = PeerIdentityInterceptor::new()
+        .allow(
+            "/test_services.ProtectedService/*",
+            "spiffe://estore.internal/ns/checkout/*",
+        )
+        .allow(
+            "/test_services.PublicService/*",
+            "spiffe://estore.internal/ns/admin/*",
+        )
+        .insert_principal(true);
+    let tls_config = pki.server_tls_config();
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .tls_config(tls_config)
+            .unwrap()
+            .layer(RequestInterceptorLayer::new(peer_identity_interceptor))
+            .add_service(public_server)
+            .add_service(InterceptorFor::new(
+                protected_server,
+                PrincipalToHeaderInterceptor,
+            ))
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let spiffe_id = "spiffe://estore.internal/ns/checkout/sa/default";
+    let channel = pki.client_channel(pki.issue(
+        "checkout",
+        vec![SanType::URI(spiffe_id.try_into().unwrap())],
+    ));
+
+    let result = ProtectedServiceClient::new(channel.clone())
+        .protected_method(mk_protected_request())
+        .await
+        .expect("Method response");
+    assert_eq!(result.get_ref().user_id, spiffe_id);
+
+    let result = PublicServiceClient::new(channel)
+        .public_method(mk_public_request())
+        .await;
+    assert!(result.is_err_and(|e| e.code() == Code::PermissionDenied));
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_dyn_stack_assembled_at_runtime() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let protected_server = services.protected_server.as_ref().clone();
+    let middleware1 = services.middleware1.as_ref().clone();
+    let auth_interceptor = services.auth_interceptor.as_ref().clone();
+    let interceptor2 = services.interceptor2.as_ref().clone();
+    let flow = services.flow;
+
+    let server_stack: DynStack = vec![
+        BoxMiddleware::new(middleware1).into(),
+        BoxInterceptor::new(interceptor2).into(),
+    ]
+    .into();
+    let protected_stack = DynStack::new().push_interceptor(auth_interceptor);
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(server_stack)
+            .add_service(public_server)
+            .add_service(protected_stack.layer(protected_server))
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    let mut protected_service_client = services.protected_service_client.as_ref().clone();
+
+    sleep().await;
+
+    let result = protected_service_client
+        .protected_method(mk_protected_request())
+        .await
+        .expect("Method response");
+
+    assert_eq!(result.get_ref().user_id, USER_ID);
+
+    services
+        .public_service_client
+        .as_ref()
+        .clone()
+        .public_method(mk_public_request())
+        .await
+        .expect("Method response");
+
+    let actions: Vec<Action> = flow.read_actions();
+    assert_eq!(actions.len(), 7);
+    assert_eq!(actions[0], Action::Middleware1Before);
+    assert_eq!(actions[1], Action::Interceptor2);
+    assert_eq!(actions[2], Action::AuthInterceptor);
+    assert_eq!(actions[3], Action::Middleware1After);
+    assert_eq!(actions[4], Action::Middleware1Before);
+    assert_eq!(actions[5], Action::Interceptor2);
+    assert_eq!(actions[6], Action::Middleware1After);
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_reloadable_interceptor_picks_up_new_configuration() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let protected_server = services.protected_server.as_ref().clone();
+    let auth_interceptor = services.auth_interceptor.as_ref().clone();
+    let metadata_policy = Reloadable::new(MetadataPolicy::new());
+    let layer = RequestInterceptorLayer::new(metadata_policy.clone());
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(layer)
+            .add_service(public_server)
+            .add_service(InterceptorFor::new(protected_server, auth_interceptor))
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let mut protected_service_client = services.protected_service_client.as_ref().clone();
+
+    protected_service_client
+        .protected_method(mk_protected_request())
+        .await
+        .expect("Method response");
+
+    metadata_policy
+        .store(MetadataPolicy::new().require("/test_services.ProtectedService/*", "x-request-id"));
+    let result = protected_service_client
+        .protected_method(mk_protected_request())
+        .await;
+    assert!(result.is_err_and(|e| e.code() == Code::InvalidArgument));
+
+    let path = std::env::temp_dir().join("tonic_middleware_reloadable_test.txt");
+    std::fs::write(&path, "x-request-id").unwrap();
+    let watcher = metadata_policy.watch_file(&path, Duration::from_millis(20), |content| {
+        Ok::<_, Infallible>(
+            MetadataPolicy::new().require("/test_services.ProtectedService/*", content.trim()),
+        )
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    std::fs::write(&path, "x-tenant-id").unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut request = mk_protected_request();
+    request
+        .metadata_mut()
+        .insert("x-request-id", "req-1".parse().unwrap());
+    let result = protected_service_client.protected_method(request).await;
+    assert!(result.is_err_and(|e| e.code() == Code::InvalidArgument));
+
+    let mut request = mk_protected_request();
+    request
+        .metadata_mut()
+        .insert("x-tenant-id", "tenant-1".parse().unwrap());
+    protected_service_client
+        .protected_method(request)
+        .await
+        .expect("Method response");
+
+    watcher.abort();
+    std::fs::remove_file(&path).unwrap();
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_reloadable_middleware_reports_completion_to_the_value_handling_the_call() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let old_recorder = CompletionRecorder::default();
+    let new_recorder = CompletionRecorder::default();
+    let recorder = Reloadable::new(old_recorder.clone());
+    let delay = middleware_fn(|req, next| async move {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        next.run(req).await
+    });
+
+    let recorder_clone = recorder.clone();
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .add_service(MiddlewareFor::new(
+                MiddlewareFor::new(public_server, delay),
+                recorder_clone,
+            ))
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let mut public_service_client = services.public_service_client.as_ref().clone();
+    let mut client = public_service_client.clone();
+    let in_flight = tokio::spawn(async move { client.public_method(mk_public_request()).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    recorder.store(new_recorder.clone());
+    in_flight.await.unwrap().expect("In-flight call response");
+    assert_eq!(old_recorder.read_completions().len(), 1);
+    assert!(new_recorder.read_completions().is_empty());
+
+    public_service_client
+        .public_method(mk_public_request())
+        .await
+        .expect("Method response");
+    assert_eq!(old_recorder.read_completions().len(), 1);
+    assert_eq!(new_recorder.read_completions().len(), 1);
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_pipeline_built_from_configuration() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let protected_server = services.protected_server.as_ref().clone();
+    let middleware1 = services.middleware1.as_ref().clone();
+    let auth_interceptor = services.auth_interceptor.as_ref().clone();
+    let flow = services.flow;
+
+    let config = PipelineConfig::from_yaml(
+        r#"
+pipeline:
+  - type: middleware1
+    methods: ["/test_services.PublicService/*"]
+  - type: metadata_policy
+    methods: ["/test_services.ProtectedService/ProtectedMethod"]
+    require:
+      "*": ["x-request-id"]
+"#,
+    )
+    .unwrap();
+    let registry =
+        Registry::new().register_middleware("middleware1", move |_stage| Ok(middleware1.clone()));
+    let layer = registry.build_layer(&config).unwrap();
+
+    let unknown = PipelineConfig::from_toml("[[pipeline]]\ntype = \"unknown\"").unwrap();
+    assert!(registry.build_layer(&unknown).is_err());
+    let invalid =
+        PipelineConfig::from_toml("[[pipeline]]\ntype = \"max_message_size\"\nmax_size = 1")
+            .unwrap();
+    assert!(registry.build_layer(&invalid).is_err());
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(layer)
+            .add_service(public_server)
+            .add_service(InterceptorFor::new(protected_server, auth_interceptor))
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let mut protected_service_client = services.protected_service_client.as_ref().clone();
+
+    let result = protected_service_client
+        .protected_method(mk_protected_request())
+        .await;
+    assert!(result.is_err_and(|e| e.code() == Code::InvalidArgument));
+
+    let mut request = mk_protected_request();
+    request
+        .metadata_mut()
+        .insert("x-request-id", "req-1".parse().unwrap());
+    protected_service_client
+        .protected_method(request)
+        .await
+        .expect("Method response");
+
+    services
+        .public_service_client
+        .as_ref()
+        .clone()
+        .public_method(mk_public_request())
+        .await
+        .expect("Method response");
+
+    let actions: Vec<Action> = flow.read_actions();
+    assert_eq!(actions.len(), 3);
+    assert_eq!(actions[0], Action::AuthInterceptor);
+    assert_eq!(actions[1], Action::Middleware1Before);
+    assert_eq!(actions[2], Action::Middleware1After);
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_status_enricher_attaches_rich_error_details() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let enricher = StatusEnricherMiddleware::new("test.local")
+        .retry_after(Code::InvalidArgument, Duration::from_secs(2))
+        .enrich(Code::InvalidArgument, |status, details| {
+            details.add_bad_request_violation("x-request-id", status.message());
+        });
+    let metadata_policy =
+        MetadataPolicy::new().require("/test_services.PublicService/*", "x-request-id");
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(MiddlewareLayer::new(enricher))
+            .layer(RequestInterceptorLayer::new(metadata_policy))
+            .add_service(public_server)
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let status = services
+        .public_service_client
+        .as_ref()
+        .clone()
+        .public_method(mk_public_request())
+        .await
+        .expect_err("Rejected request");
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let error_info = status.get_details_error_info().expect("Error info");
+    assert_eq!(error_info.reason, "INVALID_ARGUMENT");
+    assert_eq!(error_info.domain, "test.local");
+    assert_eq!(
+        error_info.metadata.get("method").map(String::as_str),
+        Some("/test_services.PublicService/PublicMethod")
+    );
+    let retry_info = status.get_details_retry_info().expect("Retry info");
+    assert_eq!(retry_info.retry_delay, Some(Duration::from_secs(2)));
+    let bad_request = status.get_details_bad_request().expect("Bad request");
+    assert_eq!(bad_request.field_violations[0].field, "x-request-id");
+
+    let status = Status::resource_exhausted("Too many requests")
+        .with_quota_failure("client:42", "Limit exceeded")
+        .with_retry_info(Duration::from_secs(30));
+    assert_eq!(status.code(), Code::ResourceExhausted);
+    assert_eq!(status.message(), "Too many requests");
+    assert_eq!(
+        status.get_details_quota_failure().unwrap().violations[0].subject,
+        "client:42"
+    );
+    assert_eq!(
+        status.get_details_retry_info().unwrap().retry_delay,
+        Some(Duration::from_secs(30))
+    );
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_redact_errors_middleware_redacts_headers_and_trailers() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let protected_server = services.protected_server.as_ref().clone();
+    let auth_interceptor = services.auth_interceptor.as_ref().clone();
+    let redact = RedactErrorsMiddleware::new()
+        .redact(Code::InvalidArgument)
+        .message("Something went wrong")
+        .strip_details(true);
+    let metadata_policy =
+        MetadataPolicy::new().require("/test_services.PublicService/*", "x-request-id");
+    let failing_trailers = TrailerStatusMiddleware::new(Status::with_details(
+        Code::Internal,
+        "connection to db:5432 refused",
+        "db:5432".into(),
+    ));
+    let messages = Arc::new(Mutex::new(Vec::new()));
+    let recorded = messages.clone();
+    let record_messages = middleware_fn(move |req, next| {
+        let recorded = recorded.clone();
+        async move {
+            let response = next.run(req).await?;
+            let header = Status::from_header_map(response.headers())
+                .map(|status| status.message().to_string());
+            let extension = response
+                .extensions()
+                .get::<Status>()
+                .map(|status| status.message().to_string());
+            recorded.lock().unwrap().push((header, extension));
+            Ok(response)
+        }
+    });
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(MiddlewareLayer::new(record_messages))
+            .layer(MiddlewareLayer::new(redact))
+            .layer(RequestInterceptorLayer::new(metadata_policy))
+            .add_service(public_server)
+            .add_service(MiddlewareFor::new(
+                InterceptorFor::new(protected_server, auth_interceptor),
+                failing_trailers,
+            ))
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    // Rejected before the service, the status is sent in the response headers
+    let status = services
+        .public_service_client
+        .as_ref()
+        .clone()
+        .public_method(mk_public_request())
+        .await
+        .expect_err("Rejected request");
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert!(status
+        .message()
+        .starts_with("Something went wrong (correlation id: "));
+    // The status extension is redacted with the same correlation id
+    let (header, extension) = messages.lock().unwrap().remove(0);
+    assert_eq!(header.as_deref(), Some(status.message()));
+    assert_eq!(extension.as_deref(), Some(status.message()));
+
+    // Failed after the response message, the status is sent in the trailers
+    let status = services
+        .protected_service_client
+        .as_ref()
+        .clone()
+        .protected_method(mk_protected_request())
+        .await
+        .expect_err("Failed request");
+    assert_eq!(status.code(), Code::Internal);
+    assert!(status
+        .message()
+        .starts_with("Something went wrong (correlation id: "));
+    assert!(!status.message().contains("db:5432"));
+    assert!(status.details().is_empty());
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_middleware_observes_call_completion() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let protected_server = services.protected_server.as_ref().clone();
+    let auth_interceptor = services.auth_interceptor.as_ref().clone();
+    let recorder = CompletionRecorder::default();
+    let metadata_policy =
+        MetadataPolicy::new().require("/test_services.ProtectedService/*", "x-request-id");
+
+    let layer = MiddlewareLayer::new(recorder.clone());
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(layer)
+            .layer(RequestInterceptorLayer::new(metadata_policy))
+            .add_service(public_server)
+            .add_service(InterceptorFor::new(protected_server, auth_interceptor))
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    services
+        .public_service_client
+        .as_ref()
+        .clone()
+        .public_method(mk_public_request())
+        .await
+        .expect("Method response");
+
+    let result = services
+        .protected_service_client
+        .as_ref()
+        .clone()
+        .protected_method(mk_protected_request())
+        .await;
+    assert!(result.is_err_and(|e| e.code() == Code::InvalidArgument));
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let completions = recorder.read_completions();
+    assert_eq!(completions.len(), 2);
+    assert_eq!(
+        completions[0].method,
+        "/test_services.PublicService/PublicMethod"
+    );
+    assert_eq!(completions[0].code, Code::Ok);
+    assert!(completions[0].has_trailers);
+    assert!(completions[0].response_bytes > 0);
+    assert_eq!(
+        completions[1].method,
+        "/test_services.ProtectedService/ProtectedMethod"
+    );
+    assert_eq!(completions[1].code, Code::InvalidArgument);
+    assert!(!completions[1].has_trailers);
+    assert_eq!(completions[1].response_bytes, 0);
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_grpc_web_and_cors_middlewares() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    // Per-service CORS policy inside of the translation applied to all services
+    let mut service = MiddlewareLayer::new(GrpcWebMiddleware::new()).layer(MiddlewareFor::new(
+        public_server,
+        CorsMiddleware::new().allow_origin("https://app.example.com"),
+    ));
+    let path = "/test_services.PublicService/PublicMethod";
+
+    let preflight = |origin: &str| {
+        http::Request::builder()
+            .method(http::Method::OPTIONS)
+            .uri(path)
+            .version(http::Version::HTTP_11)
+            .header(http::header::ORIGIN, origin)
+            .header(http::header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .body(Body::empty())
+            .unwrap()
+    };
+    let response = service
+        .call(preflight("https://app.example.com"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+    assert_eq!(
+        response.headers()[http::header::ACCESS_CONTROL_ALLOW_ORIGIN],
+        "https://app.example.com"
+    );
+    let response = service
+        .call(preflight("https://evil.example.com"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+
+    let message = PublicMethodRequest {
+        message: "Hello!".to_string(),
+    }
+    .encode_to_vec();
+    let mut frame = vec![0];
+    frame.extend((message.len() as u32).to_be_bytes());
+    frame.extend(message);
+    let request = http::Request::builder()
+        .method(http::Method::POST)
+        .uri(path)
+        .version(http::Version::HTTP_11)
+        .header(http::header::ORIGIN, "https://app.example.com")
+        .header(http::header::CONTENT_TYPE, "application/grpc-web-text")
+        .header(http::header::ACCEPT, "application/grpc-web-text")
+        .body(Body::new(BASE64_STANDARD.encode(frame)))
+        .unwrap();
+    let response = service.call(request).await.unwrap();
+    assert_eq!(response.status(), http::StatusCode::OK);
+    assert_eq!(
+        response.headers()[http::header::CONTENT_TYPE],
+        "application/grpc-web-text+proto"
+    );
+    assert_eq!(
+        response.headers()[http::header::ACCESS_CONTROL_ALLOW_ORIGIN],
+        "https://app.example.com"
+    );
+
+    // Every chunk of a text mode response is base64 encoded on its own
+    let mut body = response.into_body();
+    let mut decoded = Vec::new();
+    while let Some(frame) = std::future::poll_fn(|cx| Pin::new(&mut body).poll_frame(cx)).await {
+        if let Ok(data) = frame.unwrap().into_data() {
+            decoded.extend(BASE64_STANDARD.decode(data).unwrap());
+        }
+    }
+    assert_eq!(decoded[0], 0);
+    let len = u32::from_be_bytes(decoded[1..5].try_into().unwrap()) as usize;
+    let reply = PublicMethodResponse::decode(&decoded[5..5 + len]).unwrap();
+    assert_eq!(reply.message, "Hello Public!");
+    let trailers = &decoded[5 + len..];
+    assert_eq!(trailers[0], 0x80);
+    assert!(String::from_utf8_lossy(&trailers[5..]).contains("grpc-status:0"));
+}
+
+#[tokio::test]
+#[serial]
+async fn test_cors_middleware_allows_credentials_only_for_listed_origins() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let path = "/test_services.PublicService/PublicMethod";
+    let request = |origin: &str| {
+        let message = PublicMethodRequest {
+            message: "Hello!".to_string(),
+        }
+        .encode_to_vec();
+        let mut frame = vec![0];
+        frame.extend((message.len() as u32).to_be_bytes());
+        frame.extend(message);
+        http::Request::builder()
+            .method(http::Method::POST)
+            .uri(path)
+            .version(http::Version::HTTP_11)
+            .header(http::header::ORIGIN, origin)
+            .header(http::header::CONTENT_TYPE, "application/grpc-web")
+            .body(Body::new(Full::new(Bytes::from(frame))))
+            .unwrap()
+    };
+
+    let mut service = MiddlewareLayer::new(GrpcWebMiddleware::new()).layer(MiddlewareFor::new(
+        public_server.clone(),
+        CorsMiddleware::new()
+            .allow_origin("https://app.example.com")
+            .allow_credentials(true),
+    ));
+    let response = service
+        .call(request("https://app.example.com"))
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers()[http::header::ACCESS_CONTROL_ALLOW_ORIGIN],
+        "https://app.example.com"
+    );
+    assert_eq!(
+        response.headers()[http::header::ACCESS_CONTROL_ALLOW_CREDENTIALS],
+        "true"
+    );
+    let response = service
+        .call(request("https://evil.example.com"))
+        .await
+        .unwrap();
+    assert!(!response
+        .headers()
+        .contains_key(http::header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    assert!(!response
+        .headers()
+        .contains_key(http::header::ACCESS_CONTROL_ALLOW_CREDENTIALS));
+
+    // Without an allowlist, credentials would be allowed for any origin
+    let mut service = MiddlewareLayer::new(GrpcWebMiddleware::new()).layer(MiddlewareFor::new(
+        public_server,
+        CorsMiddleware::new().allow_credentials(true),
+    ));
+    let response = service
+        .call(request("https://evil.example.com"))
+        .await
+        .unwrap();
+    assert!(!response
+        .headers()
+        .contains_key(http::header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    assert!(!response
+        .headers()
+        .contains_key(http::header::ACCESS_CONTROL_ALLOW_CREDENTIALS));
+}
+
+#[tokio::test]
+#[serial]
+async fn test_compression_policy_middleware_applies_per_method_encodings() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let protected_server = services.protected_server.as_ref().clone();
+    let auth_interceptor = services.auth_interceptor.as_ref().clone();
+    // Neither service accepts compressed requests, the middleware decompresses them
+    let compression = CompressionPolicyMiddleware::new()
+        .forbid("/test_services.PublicService/*")
+        .allow_encodings("/test_services.ProtectedService/*", ["gzip"])
+        .compress_responses("/test_services.ProtectedService/*", Encoding::Gzip)
+        .decompress_requests(true)
+        .max_decompressed_size(1024);
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(MiddlewareLayer::new(compression))
+            .add_service(public_server)
+            .add_service(InterceptorFor::new(protected_server, auth_interceptor))
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let status = services
+        .public_service_client
+        .as_ref()
+        .clone()
+        .send_compressed(CompressionEncoding::Gzip)
+        .public_method(mk_public_request())
+        .await
+        .expect_err("Forbidden encoding");
+    assert_eq!(status.code(), Code::Unimplemented);
+    assert_eq!(
+        status.metadata().get("grpc-accept-encoding").unwrap(),
+        "identity"
+    );
+
+    let response = services
+        .public_service_client
+        .as_ref()
+        .clone()
+        .public_method(mk_public_request())
+        .await
+        .expect("Uncompressed request");
+    assert!(response.metadata().get("grpc-encoding").is_none());
+
+    let response = services
+        .protected_service_client
+        .as_ref()
+        .clone()
+        .send_compressed(CompressionEncoding::Gzip)
+        .accept_compressed(CompressionEncoding::Gzip)
+        .protected_method(mk_protected_request())
+        .await
+        .expect("Allowed encoding");
+    assert_eq!(response.metadata().get("grpc-encoding").unwrap(), "gzip");
+    assert_eq!(response.into_inner().message, "Hello Protected!");
+
+    let status = services
+        .protected_service_client
+        .as_ref()
+        .clone()
+        .send_compressed(CompressionEncoding::Zstd)
+        .protected_method(mk_protected_request())
+        .await
+        .expect_err("Encoding not allowed for the method");
+    assert_eq!(status.code(), Code::Unimplemented);
+
+    // Incompressible message whose compressed frame exceeds the limit
+    let mut seed = 42u32;
+    let message = (0..64 * 1024)
+        .map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            char::from(b'a' + (seed >> 16) as u8 % 26)
+        })
+        .collect::<String>();
+    let mut request = mk_protected_request();
+    request.get_mut().message = message;
+    let status = services
+        .protected_service_client
+        .as_ref()
+        .clone()
+        .send_compressed(CompressionEncoding::Gzip)
+        .protected_method(request)
+        .await
+        .expect_err("Oversized compressed message");
+    assert_eq!(status.code(), Code::ResourceExhausted);
+    assert!(status.message().starts_with("Message of"));
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_audit_middleware_writes_hash_chained_records() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let protected_server = services.protected_server.as_ref().clone();
+    let auth_interceptor = services.auth_interceptor.as_ref().clone();
+    let failing_trailers =
+        TrailerStatusMiddleware::new(Status::permission_denied("Order is locked"));
+    let entry = ApiKeyEntry::new(
+        HashedApiKey::new("s3cr3t").unwrap(),
+        Principal::new("partner-1"),
+    );
+    let store: InMemoryApiKeyStore = entry.to_line("partner-1").parse().expect("API key store");
+    let api_key_interceptor = ApiKeyInterceptor::new(store);
+
+    let path = std::env::temp_dir().join("tonic_middleware_audit_test.jsonl");
+    let _ = std::fs::remove_file(&path);
+    let sink = FileAuditSink::open(&path).await.expect("Audit log");
+    let audit = AuditMiddleware::new(sink)
+        .record_request::<PublicMethodRequest>("/test_services.PublicService/PublicMethod")
+        .redact_field("/test_services.PublicService/*", "message");
+    let audit_handle = audit.clone();
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(RequestInterceptorLayer::new(api_key_interceptor))
+            .layer(MiddlewareLayer::new(audit))
+            .add_service(public_server)
+            .add_service(MiddlewareFor::new(
+                InterceptorFor::new(protected_server, auth_interceptor),
+                failing_trailers,
+            ))
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let mut request = mk_public_request();
+    request
+        .metadata_mut()
+        .insert(API_KEY_HEADER, "partner-1.s3cr3t".parse().unwrap());
+    services
+        .public_service_client
+        .as_ref()
+        .clone()
+        .public_method(request)
+        .await
+        .expect("Public method response");
+
+    // Fails after the response message, the status is read from the trailers
+    let mut request = mk_protected_request();
+    request
+        .metadata_mut()
+        .insert(API_KEY_HEADER, "partner-1.s3cr3t".parse().unwrap());
+    let result = services
+        .protected_service_client
+        .as_ref()
+        .clone()
+        .protected_method(request)
+        .await;
+    assert!(result.is_err());
+
+    audit_handle.flush().await;
+    let log = std::fs::read_to_string(&path).expect("Audit log");
+    let records: Vec<serde_json::Value> = log
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("Audit record"))
+        .collect();
+    assert_eq!(records.len(), 2);
+
+    assert_eq!(
+        records[0]["method"],
+        "/test_services.PublicService/PublicMethod"
+    );
+    assert_eq!(records[0]["principal"], "partner-1");
+    assert_eq!(records[0]["request"]["message"], "[REDACTED]");
+    assert_eq!(records[0]["status"]["code"], "Ok");
+
+    assert_eq!(
+        records[1]["method"],
+        "/test_services.ProtectedService/ProtectedMethod"
+    );
+    assert!(records[1]["request"].is_null());
+    assert_eq!(records[1]["status"]["code"], "PermissionDenied");
+    assert_eq!(records[1]["status"]["message"], "Order is locked");
+    assert_eq!(records[1]["prev_hash"], records[0]["hash"]);
+
+    assert_eq!(verify_audit_log(log.as_bytes()).expect("Valid chain"), 2);
+    let tampered = log.replacen("partner-1", "partner-2", 1);
+    assert!(verify_audit_log(tampered.as_bytes()).is_err());
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_idempotency_middleware_replays_repeated_calls() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let recorder = CompletionRecorder::default();
+    let idempotency = IdempotencyMiddleware::new(InMemoryIdempotencyStore::new())
+        .apply_to("/test_services.PublicService/PublicMethod");
+
+    let recorder_clone = recorder.clone();
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(MiddlewareLayer::new(idempotency))
+            .add_service(MiddlewareFor::new(public_server, recorder_clone))
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let mut public_service_client = services.public_service_client.as_ref().clone();
+    let keyed_request = |message: &str| {
+        let mut request = tonic::Request::new(PublicMethodRequest {
+            message: message.to_string(),
+        });
+        request
+            .metadata_mut()
+            .insert("idempotency-key", "order-1".parse().unwrap());
+        request
+    };
+
+    let response = public_service_client
+        .public_method(keyed_request("Create"))
+        .await
+        .expect("First call");
+    assert!(response.metadata().get("idempotency-replayed").is_none());
+    assert_eq!(recorder.read_completions().len(), 1);
+
+    let response = public_service_client
+        .public_method(keyed_request("Create"))
+        .await
+        .expect("Replayed call");
+    assert_eq!(
+        response.metadata().get("idempotency-replayed").unwrap(),
+        "true"
+    );
+    assert_eq!(response.into_inner().message, "Hello Public!");
+    assert_eq!(recorder.read_completions().len(), 1);
+
+    let result = public_service_client
+        .public_method(keyed_request("Create another"))
+        .await;
+    assert!(result.is_err_and(|e| e.code() == Code::InvalidArgument));
+
+    public_service_client
+        .public_method(mk_public_request())
+        .await
+        .expect("Call without key");
+    assert_eq!(recorder.read_completions().len(), 2);
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_idempotency_middleware_releases_keys_of_cancelled_calls() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let idempotency = IdempotencyMiddleware::new(InMemoryIdempotencyStore::new())
+        .apply_to("/test_services.PublicService/PublicMethod");
+    let delay = middleware_fn(|req, next| async move {
+        if req.headers().contains_key("x-delay") {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+        next.run(req).await
+    });
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(MiddlewareLayer::new(idempotency))
+            .add_service(MiddlewareFor::new(public_server, delay))
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let mut public_service_client = services.public_service_client.as_ref().clone();
+    let mut request = mk_public_request();
+    request
+        .metadata_mut()
+        .insert("idempotency-key", "order-1".parse().unwrap());
+    request
+        .metadata_mut()
+        .insert("x-delay", "true".parse().unwrap());
+    let cancelled = tokio::time::timeout(
+        Duration::from_millis(100),
+        public_service_client.public_method(request),
+    )
+    .await;
+    assert!(cancelled.is_err());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut request = mk_public_request();
+    request
+        .metadata_mut()
+        .insert("idempotency-key", "order-1".parse().unwrap());
+    let response = public_service_client
+        .public_method(request)
+        .await
+        .expect("Retried call");
+    assert!(response.metadata().get("idempotency-replayed").is_none());
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_interceptors_and_middlewares_created_from_functions() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let protected_server = services.protected_server.as_ref().clone();
+    let auth_interceptor = services.auth_interceptor.as_ref().clone();
+    let require_tenant = interceptor_fn(|req: http::Request<Body>| async move {
+        if req.headers().contains_key("x-tenant-id") {
+            Ok(req)
+        } else {
+            Err(Status::permission_denied("Missing tenant"))
+        }
+    });
+    let served_at = middleware_fn(|req: http::Request<Body>, next: Next| async move {
+        let mut response = next.run(req).await?;
+        response
+            .headers_mut()
+            .insert("x-served-at", "test".parse().unwrap());
+        Ok(response)
+    });
+
+    // The annotated function stays callable on its own
+    let request = http::Request::builder().body(Body::empty()).unwrap();
+    let status = require_header(request, "x-request-id")
+        .await
+        .expect_err("Missing header");
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(MiddlewareLayer::new(served_at))
+            .add_service(InterceptorFor::new(
+                MiddlewareFor::new(public_server, ServedBy::new("instance-1".to_string())),
+                RequireHeader::new("x-request-id".to_string()),
+            ))
+            .add_service(InterceptorFor::new(
+                InterceptorFor::new(protected_server, auth_interceptor),
+                require_tenant,
+            ))
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let mut public_service_client = services.public_service_client.as_ref().clone();
+    let result = public_service_client
+        .public_method(mk_public_request())
+        .await;
+    assert!(result.is_err_and(|e| e.code() == Code::InvalidArgument));
+
+    let mut request = mk_public_request();
+    request
+        .metadata_mut()
+        .insert("x-request-id", "42".parse().unwrap());
+    let response = public_service_client
+        .public_method(request)
+        .await
+        .expect("Public method response");
+    assert_eq!(
+        response.metadata().get("x-served-by").unwrap(),
+        "instance-1"
+    );
+    assert_eq!(response.metadata().get("x-served-at").unwrap(), "test");
+
+    let mut protected_service_client = services.protected_service_client.as_ref().clone();
+    let result = protected_service_client
+        .protected_method(mk_protected_request())
+        .await;
+    assert!(result.is_err_and(|e| e.code() == Code::PermissionDenied));
+
+    let mut request = mk_protected_request();
+    request
+        .metadata_mut()
+        .insert("x-tenant-id", "acme".parse().unwrap());
+    protected_service_client
+        .protected_method(request)
+        .await
+        .expect("Protected method response");
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_closure_interceptors_and_middlewares_without_type_annotations() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let reject_anonymous = from_fn(|req| async move {
+        match req.headers().get("x-client-id") {
+            Some(_) => Ok(req),
+            None => Err(Status::unauthenticated("Missing x-client-id")),
+        }
+    });
+    let add_header = middleware_fn(|req, next| async move {
+        let mut response = next.run(req).await?;
+        response
+            .headers_mut()
+            .insert("x-handled", "true".parse().unwrap());
+        Ok(response)
+    });
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(MiddlewareLayer::new(add_header))
+            .layer(RequestInterceptorLayer::new(reject_anonymous))
+            .add_service(public_server)
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let mut public_service_client = services.public_service_client.as_ref().clone();
+    let status = public_service_client
+        .public_method(mk_public_request())
+        .await
+        .expect_err("Anonymous request");
+    assert_eq!(status.code(), Code::Unauthenticated);
+    assert_eq!(status.metadata().get("x-handled").unwrap(), "true");
+
+    let mut request = mk_public_request();
+    request
+        .metadata_mut()
+        .insert("x-client-id", "web".parse().unwrap());
+    let response = public_service_client
+        .public_method(request)
+        .await
+        .expect("Public method response");
+    assert_eq!(response.metadata().get("x-handled").unwrap(), "true");
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+metadata_key!(ClientId = "x-client-id");
+
+#[tokio::test]
+#[serial]
+async fn test_extract_fn_interceptor_receives_typed_request_parts() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let extracted = Arc::new(Mutex::new(Vec::new()));
+    let recorded = extracted.clone();
+    let interceptor = extract_fn(
+        move |req: http::Request<Body>,
+              token: Metadata<Authorization>,
+              client: Option<Metadata<ClientId>>,
+              MethodPath(method): MethodPath,
+              PeerAddr(peer): PeerAddr,
+              deadline: Deadline| {
+            let recorded = recorded.clone();
+            async move {
+                recorded.lock().unwrap().push((
+                    token.into_value(),
+                    client.map(Metadata::into_value),
+                    method,
+                    peer.ip().is_loopback(),
+                    deadline.remaining().is_some(),
+                ));
+                Ok(req)
+            }
+        },
+    );
+    let authenticate = extract_fn(
+        |mut req: http::Request<Body>, client: Option<Metadata<ClientId>>| async move {
+            if let Some(client) = client {
+                req.extensions_mut().insert(Principal::new(client.value()));
+            }
+            Ok(req)
+        },
+    );
+    let require_principal = extract_fn(
+        |req: http::Request<Body>, Extension(principal): Extension<Principal>| async move {
+            match principal.id.as_str() {
+                "web" => Ok(req),
+                _ => Err(Status::permission_denied("Unknown client")),
+            }
+        },
+    );
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(RequestInterceptorLayer::new(interceptor))
+            .layer(RequestInterceptorLayer::new(authenticate))
+            .layer(RequestInterceptorLayer::new(require_principal))
+            .add_service(public_server)
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let mut public_service_client = services.public_service_client.as_ref().clone();
+    let status = public_service_client
+        .public_method(mk_public_request())
+        .await
+        .expect_err("Request without authorization");
+    assert_eq!(status.code(), Code::Unauthenticated);
+
+    let mut request = mk_public_request();
+    request
+        .metadata_mut()
+        .insert("authorization", "Bearer token".parse().unwrap());
+    request
+        .metadata_mut()
+        .insert("grpc-timeout", "soon".parse().unwrap());
+    let status = public_service_client
+        .public_method(request)
+        .await
+        .expect_err("Request with malformed timeout");
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    let mut request = mk_public_request();
+    request
+        .metadata_mut()
+        .insert("authorization", "Bearer token".parse().unwrap());
+    request
+        .metadata_mut()
+        .insert("x-client-id", "web".parse().unwrap());
+    request.set_timeout(Duration::from_secs(5));
+    public_service_client
+        .public_method(request)
+        .await
+        .expect("Public method response");
+
+    let mut request = mk_public_request();
+    request
+        .metadata_mut()
+        .insert("authorization", "Bearer other".parse().unwrap());
+    let status = public_service_client
+        .public_method(request)
+        .await
+        .expect_err("Request without principal extension");
+    assert_eq!(status.code(), Code::Internal);
+
+    assert_eq!(
+        *extracted.lock().unwrap(),
+        vec![
+            (
+                "Bearer token".to_string(),
+                Some("web".to_string()),
+                "/test_services.PublicService/PublicMethod".to_string(),
+                true,
+                true,
+            ),
+            (
+                "Bearer other".to_string(),
+                None,
+                "/test_services.PublicService/PublicMethod".to_string(),
+                true,
+                false,
+            ),
+        ]
+    );
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_grpc_method_parsed_once_and_available_to_every_middleware() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    GrpcMethod::register_file_descriptor_set(proto::FILE_DESCRIPTOR_SET).unwrap();
+    let observed = Arc::new(Mutex::new(Vec::new()));
+    let recorded = observed.clone();
+    let observe = middleware_fn(move |req, next| {
+        let method = req.grpc_method().cloned();
+        recorded.lock().unwrap().push(method);
+        next.run(req)
+    });
+    let check_method = extract_fn(|req: http::Request<Body>, method: GrpcMethod| async move {
+        match method.method() {
+            "PublicMethod" => Ok(req),
+            _ => Err(Status::permission_denied("Unexpected method")),
+        }
+    });
+    let mut service =
+        MiddlewareLayer::new(observe).layer(InterceptorFor::new(public_server, check_method));
+
+    let message = PublicMethodRequest {
+        message: "Hello!".to_string(),
+    }
+    .encode_to_vec();
+    let mut frame = vec![0];
+    frame.extend((message.len() as u32).to_be_bytes());
+    frame.extend(message);
+    let request = |path: &str| {
+        http::Request::builder()
+            .method(http::Method::POST)
+            .uri(path)
+            .header(http::header::CONTENT_TYPE, "application/grpc")
+            .body(Body::new(Full::<Bytes>::from(frame.clone())))
+            .unwrap()
+    };
+
+    let response = service
+        .call(request("/test_services.PublicService/PublicMethod"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), http::StatusCode::OK);
+    assert!(!response.headers().contains_key("grpc-status"));
+
+    for path in [
+        "/test_services.PublicService",
+        "/test_services.PublicService/PublicMethod/extra",
+        "/test_services..PublicService/PublicMethod",
+        "/test_services.PublicService/",
+    ] {
+        let response = service.call(request(path)).await.unwrap();
+        assert_eq!(response.headers()["grpc-status"], "12", "{path}");
+    }
+
+    let observed = observed.lock().unwrap();
+    assert_eq!(observed.len(), 1);
+    let method = observed[0].as_ref().expect("Parsed method");
+    assert_eq!(method.package(), "test_services");
+    assert_eq!(method.service(), "PublicService");
+    assert_eq!(method.qualified_service(), "test_services.PublicService");
+    assert_eq!(method.method(), "PublicMethod");
+    assert_eq!(
+        method.full_path(),
+        "/test_services.PublicService/PublicMethod"
+    );
+    assert_eq!(method.streaming_kind(), Some(StreamingKind::Unary));
+
+    let method = GrpcMethod::parse("/Greeter/SayHello").unwrap();
+    assert_eq!(method.package(), "");
+    assert_eq!(method.service(), "Greeter");
+    assert_eq!(method.streaming_kind(), None);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_method_options_generated_from_proto_configure_built_ins() {
+    let options = proto::method_options();
+    let public_method = "/test_services.PublicService/PublicMethod";
+    let protected_method = "/test_services.ProtectedService/ProtectedMethod";
+    assert_eq!(
+        options.get(public_method, "auth.required"),
+        Some(&OptionValue::Bool(false))
+    );
+    assert_eq!(
+        options
+            .get(protected_method, "auth.scopes")
+            .map(OptionValue::as_strings),
+        Some(vec!["protected:read", "protected:write"])
+    );
+    assert_eq!(
+        options.get(protected_method, "limits.rate_limit.rps"),
+        Some(&OptionValue::Float(0.1))
+    );
+    assert_eq!(
+        options
+            .get(protected_method, "limits.rate_limit.burst")
+            .and_then(OptionValue::as_u64),
+        Some(2)
+    );
+    assert_eq!(options.get(protected_method, "auth.required"), None);
+    assert_eq!(
+        options
+            .get(protected_method, "auth.audience")
+            .and_then(OptionValue::as_str),
+        Some("internal")
+    );
+    assert_eq!(options.get(public_method, "auth.audience"), None);
+
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let protected_server = services.protected_server.as_ref().clone();
+    let mut store = InMemoryApiKeyStore::new();
+    store
+        .insert(
+            "reader",
+            "s3cr3t",
+            Principal::new("reader").with_scopes(["protected:read"]),
+        )
+        .unwrap();
+    store
+        .insert(
+            "writer",
+            "s3cr3t",
+            Principal::new("writer").with_scopes(["protected:read", "protected:write"]),
+        )
+        .unwrap();
+    let api_key_interceptor = ApiKeyInterceptor::new(store).method_options(&options);
+    let max_message_size = MaxMessageSizeInterceptor::new(4 * 1024 * 1024).method_options(&options);
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(RequestInterceptorLayer::new(max_message_size))
+            .layer(RequestInterceptorLayer::new(api_key_interceptor))
+            .add_service(public_server)
+            .add_service(InterceptorFor::new(
+                protected_server,
+                PrincipalToHeaderInterceptor,
+            ))
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    // (auth.required) = false lets anonymous calls through
+    let mut public_service_client = services.public_service_client.as_ref().clone();
+    public_service_client
+        .public_method(mk_public_request())
+        .await
+        .expect("Public method response");
+
+    // (limits.max_message_size) = 1024
+    let status = public_service_client
+        .public_method(PublicMethodRequest {
+            message: "x".repeat(2048),
+        })
+        .await
+        .expect_err("Too large message");
+    assert_eq!(status.code(), Code::ResourceExhausted);
+
+    let mut protected_service_client = services.protected_service_client.as_ref().clone();
+    let status = protected_service_client
+        .protected_method(mk_protected_request())
+        .await
+        .expect_err("Anonymous protected call");
+    assert_eq!(status.code(), Code::Unauthenticated);
+
+    // (auth.scopes) requires both scopes
+    let mut request = mk_protected_request();
+    request
+        .metadata_mut()
+        .insert(API_KEY_HEADER, "reader.s3cr3t".parse().unwrap());
+    let status = protected_service_client
+        .protected_method(request)
+        .await
+        .expect_err("Missing scope");
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    let mut request = mk_protected_request();
+    request
+        .metadata_mut()
+        .insert(API_KEY_HEADER, "writer.s3cr3t".parse().unwrap());
+    let response = protected_service_client
+        .protected_method(request)
+        .await
+        .expect("Protected method response");
+    assert_eq!(response.into_inner().user_id, "writer");
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_rate_limit_interceptor_reads_renamed_method_options() {
+    let options = proto::method_options().rename("limits.rate_limit", "ratelimit");
+    let protected_method = "/test_services.ProtectedService/ProtectedMethod";
+    assert_eq!(
+        options.get(protected_method, "ratelimit.rps"),
+        Some(&OptionValue::Float(0.1))
+    );
+    assert_eq!(options.get(protected_method, "limits.rate_limit.rps"), None);
+    assert_eq!(
+        options
+            .get(protected_method, "auth.audience")
+            .and_then(OptionValue::as_str),
+        Some("internal")
+    );
+
+    let rate_limit = RateLimitInterceptor::new().method_options(&options);
+    let request = |path: &str, principal: &str| {
+        let mut request = http::Request::builder()
+            .uri(format!("http://localhost{path}"))
+            .body(Body::empty())
+            .unwrap();
+        request.extensions_mut().insert(Principal::new(principal));
+        request
+    };
+
+    // (limits.rate_limit) = { rps: 0.1 burst: 2 }
+    for _ in 0..2 {
+        rate_limit
+            .intercept(request(protected_method, "writer"))
+            .await
+            .expect("Call within the burst");
+    }
+    let status = rate_limit
+        .intercept(request(protected_method, "writer"))
+        .await
+        .expect_err("Call above the rate limit");
+    assert_eq!(status.code(), Code::ResourceExhausted);
+    let retry_after: u64 = status
+        .metadata()
+        .get("grpc-retry-pushback-ms")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(
+        retry_after > 9_000 && retry_after <= 10_000,
+        "{retry_after}"
+    );
+
+    // Calls are counted per principal, and methods without options are not limited
+    rate_limit
+        .intercept(request(protected_method, "reader"))
+        .await
+        .expect("Call of another principal");
+    for _ in 0..10 {
+        rate_limit
+            .intercept(request(
+                "/test_services.PublicService/PublicMethod",
+                "writer",
+            ))
+            .await
+            .expect("Unlimited method");
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_rate_limit_interceptor_applies_rate_limits_of_tenants() {
+    let public_method = "/test_services.PublicService/PublicMethod";
+    let protected_method = "/test_services.ProtectedService/ProtectedMethod";
+    let rate_limit =
+        RateLimitInterceptor::new().method_limit(protected_method, RateLimit::new(0.1, 1));
+    let acme = Tenant::new("acme")
+        .with_policy(TenantPolicy::new().with_rate_limit(RateLimit::new(0.1, 2)));
+    let initech = Tenant::new("initech");
+    let request = |path: &str, tenant: &Tenant, principal: &str| {
+        let mut request = http::Request::builder()
+            .uri(format!("http://localhost{path}"))
+            .body(Body::empty())
+            .unwrap();
+        request.extensions_mut().insert(tenant.clone());
+        request.extensions_mut().insert(Principal::new(principal));
+        request
+    };
+
+    rate_limit
+        .intercept(request(protected_method, &acme, "alice"))
+        .await
+        .expect("Call within the limits");
+    // Calls rejected by the method limit are not counted against the tenant
+    let status = rate_limit
+        .intercept(request(protected_method, &acme, "alice"))
+        .await
+        .expect_err("Call above the method limit");
+    assert_eq!(status.code(), Code::ResourceExhausted);
+    // The tenant limit is shared by all methods and principals of the tenant
+    rate_limit
+        .intercept(request(public_method, &acme, "bob"))
+        .await
+        .expect("Call within the tenant limit");
+    let status = rate_limit
+        .intercept(request(public_method, &acme, "carol"))
+        .await
+        .expect_err("Call above the tenant limit");
+    assert_eq!(status.code(), Code::ResourceExhausted);
+    assert!(status.message().contains("acme"), "{}", status.message());
+    assert!(status.metadata().get("grpc-retry-pushback-ms").is_some());
+
+    // Tenants without a rate limit only get the method limits
+    for _ in 0..10 {
+        rate_limit
+            .intercept(request(public_method, &initech, "bob"))
+            .await
+            .expect("Tenant without rate limit");
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_pipeline_configures_tenants_rate_limits_and_error_redaction() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+
+    let pipeline = PipelineConfig::from_toml(
+        r#"
+        [[pipeline]]
+        type = "redact_errors"
+        codes = ["internal", "PERMISSION_DENIED"]
+        message = "Request failed"
+
+        [[pipeline]]
+        type = "tenant"
+        tenants = { acme = { rate_limit = { rps = 0.1, burst = 2 } }, globex = {} }
+
+        [[pipeline]]
+        type = "rate_limit"
+        method_limits = { "/test_services.PublicService/*" = { rps = 0.1, burst = 1 } }
+
+        [[pipeline]]
+        type = "compression"
+        forbid = ["/test_services.ProtectedService/*"]
+
+        [[pipeline]]
+        type = "idempotency"
+        apply_to = ["/test_services.ProtectedService/ProtectedMethod"]
+        ttl_secs = 60
+        "#,
+    )
+    .unwrap();
+    let layer = tonic_middleware::config::build_layer(&pipeline).unwrap();
+
+    for invalid in [
+        "[[pipeline]]\ntype = \"redact_errors\"\ncodes = [\"internal_error\"]",
+        "[[pipeline]]\ntype = \"compression\"\ncompress_responses = { \"*\" = \"lz4\" }",
+        "[[pipeline]]\ntype = \"signature\"\nsecret_env = \"TONIC_MIDDLEWARE_UNSET_SECRET\"",
+        "[[pipeline]]\ntype = \"rate_limit\"\nmethod_limits = { \"*\" = { burst = 1 } }",
+    ] {
+        let invalid = PipelineConfig::from_toml(invalid).unwrap();
+        assert!(
+            tonic_middleware::config::build_layer(&invalid).is_err(),
+            "{invalid:?}"
+        );
+    }
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(layer)
+            .add_service(public_server)
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let mut public_service_client = services.public_service_client.as_ref().clone();
+    let request = |tenant: &str| {
+        let mut request = mk_public_request();
+        request
+            .metadata_mut()
+            .insert(TENANT_HEADER, tenant.parse().unwrap());
+        request
+    };
+
+    // Each tenant gets its own bucket of the method limit
+    for tenant in ["acme", "globex"] {
+        public_service_client
+            .public_method(request(tenant))
+            .await
+            .expect("Call within the rate limit");
+        let status = public_service_client
+            .public_method(request(tenant))
+            .await
+            .expect_err("Call above the rate limit");
+        assert_eq!(status.code(), Code::ResourceExhausted);
+    }
+
+    // Unknown tenants are rejected, and the rejection is redacted
+    let status = public_service_client
+        .public_method(request("initech"))
+        .await
+        .expect_err("Unknown tenant");
+    assert_eq!(status.code(), Code::PermissionDenied);
+    assert!(
+        status
+            .message()
+            .starts_with("Request failed (correlation id: "),
+        "{}",
+        status.message()
+    );
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_layers_bypass_health_checks_and_health_reporting_middleware() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let auth_interceptor = services.auth_interceptor.as_ref().clone();
+    let (reporter, health_server) = tonic_health::server::health_reporter();
+    let breaker_open = Arc::new(AtomicBool::new(false));
+    let open = breaker_open.clone();
+    let public_service = "test_services.PublicService";
+    let health_reporting = HealthReportingMiddleware::new(reporter, public_service)
+        .error_rate_threshold(0.5)
+        .min_calls(2)
+        .window(Duration::from_millis(500))
+        .check_interval(Duration::from_millis(20))
+        .unhealthy_when(move || open.load(Ordering::Relaxed));
+    let fail_on_request = from_fn(|req| async move {
+        match req.headers().contains_key("x-fail") {
+            true => Err(Status::internal("Failure")),
+            false => Ok(req),
+        }
+    });
+
+    let auth_stack = DynStack::new()
+        .push_interceptor(auth_interceptor.clone())
+        .bypass(public_service);
+    let pipeline = PipelineConfig::from_toml(
+        r#"
+        bypass = ["test_services.PublicService"]
+
+        [[pipeline]]
+        type = "metadata_policy"
+        require = { "*" = ["x-client-id"] }
+        "#,
+    )
+    .unwrap();
+    let pipeline_layer = tonic_middleware::config::build_layer(&pipeline).unwrap();
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            // Health checks skip the authentication without being configured
+            .layer(RequestInterceptorLayer::new(auth_interceptor).bypass(public_service))
+            .layer(auth_stack)
+            .layer(pipeline_layer)
+            .layer(MiddlewareLayer::new(health_reporting))
+            .layer(RequestInterceptorLayer::new(fail_on_request))
+            .add_service(health_server)
+            .add_service(public_server)
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let health_client = HealthClient::new(services.channel.as_ref().clone());
+    let check = |service: &str| {
+        let request = HealthCheckRequest {
+            service: service.to_string(),
+        };
+        let mut health_client = health_client.clone();
+        async move {
+            health_client
+                .check(request)
+                .await
+                .expect("Health check response")
+                .into_inner()
+                .status()
+        }
+    };
+    let mut public_service_client = services.public_service_client.as_ref().clone();
+    let failing_client = public_service_client.clone();
+    let failing_call = || {
+        let mut request = mk_public_request();
+        request
+            .metadata_mut()
+            .insert("x-fail", "true".parse().unwrap());
+        let mut client = failing_client.clone();
+        async move { client.public_method(request).await }
+    };
+
+    assert_eq!(check("").await, ServingStatus::Serving);
+    public_service_client
+        .public_method(mk_public_request())
+        .await
+        .expect("Public method response");
+    sleep().await;
+    assert_eq!(check(public_service).await, ServingStatus::Serving);
+
+    for _ in 0..3 {
+        let status = failing_call().await.expect_err("Failing call");
+        assert_eq!(status.code(), Code::Internal);
+    }
+    sleep().await;
+    assert_eq!(check(public_service).await, ServingStatus::NotServing);
+
+    // The errors leave the window
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(check(public_service).await, ServingStatus::Serving);
+
+    breaker_open.store(true, Ordering::Relaxed);
+    sleep().await;
+    assert_eq!(check(public_service).await, ServingStatus::NotServing);
+    assert_eq!(check("").await, ServingStatus::Serving);
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_drain_middleware_rejects_new_calls_and_awaits_in_flight_calls() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let drain = DrainMiddleware::new().retry_after(Duration::from_millis(250));
+    let handle = drain.handle();
+    let slow_on_request = from_fn(|req| async move {
+        if req.headers().contains_key("x-slow") {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+        Ok(req)
+    });
+
+    let (tx, rx) = oneshot::channel();
+    let shutdown = handle
+        .clone()
+        .drain_on(async { drop(rx.await) }, Duration::from_secs(5));
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(MiddlewareLayer::new(drain))
+            .layer(RequestInterceptorLayer::new(slow_on_request))
+            .add_service(public_server)
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), shutdown)
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let public_service_client = services.public_service_client.as_ref().clone();
+    let mut client = public_service_client.clone();
+    let in_flight = tokio::spawn(async move {
+        let mut request = mk_public_request();
+        request
+            .metadata_mut()
+            .insert("x-slow", "true".parse().unwrap());
+        client.public_method(request).await
+    });
+    sleep().await;
+    assert_eq!(handle.in_flight(), 1);
+    assert!(!handle.is_draining());
+
+    tx.send(()).unwrap();
+    sleep().await;
+    assert!(handle.is_draining());
+
+    let mut client = public_service_client.clone();
+    let status = client
+        .public_method(mk_public_request())
+        .await
+        .expect_err("Call rejected while draining");
+    assert_eq!(status.code(), Code::Unavailable);
+    assert_eq!(
+        status.metadata().get("grpc-retry-pushback-ms").unwrap(),
+        "250"
+    );
+
+    // The call in flight completes, after which the server shuts down
+    in_flight.await.unwrap().expect("Call in flight completes");
+    handle.drained().await;
+    assert_eq!(handle.in_flight(), 0);
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_buffered_request_inspects_and_forwards_request_body() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let reject_empty_messages = from_fn(|req| async move {
+        let req = BufferedRequest::collect(req, 64).await?;
+        for frame in req.frames()? {
+            let message = PublicMethodRequest::decode(frame.data)
+                .map_err(|e| Status::invalid_argument(e.to_string()))?;
+            if message.message.is_empty() {
+                return Err(Status::invalid_argument("Empty message"));
+            }
+        }
+        Ok(req.into_request())
+    });
+    let forwarded = Arc::new(Mutex::new(Vec::new()));
+    let recorded = forwarded.clone();
+    let record_forwarded = from_fn(move |req| {
+        let recorded = recorded.clone();
+        async move {
+            let req = BufferedRequest::collect(req, 64).await?;
+            let trailer = req
+                .trailers()
+                .and_then(|trailers| trailers.get("x-checksum"))
+                .map(|value| value.to_str().unwrap().to_string());
+            recorded
+                .lock()
+                .unwrap()
+                .push((req.frames()?.len(), trailer));
+            Ok(req.into_request())
+        }
+    });
+    let mut service = InterceptorFor::new(
+        InterceptorFor::new(public_server, record_forwarded),
+        reject_empty_messages,
+    );
+
+    let frame = |message: &str| {
+        let message = PublicMethodRequest {
+            message: message.to_string(),
+        }
+        .encode_to_vec();
+        let mut frame = vec![0];
+        frame.extend((message.len() as u32).to_be_bytes());
+        frame.extend(message);
+        Bytes::from(frame)
+    };
+    let request = |body: Body| {
+        http::Request::builder()
+            .method(http::Method::POST)
+            .uri("/test_services.PublicService/PublicMethod")
+            .header(http::header::CONTENT_TYPE, "application/grpc")
+            .body(body)
+            .unwrap()
+    };
+
+    // The body and its trailers are forwarded
+    let mut trailers = http::HeaderMap::new();
+    trailers.insert("x-checksum", "1234".parse().unwrap());
+    let body = Full::new(frame("Hello!")).with_trailers(async { Some(Ok(trailers)) });
+    let response = service.call(request(Body::new(body))).await.unwrap();
+    assert_eq!(response.status(), http::StatusCode::OK);
+    assert!(!response.headers().contains_key("grpc-status"));
+    assert_eq!(
+        *forwarded.lock().unwrap(),
+        vec![(1, Some("1234".to_string()))]
+    );
+
+    let response = service
+        .call(request(Body::new(Full::new(frame("")))))
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["grpc-status"], "3");
+
+    let response = service
+        .call(request(Body::new(Full::new(frame(&"a".repeat(100))))))
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["grpc-status"], "8");
+
+    let incomplete = frame("Hello!").slice(..8);
+    let response = service
+        .call(request(Body::new(Full::new(incomplete))))
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["grpc-status"], "13");
+    assert_eq!(forwarded.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_signature_interceptor_verifies_calls_signed_by_signing_middleware() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let signature_interceptor = SignatureInterceptor::new("new-secret", InMemoryNonceStore::new())
+        .previous_secret("old-secret")
+        .max_skew(Duration::from_secs(60));
+    let signed = Arc::new(Mutex::new(Vec::new()));
+    let recorded = signed.clone();
+    let record_signature = from_fn(move |req| {
+        recorded.lock().unwrap().push(req.headers().clone());
+        async move { Ok(req) }
+    });
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(RequestInterceptorLayer::new(signature_interceptor))
+            .layer(RequestInterceptorLayer::new(record_signature))
+            .add_service(public_server)
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let channel = services.channel.as_ref().clone();
+    let signed_client = |secret: &str| {
+        PublicServiceClient::new(MiddlewareFor::new(
+            channel.clone(),
+            SigningMiddleware::new(secret),
+        ))
+    };
+
+    for secret in ["new-secret", "old-secret"] {
+        let response = signed_client(secret)
+            .public_method(mk_public_request())
+            .await
+            .expect("Signed call response");
+        assert_eq!(response.into_inner().message, "Hello Public!");
+    }
+
+    let status = signed_client("wrong-secret")
+        .public_method(mk_public_request())
+        .await
+        .expect_err("Wrong secret");
+    assert_eq!(status.code(), Code::Unauthenticated);
+    assert_eq!(status.message(), "Invalid request signature");
+
+    let mut public_service_client = services.public_service_client.as_ref().clone();
+    let status = public_service_client
+        .public_method(mk_public_request())
+        .await
+        .expect_err("Unsigned call");
+    assert_eq!(status.code(), Code::Unauthenticated);
+    assert_eq!(status.message(), "Missing request signature");
+
+    // Replaying a verified call, as is and with a different body
+    let headers = signed.lock().unwrap()[0].clone();
+    let replayed = |message: &str| {
+        let mut request = tonic::Request::new(PublicMethodRequest {
+            message: message.to_string(),
+        });
+        for name in [
+            SIGNATURE_HEADER,
+            SIGNATURE_TIMESTAMP_HEADER,
+            SIGNATURE_NONCE_HEADER,
+        ] {
+            let value = headers[name].to_str().unwrap().parse().unwrap();
+            request.metadata_mut().insert(name, value);
+        }
+        request
+    };
+    let status = public_service_client
+        .public_method(replayed("Hello!"))
+        .await
+        .expect_err("Replayed call");
+    assert_eq!(status.message(), "Replayed request signature");
+    let status = public_service_client
+        .public_method(replayed("Tampered!"))
+        .await
+        .expect_err("Tampered call");
+    assert_eq!(status.message(), "Invalid request signature");
+
+    let mut request = replayed("Hello!");
+    request
+        .metadata_mut()
+        .insert(SIGNATURE_TIMESTAMP_HEADER, "1700000000".parse().unwrap());
+    let status = public_service_client
+        .public_method(request)
+        .await
+        .expect_err("Expired call");
+    assert_eq!(status.code(), Code::Unauthenticated);
+    assert_eq!(status.message(), "Request signature expired");
+    assert_eq!(signed.lock().unwrap().len(), 2);
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_tenant_middleware_resolves_tenants_and_applies_their_policies() {
+    let services = Services::new();
+    let public_server = services.public_server.as_ref().clone();
+    let protected_server = services.protected_server.as_ref().clone();
+    let mut tenants = InMemoryTenantResolver::new();
+    tenants.insert(
+        Tenant::new("acme").with_policy(
+            TenantPolicy::new()
+                .with_enabled_methods(["/test_services.PublicService/*"])
+                .with_max_message_size(16)
+                .with_option("ratelimit.rps", 10u64),
+        ),
+    );
+    tenants.insert(Tenant::new("globex"));
+    // Stands for an authentication interceptor copying the tenant from token claims
+    let claims = from_fn(|mut req| async move {
+        if let Some(tenant) = req.headers().get("x-claims-tenant") {
+            let tenant = tenant.to_str().unwrap().to_string();
+            let principal = Principal::new("user-1").with_attribute(TENANT_ATTRIBUTE, tenant);
+            req.extensions_mut().insert(principal);
+        }
+        Ok(req)
+    });
+    let resolved = Arc::new(Mutex::new(Vec::new()));
+    let recorded = resolved.clone();
+    let record_tenant = extract_fn(move |req: http::Request<Body>, tenant: Tenant| {
+        let rps = tenant
+            .policy
+            .option("ratelimit.rps")
+            .and_then(OptionValue::as_u64);
+        recorded.lock().unwrap().push((tenant.id, rps));
+        async move { Ok(req) }
+    });
+
+    let (tx, rx) = oneshot::channel();
+    let jh = tokio::spawn(async move {
+        Server::builder()
+            .layer(RequestInterceptorLayer::new(claims))
+            .layer(MiddlewareLayer::new(TenantMiddleware::new(tenants)))
+            .layer(RequestInterceptorLayer::new(
+                MaxMessageSizeInterceptor::new(1024),
+            ))
+            .layer(RequestInterceptorLayer::new(record_tenant))
+            .add_service(public_server)
+            .add_service(protected_server)
+            .serve_with_shutdown(grpc_server_addr().parse().unwrap(), async {
+                drop(rx.await)
+            })
+            .await
+            .unwrap()
+    });
+
+    sleep().await;
+
+    let mut public_service_client = services.public_service_client.as_ref().clone();
+    let mut protected_service_client = services.protected_service_client.as_ref().clone();
+    let public_request = |message: &str, metadata: &[(&'static str, &str)]| {
+        let mut request = tonic::Request::new(PublicMethodRequest {
+            message: message.to_string(),
+        });
+        for (key, value) in metadata {
+            request.metadata_mut().insert(*key, value.parse().unwrap());
+        }
+        request
+    };
+
+    for metadata in [
+        &[(TENANT_HEADER, "globex")][..],
+        &[(TENANT_HEADER, "acme")],
+        &[("x-claims-tenant", "acme")],
+        &[(TENANT_HEADER, "acme"), ("x-claims-tenant", "acme")],
+    ] {
+        public_service_client
+            .public_method(public_request("Hello!", metadata))
+            .await
+            .expect("Public method response");
+    }
+    assert_eq!(
+        *resolved.lock().unwrap(),
+        vec![
+            ("globex".to_string(), None),
+            ("acme".to_string(), Some(10)),
+            ("acme".to_string(), Some(10)),
+            ("acme".to_string(), Some(10)),
+        ]
+    );
+
+    let rejected = [
+        (&[][..], Code::InvalidArgument),
+        (&[(TENANT_HEADER, "initech")], Code::PermissionDenied),
+        // The principal cannot act on behalf of another tenant
+        (
+            &[(TENANT_HEADER, "acme"), ("x-claims-tenant", "globex")],
+            Code::PermissionDenied,
+        ),
+    ];
+    for (metadata, code) in rejected {
+        let status = public_service_client
+            .public_method(public_request("Hello!", metadata))
+            .await
+            .expect_err("Rejected tenant");
+        assert_eq!(status.code(), code, "{metadata:?}");
+    }
+
+    // The quota of the tenant takes precedence over the limit of the interceptor
+    let long_message = "a".repeat(64);
+    let status = public_service_client
+        .public_method(public_request(&long_message, &[(TENANT_HEADER, "acme")]))
+        .await
+        .expect_err("Message above the tenant quota");
+    assert_eq!(status.code(), Code::ResourceExhausted);
+    public_service_client
+        .public_method(public_request(&long_message, &[(TENANT_HEADER, "globex")]))
+        .await
+        .expect("Message within the default limit");
+
+    let mut request = mk_protected_request();
+    request
+        .metadata_mut()
+        .insert(TENANT_HEADER, "acme".parse().unwrap());
+    let status = protected_service_client
+        .protected_method(request)
+        .await
+        .expect_err("Method not enabled for the tenant");
+    assert_eq!(status.code(), Code::PermissionDenied);
+    // Message sizes are checked as the body is streamed, after the interceptors
+    assert_eq!(resolved.lock().unwrap().len(), 6);
+
+    tx.send(()).unwrap();
+    jh.await.unwrap();
+}
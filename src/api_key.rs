@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::grpc_method::method_path;
+use crate::method_map::MethodMap;
+use crate::{MethodOptionsRegistry, Principal, RequestInterceptor};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use tonic::body::Body;
+use tonic::codegen::http::Request;
+use tonic::Status;
+
+/// Default metadata key carrying the API key.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+const SALT_LEN: usize = 16;
+
+/// `ApiKeyStore` resolves API key ids to their stored entries.
+///
+/// API keys are expected in the `<key_id>.<secret>` format. Stores look entries up by the key id
+/// only and never see the secret, which is verified by [ApiKeyInterceptor] against the salted
+/// hash of the entry.
+#[async_trait]
+pub trait ApiKeyStore: Send + Sync + 'static {
+    /// Returns the entry for `key_id`, or `None` if the key is unknown.
+    ///
+    /// Returning a `Status` error rejects the request with that status, e.g. when the backing
+    /// storage is unavailable.
+    async fn get(&self, key_id: &str) -> Result<Option<ApiKeyEntry>, Status>;
+}
+
+/// Salted SHA-256 hash of an API key secret.
+#[derive(Clone, PartialEq, Eq)]
+pub struct HashedApiKey {
+    salt: Vec<u8>,
+    hash: Vec<u8>,
+}
+
+impl HashedApiKey {
+    /// Hashes `secret` with a randomly generated salt.
+    ///
+    /// Returns an error if the operating system cannot provide random bytes for the salt.
+    pub fn new(secret: &str) -> io::Result<Self> {
+        let mut salt = vec![0; SALT_LEN];
+        getrandom::getrandom(&mut salt)
+            .map_err(|e| io::Error::other(format!("Failed to generate API key salt: {}", e)))?;
+        Ok(Self::with_salt(secret, salt))
+    }
+
+    /// Hashes `secret` with the given salt.
+    pub fn with_salt(secret: &str, salt: impl Into<Vec<u8>>) -> Self {
+        let salt = salt.into();
+        let hash = hash(&salt, secret);
+        HashedApiKey { salt, hash }
+    }
+
+    /// Checks whether `secret` matches the hash, comparing in constant time.
+    pub fn verify(&self, secret: &str) -> bool {
+        hash(&self.salt, secret).ct_eq(&self.hash).into()
+    }
+}
+
+impl fmt::Debug for HashedApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HashedApiKey").finish_non_exhaustive()
+    }
+}
+
+fn hash(salt: &[u8], secret: &str) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(secret.as_bytes());
+    hasher.finalize().to_vec()
+}
+
+/// Stored API key: the hashed secret and the principal it authenticates.
+#[derive(Clone, Debug)]
+pub struct ApiKeyEntry {
+    pub key: HashedApiKey,
+    pub principal: Principal,
+}
+
+impl ApiKeyEntry {
+    /// Creates a new `ApiKeyEntry` with the given hashed key and principal.
+    pub fn new(key: HashedApiKey, principal: Principal) -> Self {
+        ApiKeyEntry { key, principal }
+    }
+
+    /// Formats the entry as a line of the file read by [InMemoryApiKeyStore::load].
+    pub fn to_line(&self, key_id: &str) -> String {
+        let mut scopes: Vec<&str> = self.principal.scopes.iter().map(String::as_str).collect();
+        scopes.sort_unstable();
+        format!(
+            "{} {} {} {} {}",
+            key_id,
+            STANDARD.encode(&self.key.salt),
+            STANDARD.encode(&self.key.hash),
+            self.principal.id,
+            scopes.join(",")
+        )
+        .trim_end()
+        .to_string()
+    }
+
+    fn from_line(line: &str) -> Option<(String, ApiKeyEntry)> {
+        let mut parts = line.split_whitespace();
+        let key_id = parts.next()?;
+        let salt = STANDARD.decode(parts.next()?).ok()?;
+        let hash = STANDARD.decode(parts.next()?).ok()?;
+        let principal_id = parts.next()?;
+        let scopes: Vec<&str> = parts
+            .next()
+            .map(|scopes| scopes.split(',').filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default();
+        if parts.next().is_some() {
+            return None;
+        }
+        let entry = ApiKeyEntry::new(
+            HashedApiKey { salt, hash },
+            Principal::new(principal_id).with_scopes(scopes),
+        );
+        Some((key_id.to_string(), entry))
+    }
+}
+
+/// `InMemoryApiKeyStore` is an [ApiKeyStore] holding salted hashes of API keys in memory.
+///
+/// Entries can be added programmatically or loaded from a file where each line has the format
+/// `<key_id> <salt> <hash> <principal_id> [<scope>,<scope>...]`, with salt and hash base64 encoded.
+/// Empty lines and lines starting with `#` are ignored. Lines can be produced with
+/// [ApiKeyEntry::to_line].
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryApiKeyStore {
+    entries: HashMap<String, ApiKeyEntry>,
+}
+
+impl InMemoryApiKeyStore {
+    /// Creates a new empty `InMemoryApiKeyStore`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads entries from the file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    /// Adds the API key `<key_id>.<secret>` authenticating `principal`. Only the salted hash of
+    /// the secret is stored.
+    ///
+    /// Returns an error if the salt of the hash cannot be generated.
+    pub fn insert(
+        &mut self,
+        key_id: impl Into<String>,
+        secret: &str,
+        principal: Principal,
+    ) -> io::Result<()> {
+        let key = HashedApiKey::new(secret)?;
+        self.insert_entry(key_id, ApiKeyEntry::new(key, principal));
+        Ok(())
+    }
+
+    /// Adds an already hashed entry.
+    pub fn insert_entry(&mut self, key_id: impl Into<String>, entry: ApiKeyEntry) {
+        self.entries.insert(key_id.into(), entry);
+    }
+}
+
+impl std::str::FromStr for InMemoryApiKeyStore {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut store = InMemoryApiKeyStore::new();
+        for (n, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key_id, entry) = ApiKeyEntry::from_line(line).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid API key entry on line {}", n + 1),
+                )
+            })?;
+            store.insert_entry(key_id, entry);
+        }
+        Ok(store)
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for InMemoryApiKeyStore {
+    async fn get(&self, key_id: &str) -> Result<Option<ApiKeyEntry>, Status> {
+        Ok(self.entries.get(key_id).cloned())
+    }
+}
+
+/// `ApiKeyInterceptor` authenticates requests by the API key sent in the `x-api-key` metadata.
+///
+/// Keys have the `<key_id>.<secret>` format. The entry for the key id is resolved through an
+/// [ApiKeyStore] and the secret is compared against its salted hash in constant time. On success,
+/// the [Principal] of the entry is inserted into the request extensions. Requests with a missing
+/// or invalid key are rejected with `Status::unauthenticated`, and requests lacking a scope
+/// required for the called method with `Status::permission_denied`.
+///
+/// # Example
+///
+/// ```
+/// use tonic_middleware::{ApiKeyInterceptor, InMemoryApiKeyStore, Principal};
+///
+/// let mut store = InMemoryApiKeyStore::new();
+/// store
+///     .insert("partner-1", "s3cr3t", Principal::new("partner-1").with_scopes(["orders:read"]))
+///     .expect("API key salt");
+///
+/// let interceptor = ApiKeyInterceptor::new(store)
+///     .require_scope("/estore.OrderService/GetMyOrders", "orders:read");
+/// ```
+pub struct ApiKeyInterceptor<S: ApiKeyStore> {
+    store: Arc<S>,
+    header: String,
+    required_scopes: MethodMap<Vec<String>>,
+    anonymous: MethodMap<bool>,
+}
+
+impl<S: ApiKeyStore> Clone for ApiKeyInterceptor<S> {
+    fn clone(&self) -> Self {
+        ApiKeyInterceptor {
+            store: self.store.clone(),
+            header: self.header.clone(),
+            required_scopes: self.required_scopes.clone(),
+            anonymous: self.anonymous.clone(),
+        }
+    }
+}
+
+impl<S: ApiKeyStore> ApiKeyInterceptor<S> {
+    /// Creates a new `ApiKeyInterceptor` resolving keys through the given store.
+    pub fn new(store: S) -> Self {
+        ApiKeyInterceptor {
+            store: Arc::new(store),
+            header: API_KEY_HEADER.to_string(),
+            required_scopes: MethodMap::default(),
+            anonymous: MethodMap::default(),
+        }
+    }
+
+    /// Reads the API key from the given metadata key instead of `x-api-key`.
+    pub fn header(mut self, header: impl AsRef<str>) -> Self {
+        self.header = header.as_ref().to_ascii_lowercase();
+        self
+    }
+
+    /// Requires principals calling the given method or service to have `scope`.
+    ///
+    /// # Parameters
+    ///
+    /// * `method`: A full method path (`/package.Service/Method`), a service wildcard
+    ///   (`/package.Service/*`) or `*` for all methods.
+    /// * `scope`: The required scope.
+    pub fn require_scope(mut self, method: impl Into<String>, scope: impl Into<String>) -> Self {
+        self.required_scopes.entry(method).push(scope.into());
+        self
+    }
+
+    /// Lets calls to the given method or service without an API key through, without
+    /// [Principal]. Calls with an API key are still authenticated.
+    ///
+    /// # Parameters
+    ///
+    /// * `method`: A full method path (`/package.Service/Method`), a service wildcard
+    ///   (`/package.Service/*`) or `*` for all methods.
+    pub fn allow_anonymous(mut self, method: impl Into<String>) -> Self {
+        self.anonymous.insert(method, true);
+        self
+    }
+
+    /// Applies the `auth.required` and `auth.scopes` options of the methods and services:
+    /// methods with `(auth.required) = false` allow anonymous calls, and the scopes listed in
+    /// `(auth.scopes)` are required.
+    pub fn method_options(mut self, options: &MethodOptionsRegistry) -> Self {
+        for (method, required) in options.methods_with("auth.required") {
+            if let Some(required) = required.as_bool() {
+                self.anonymous.insert(method, !required);
+            }
+        }
+        for (method, scopes) in options.methods_with("auth.scopes") {
+            for scope in scopes.as_strings() {
+                self = self.require_scope(method, scope);
+            }
+        }
+        self
+    }
+}
+
+#[async_trait]
+impl<S: ApiKeyStore> RequestInterceptor for ApiKeyInterceptor<S> {
+    async fn intercept(&self, mut req: Request<Body>) -> Result<Request<Body>, Status> {
+        let api_key = match req.headers().get(self.header.as_str()).map(|v| v.to_str()) {
+            Some(Ok(api_key)) => api_key,
+            None if self.anonymous.get(method_path(&req)) == Some(&true) => return Ok(req),
+            _ => return Err(Status::unauthenticated("Missing API key")),
+        };
+        let (key_id, secret) = api_key
+            .split_once('.')
+            .ok_or_else(|| Status::unauthenticated("Invalid API key"))?;
+
+        let entry = match self.store.get(key_id).await? {
+            Some(entry) if entry.key.verify(secret) => entry,
+            Some(_) => return Err(Status::unauthenticated("Invalid API key")),
+            None => {
+                // Hash the secret anyway, so that unknown key ids cannot be told apart from
+                // wrong secrets by the response time
+                std::hint::black_box(hash(&[0; SALT_LEN], secret));
+                return Err(Status::unauthenticated("Invalid API key"));
+            }
+        };
+
+        for scopes in self.required_scopes.get_all(method_path(&req)) {
+            for scope in scopes {
+                if !entry.principal.has_scope(scope) {
+                    return Err(Status::permission_denied(format!(
+                        "API key is missing the `{}` scope",
+                        scope
+                    )));
+                }
+            }
+        }
+
+        req.extensions_mut().insert(entry.principal);
+        Ok(req)
+    }
+}
@@ -0,0 +1,496 @@
+use std::fmt;
+use std::io::{self, BufRead};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::grpc_frame::{parse_frame_header, GRPC_HEADER_SIZE};
+use crate::grpc_method::method_path;
+use crate::method_map::MethodMap;
+use crate::{Middleware, ObservedBody, Principal, ServiceBound};
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use http_body::Frame;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, oneshot};
+use tonic::body::Body;
+use tonic::codegen::http::{Request, Response};
+use tonic::Status;
+
+/// Previous hash of the first record of a new chain.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+const REDACTED: &str = "[REDACTED]";
+const DEFAULT_CAPACITY: usize = 1024;
+const DEFAULT_MAX_CAPTURED_SIZE: usize = 64 * 1024;
+
+type RequestDecoder = Arc<dyn Fn(&[u8]) -> Option<Value> + Send + Sync>;
+
+/// Destination of the records written by [AuditMiddleware], one JSON object per line.
+///
+/// Records are written sequentially by a single background task, so implementations do not need
+/// to synchronize writes.
+#[async_trait]
+pub trait AuditSink: Send + 'static {
+    /// Returns the hash of the last record already stored in the sink, so the hash chain
+    /// continues across restarts. `None` starts a new chain.
+    async fn last_hash(&mut self) -> io::Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Appends `line`, a JSON object without the trailing newline, to the sink.
+    async fn write(&mut self, line: &str) -> io::Result<()>;
+}
+
+/// `FileAuditSink` appends audit records as JSON lines to a file.
+pub struct FileAuditSink {
+    path: PathBuf,
+    file: tokio::fs::File,
+}
+
+impl FileAuditSink {
+    /// Opens the file at `path` for appending, creating it if it does not exist.
+    pub async fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        Ok(FileAuditSink { path, file })
+    }
+}
+
+#[async_trait]
+impl AuditSink for FileAuditSink {
+    async fn last_hash(&mut self) -> io::Result<Option<String>> {
+        let content = tokio::fs::read_to_string(&self.path).await?;
+        let Some(line) = content.lines().rev().find(|line| !line.trim().is_empty()) else {
+            return Ok(None);
+        };
+        let record: Map<String, Value> = serde_json::from_str(line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        match record.get("hash").and_then(Value::as_str) {
+            Some(hash) => Ok(Some(hash.to_string())),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Last audit record has no hash",
+            )),
+        }
+    }
+
+    async fn write(&mut self, line: &str) -> io::Result<()> {
+        self.file
+            .write_all(format!("{}\n", line).as_bytes())
+            .await?;
+        self.file.flush().await
+    }
+}
+
+impl fmt::Debug for FileAuditSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileAuditSink")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+/// `AuditMiddleware` records who called which method with what arguments, and what the outcome
+/// of the call was, for services that must keep an audit trail.
+///
+/// Every call produces one JSON record with the id of the authenticated [Principal], taken from
+/// the request extensions, the method, the first request message, the final status, which is
+/// read from the trailers for calls failing after a response message, and the timing of the
+/// call. Request messages are only recorded for methods registered with
+/// [AuditMiddleware::record_request], with the fields registered with
+/// [AuditMiddleware::redact_field] replaced by `[REDACTED]`.
+///
+/// Records are handed to a background task through a bounded queue, so writing them never
+/// blocks the request path; when the queue is full, records are dropped and an error is logged.
+/// The task links records into a hash chain: each record holds the SHA-256 hash of the previous
+/// record in `prev_hash` and its own hash in `hash`, so modified, removed or reordered records
+/// are detected by [verify_audit_log].
+///
+/// Apply it inside of the interceptor authenticating the caller, so the principal is available.
+/// Requires the `audit` feature.
+///
+/// # Example
+///
+/// ```no_run
+/// # #[derive(Clone, PartialEq, prost::Message, serde::Serialize)]
+/// # struct CreateOrderRequest {}
+/// use tonic_middleware::{AuditMiddleware, FileAuditSink, MiddlewareLayer};
+///
+/// # async fn run() -> std::io::Result<()> {
+/// let sink = FileAuditSink::open("/var/log/estore/audit.jsonl").await?;
+/// let audit = AuditMiddleware::new(sink)
+///     .record_request::<CreateOrderRequest>("/estore.OrderService/CreateOrder")
+///     .redact_field("/estore.OrderService/CreateOrder", "payment.card_number");
+/// let layer = MiddlewareLayer::new(audit);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct AuditMiddleware {
+    config: Arc<AuditConfig>,
+    sender: mpsc::Sender<Command>,
+}
+
+#[derive(Clone, Default)]
+struct AuditConfig {
+    decoders: MethodMap<RequestDecoder>,
+    redactions: MethodMap<Vec<String>>,
+    max_captured_size: usize,
+}
+
+enum Command {
+    Record(Box<PendingRecord>),
+    Flush(oneshot::Sender<()>),
+}
+
+/// Record of a completed call, decoded and serialized by the background task.
+struct PendingRecord {
+    timestamp_ms: u64,
+    method: String,
+    principal: Option<String>,
+    request: Option<(RequestDecoder, Bytes)>,
+    redactions: Vec<String>,
+    status: Status,
+    duration_ms: u64,
+}
+
+impl AuditMiddleware {
+    /// Creates a new `AuditMiddleware` writing records to `sink` from a background task, queuing
+    /// up to 1024 records. Must be called within a Tokio runtime.
+    pub fn new<K: AuditSink>(sink: K) -> Self {
+        Self::with_capacity(sink, DEFAULT_CAPACITY)
+    }
+
+    /// Creates a new `AuditMiddleware` writing records to `sink` from a background task, queuing
+    /// up to `capacity` records. Must be called within a Tokio runtime.
+    pub fn with_capacity<K: AuditSink>(sink: K, capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::channel(capacity);
+        tokio::spawn(write_records(sink, receiver));
+        AuditMiddleware {
+            config: Arc::new(AuditConfig {
+                max_captured_size: DEFAULT_MAX_CAPTURED_SIZE,
+                ..Default::default()
+            }),
+            sender,
+        }
+    }
+
+    /// Records the request message of the given method or service, decoded as `M`, in the
+    /// `request` field. Only the first message of streaming calls is recorded.
+    ///
+    /// # Parameters
+    ///
+    /// * `method`: A full method path (`/package.Service/Method`), a service wildcard
+    ///   (`/package.Service/*`) or `*` for all methods.
+    /// * `M`: The prost request message, serialized with serde. With `tonic-prost-build`, derive
+    ///   `serde::Serialize` via `type_attribute`.
+    pub fn record_request<M>(mut self, method: &str) -> Self
+    where
+        M: prost::Message + Default + serde::Serialize + 'static,
+    {
+        let decoder: RequestDecoder = Arc::new(|message| {
+            let message = M::decode(message).ok()?;
+            serde_json::to_value(&message).ok()
+        });
+        Arc::make_mut(&mut self.config)
+            .decoders
+            .insert(method, decoder);
+        self
+    }
+
+    /// Replaces the request field at `path` with `[REDACTED]` in records of the given method or
+    /// service.
+    ///
+    /// # Parameters
+    ///
+    /// * `method`: A full method path (`/package.Service/Method`), a service wildcard
+    ///   (`/package.Service/*`) or `*` for all methods.
+    /// * `path`: Dot-separated path of the field, using the field names of the generated
+    ///   message, e.g. `payment.card_number`. Repeated fields are redacted in every element.
+    pub fn redact_field(mut self, method: impl Into<String>, path: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.config)
+            .redactions
+            .entry(method)
+            .push(path.into());
+        self
+    }
+
+    /// Sets the maximum size of a request message to record, 64 KiB by default. Larger and
+    /// compressed messages are not recorded.
+    pub fn max_captured_size(mut self, max_captured_size: usize) -> Self {
+        Arc::make_mut(&mut self.config).max_captured_size = max_captured_size;
+        self
+    }
+
+    /// Waits until all records queued so far are written to the sink, e.g. before shutting down.
+    pub async fn flush(&self) {
+        let (done, written) = oneshot::channel();
+        if self.sender.send(Command::Flush(done)).await.is_ok() {
+            let _ = written.await;
+        }
+    }
+}
+
+#[async_trait]
+impl<S> Middleware<S> for AuditMiddleware
+where
+    S: ServiceBound,
+    S::Future: Send,
+{
+    async fn call(&self, req: Request<Body>, mut service: S) -> Result<Response<Body>, S::Error> {
+        let started = Instant::now();
+        let method = method_path(&req).to_string();
+        let principal = req.extensions().get::<Principal>().map(|p| p.id.clone());
+
+        let decoder = self.config.decoders.get(&method).cloned();
+        let captured = Arc::new(Mutex::new(None));
+        let req = match decoder {
+            Some(_) => {
+                let captured = captured.clone();
+                let max_size = self.config.max_captured_size;
+                req.map(|body| {
+                    Body::new(CapturingBody {
+                        inner: body,
+                        buffer: BytesMut::new(),
+                        max_size,
+                        captured: Some(captured),
+                    })
+                })
+            }
+            None => req,
+        };
+
+        let response = service.call(req).await?;
+
+        let redactions = self
+            .config
+            .redactions
+            .get_all(&method)
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect();
+        let sender = self.sender.clone();
+        Ok(ObservedBody::observe(
+            response,
+            method,
+            started,
+            move |status, _trailers, stats| {
+                let message = captured.lock().ok().and_then(|mut c| c.take());
+                let record = PendingRecord {
+                    timestamp_ms: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |d| d.as_millis() as u64),
+                    method: stats.method.clone(),
+                    principal,
+                    request: decoder.zip(message),
+                    redactions,
+                    status: status.clone(),
+                    duration_ms: stats.duration.as_millis() as u64,
+                };
+                if let Err(e) = sender.try_send(Command::Record(Box::new(record))) {
+                    tracing::error!(method = %stats.method, "Audit record dropped: {}", e);
+                }
+            },
+        ))
+    }
+}
+
+impl PendingRecord {
+    fn into_fields(self) -> Map<String, Value> {
+        let request = self.request.and_then(|(decode, message)| {
+            let mut request = decode(&message)?;
+            for path in &self.redactions {
+                redact(&mut request, &path.split('.').collect::<Vec<_>>());
+            }
+            Some(request)
+        });
+        let record = json!({
+            "timestamp_ms": self.timestamp_ms,
+            "method": self.method,
+            "principal": self.principal,
+            "request": request,
+            "status": {
+                "code": format!("{:?}", self.status.code()),
+                "message": self.status.message(),
+            },
+            "duration_ms": self.duration_ms,
+        });
+        match record {
+            Value::Object(fields) => fields,
+            _ => unreachable!("record is an object"),
+        }
+    }
+}
+
+/// Replaces the field at `path` of `value` with [REDACTED].
+fn redact(value: &mut Value, path: &[&str]) {
+    match value {
+        Value::Array(items) => items.iter_mut().for_each(|item| redact(item, path)),
+        Value::Object(fields) => {
+            let Some((name, rest)) = path.split_first() else {
+                return;
+            };
+            match fields.get_mut(*name) {
+                Some(field) if rest.is_empty() => *field = Value::String(REDACTED.to_string()),
+                Some(field) => redact(field, rest),
+                None => {}
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Returns the hex encoded SHA-256 hash of a record without its `hash` field.
+fn chain_hash(fields: &Map<String, Value>) -> String {
+    let serialized = Value::Object(fields.clone()).to_string();
+    Sha256::digest(serialized.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+async fn write_records<K: AuditSink>(mut sink: K, mut receiver: mpsc::Receiver<Command>) {
+    let mut prev_hash = match sink.last_hash().await {
+        Ok(hash) => hash.unwrap_or_else(|| GENESIS_HASH.to_string()),
+        Err(e) => {
+            tracing::error!(
+                "Failed to read the last audit record, starting a new chain: {}",
+                e
+            );
+            GENESIS_HASH.to_string()
+        }
+    };
+
+    while let Some(command) = receiver.recv().await {
+        let record = match command {
+            Command::Record(record) => record,
+            Command::Flush(done) => {
+                let _ = done.send(());
+                continue;
+            }
+        };
+        let mut fields = record.into_fields();
+        fields.insert("prev_hash".to_string(), Value::String(prev_hash.clone()));
+        let hash = chain_hash(&fields);
+        fields.insert("hash".to_string(), Value::String(hash.clone()));
+        match sink.write(&Value::Object(fields).to_string()).await {
+            Ok(()) => prev_hash = hash,
+            Err(e) => tracing::error!("Failed to write audit record: {}", e),
+        }
+    }
+}
+
+/// Verifies the hash chain of audit records written by [AuditMiddleware], returning the number
+/// of records.
+///
+/// The first record is trusted as the start of the chain, as logs may be rotated; every
+/// following record must hold the hash of its predecessor and match its own hash. Fails with
+/// `InvalidData` naming the line of the first record breaking the chain.
+pub fn verify_audit_log(reader: impl BufRead) -> io::Result<u64> {
+    let mut prev_hash: Option<String> = None;
+    let mut count = 0;
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = |reason: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Audit record on line {} {}", index + 1, reason),
+            )
+        };
+
+        let mut fields: Map<String, Value> =
+            serde_json::from_str(&line).map_err(|_| invalid("is no JSON object"))?;
+        let Some(Value::String(hash)) = fields.remove("hash") else {
+            return Err(invalid("has no hash"));
+        };
+        let record_prev_hash = fields
+            .get("prev_hash")
+            .and_then(Value::as_str)
+            .ok_or_else(|| invalid("has no previous hash"))?;
+        if prev_hash
+            .as_deref()
+            .is_some_and(|prev| prev != record_prev_hash)
+        {
+            return Err(invalid("does not follow the previous record"));
+        }
+        if chain_hash(&fields) != hash {
+            return Err(invalid("was modified"));
+        }
+        prev_hash = Some(hash);
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Request body wrapper capturing the first message while passing all frames through.
+struct CapturingBody {
+    inner: Body,
+    buffer: BytesMut,
+    max_size: usize,
+    captured: Option<Arc<Mutex<Option<Bytes>>>>,
+}
+
+impl CapturingBody {
+    fn capture(&mut self, data: &Bytes) {
+        self.buffer.extend_from_slice(data);
+        if self.buffer.len() < GRPC_HEADER_SIZE {
+            return;
+        }
+        let (compressed, len) = parse_frame_header(&self.buffer);
+        if compressed || len > self.max_size {
+            self.stop();
+        } else if self.buffer.len() >= GRPC_HEADER_SIZE + len {
+            let message = self.buffer.split_off(GRPC_HEADER_SIZE).split_to(len);
+            if let Some(Ok(mut captured)) = self.captured.as_ref().map(|c| c.lock()) {
+                *captured = Some(message.freeze());
+            }
+            self.stop();
+        }
+    }
+
+    fn stop(&mut self) {
+        self.captured = None;
+        self.buffer = BytesMut::new();
+    }
+}
+
+impl http_body::Body for CapturingBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = &mut *self;
+        match Pin::new(&mut this.inner).poll_frame(cx) {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(data) = frame.data_ref().filter(|_| this.captured.is_some()) {
+                    this.capture(data);
+                }
+                Poll::Ready(Some(Ok(frame)))
+            }
+            other => other,
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::grpc_method::parts_method_path;
+use crate::method_map::MethodMap;
+use crate::{MethodOptionsRegistry, Principal, RequestInterceptor};
+use async_trait::async_trait;
+use tonic::body::Body;
+use tonic::codegen::http::request::Parts;
+use tonic::codegen::http::Request;
+use tonic::Status;
+
+/// Outcome of a [Policy] evaluation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Decision {
+    /// The call is allowed.
+    Allow,
+    /// The call is denied for the given reason, which is returned to the client.
+    Deny(String),
+}
+
+/// The `Policy` trait decides whether an authenticated [Principal] may perform a request.
+///
+/// Implement it for role based (see [RolePolicy]) or attribute based rules, e.g. by comparing
+/// principal attributes with request metadata, or to delegate decisions to an external policy
+/// engine.
+#[async_trait]
+pub trait Policy: Send + Sync + 'static {
+    /// Evaluates the policy for a request.
+    ///
+    /// # Parameters
+    ///
+    /// * `principal`: The authenticated caller found in the request extensions.
+    /// * `request`: The request head, giving access to the method path, metadata and extensions.
+    async fn evaluate(&self, principal: &Principal, request: &Parts) -> Decision;
+}
+
+/// `RolePolicy` is a role based [Policy] allowing principals to call methods mapped to any of
+/// their roles.
+///
+/// Methods are given as full method paths (`/package.Service/Method`), service wildcards
+/// (`/package.Service/*`) or `*` for all methods.
+///
+/// With the `config` feature enabled, the mapping can be loaded from TOML or YAML:
+///
+/// ```toml
+/// [roles]
+/// admin = ["*"]
+/// customer = ["/estore.OrderService/GetMyOrders", "/estore.ProductService/*"]
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RolePolicy {
+    allowed_roles: MethodMap<HashSet<String>>,
+}
+
+impl RolePolicy {
+    /// Creates a new `RolePolicy` which does not allow any calls.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows principals with `role` to call the given method or service.
+    pub fn allow(mut self, role: impl Into<String>, method: impl Into<String>) -> Self {
+        self.allowed_roles.entry(method).insert(role.into());
+        self
+    }
+
+    /// Allows the roles listed in the `auth.roles` option of the methods and services to call
+    /// them.
+    pub fn method_options(mut self, options: &MethodOptionsRegistry) -> Self {
+        for (method, roles) in options.methods_with("auth.roles") {
+            for role in roles.as_strings() {
+                self = self.allow(role, method);
+            }
+        }
+        self
+    }
+
+    /// Parses the role to method mapping from TOML.
+    #[cfg(feature = "config")]
+    pub fn from_toml(s: &str) -> std::io::Result<Self> {
+        let file: RolePolicyFile = toml::from_str(s)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        Ok(file.into())
+    }
+
+    /// Parses the role to method mapping from YAML.
+    #[cfg(feature = "config")]
+    pub fn from_yaml(s: &str) -> std::io::Result<Self> {
+        let file: RolePolicyFile = serde_yaml::from_str(s)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        Ok(file.into())
+    }
+
+    /// Loads the role to method mapping from a `.toml`, `.yaml` or `.yml` file.
+    #[cfg(feature = "config")]
+    pub fn load(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml(&content),
+            Some("yaml") | Some("yml") => Self::from_yaml(&content),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Unsupported policy file format: {}", path.display()),
+            )),
+        }
+    }
+}
+
+#[cfg(feature = "config")]
+#[derive(serde::Deserialize)]
+struct RolePolicyFile {
+    roles: std::collections::HashMap<String, Vec<String>>,
+}
+
+#[cfg(feature = "config")]
+impl From<RolePolicyFile> for RolePolicy {
+    fn from(file: RolePolicyFile) -> Self {
+        let mut policy = RolePolicy::new();
+        for (role, methods) in file.roles {
+            for method in methods {
+                policy = policy.allow(role.clone(), method);
+            }
+        }
+        policy
+    }
+}
+
+#[async_trait]
+impl Policy for RolePolicy {
+    async fn evaluate(&self, principal: &Principal, request: &Parts) -> Decision {
+        let path = parts_method_path(request);
+        let allowed = self
+            .allowed_roles
+            .get_all(path)
+            .into_iter()
+            .any(|roles| roles.iter().any(|role| principal.has_role(role)));
+        if allowed {
+            Decision::Allow
+        } else {
+            Decision::Deny(format!("Not allowed to call {}", path))
+        }
+    }
+}
+
+/// `AuthorizationInterceptor` evaluates a [Policy] against the [Principal] found in the request
+/// extensions and rejects denied calls with `Status::permission_denied`.
+///
+/// It must be applied after an interceptor that authenticates the caller and inserts the
+/// principal (e.g. [crate::ApiKeyInterceptor]); requests without a principal are rejected with
+/// `Status::unauthenticated`.
+///
+/// In dry-run mode, decisions are only logged and all requests are let through, which helps
+/// rolling out new policies safely.
+///
+/// # Example
+///
+/// ```
+/// use tonic_middleware::{AuthorizationInterceptor, RolePolicy};
+///
+/// let policy = RolePolicy::new()
+///     .allow("admin", "*")
+///     .allow("customer", "/estore.OrderService/GetMyOrders");
+/// let interceptor = AuthorizationInterceptor::new(policy).dry_run(true);
+/// ```
+pub struct AuthorizationInterceptor<P: Policy> {
+    policy: Arc<P>,
+    dry_run: bool,
+}
+
+impl<P: Policy> Clone for AuthorizationInterceptor<P> {
+    fn clone(&self) -> Self {
+        AuthorizationInterceptor {
+            policy: self.policy.clone(),
+            dry_run: self.dry_run,
+        }
+    }
+}
+
+impl<P: Policy> AuthorizationInterceptor<P> {
+    /// Creates a new `AuthorizationInterceptor` enforcing the given policy.
+    pub fn new(policy: P) -> Self {
+        AuthorizationInterceptor {
+            policy: Arc::new(policy),
+            dry_run: false,
+        }
+    }
+
+    /// Enables or disables dry-run mode, in which decisions are logged but not enforced.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+}
+
+#[async_trait]
+impl<P: Policy> RequestInterceptor for AuthorizationInterceptor<P> {
+    async fn intercept(&self, req: Request<Body>) -> Result<Request<Body>, Status> {
+        let (parts, body) = req.into_parts();
+        let path = parts_method_path(&parts);
+
+        let decision = match parts.extensions.get::<Principal>() {
+            Some(principal) => {
+                let decision = self.policy.evaluate(principal, &parts).await;
+                if self.dry_run {
+                    tracing::info!(principal = %principal.id, method = %path, ?decision, "Authorization decision (dry run)");
+                } else if let Decision::Deny(reason) = &decision {
+                    tracing::debug!(principal = %principal.id, method = %path, %reason, "Authorization denied");
+                }
+                decision
+            }
+            None if self.dry_run => {
+                tracing::info!(method = %path, "Authorization decision (dry run): no principal");
+                Decision::Allow
+            }
+            None => return Err(Status::unauthenticated("Unauthenticated")),
+        };
+
+        match decision {
+            Decision::Deny(reason) if !self.dry_run => Err(Status::permission_denied(reason)),
+            _ => Ok(Request::from_parts(parts, body)),
+        }
+    }
+}
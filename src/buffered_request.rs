@@ -0,0 +1,200 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::grpc_frame::{parse_frame_header, GRPC_HEADER_SIZE};
+use bytes::{Buf, Bytes, BytesMut};
+use futures_util::future::poll_fn;
+use http_body::Frame;
+use tonic::body::Body;
+use tonic::codegen::http::request::Parts;
+use tonic::codegen::http::{HeaderMap, Request};
+use tonic::Status;
+
+/// A request whose body was read to its end, for interceptors that need to look at the payload,
+/// e.g. to verify a signature or validate messages, before forwarding the request.
+///
+/// The body is collected up to a size limit, so that a large or endless stream cannot exhaust the
+/// memory of the server. Request trailers, which some clients send after the last message, are
+/// kept and forwarded with the body.
+///
+/// # Example
+///
+/// ```
+/// use async_trait::async_trait;
+/// use tonic::body::Body;
+/// use tonic::codegen::http::Request;
+/// use tonic::Status;
+/// use tonic_middleware::{BufferedRequest, RequestInterceptor};
+///
+/// #[derive(Clone)]
+/// struct RejectEmptyMessages;
+///
+/// #[async_trait]
+/// impl RequestInterceptor for RejectEmptyMessages {
+///     async fn intercept(&self, req: Request<Body>) -> Result<Request<Body>, Status> {
+///         let req = BufferedRequest::collect(req, 64 * 1024).await?;
+///         if req.frames()?.iter().any(|frame| frame.data.is_empty()) {
+///             return Err(Status::invalid_argument("Empty message"));
+///         }
+///         Ok(req.into_request())
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct BufferedRequest {
+    parts: Parts,
+    body: Bytes,
+    trailers: Option<HeaderMap>,
+}
+
+/// A length-prefixed gRPC message of a [BufferedRequest].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GrpcFrame {
+    /// Whether the message is compressed with the `grpc-encoding` of the request.
+    pub compressed: bool,
+    /// The encoded message, without its header.
+    pub data: Bytes,
+}
+
+impl BufferedRequest {
+    /// Reads the body of `req` to its end, failing with `Status::resource_exhausted` if it
+    /// exceeds `max_size` bytes.
+    ///
+    /// # Parameters
+    ///
+    /// * `req`: The request to buffer.
+    /// * `max_size`: The maximum size of the body, including gRPC frame headers.
+    pub async fn collect(req: Request<Body>, max_size: usize) -> Result<Self, Status> {
+        let (parts, body) = req.into_parts();
+        let (body, trailers) = collect_body(body, max_size).await?;
+        Ok(BufferedRequest {
+            parts,
+            body,
+            trailers,
+        })
+    }
+
+    /// Returns the method, URI, headers and extensions of the request.
+    pub fn parts(&self) -> &Parts {
+        &self.parts
+    }
+
+    /// Returns the method, URI, headers and extensions of the request, to be modified before
+    /// forwarding it.
+    pub fn parts_mut(&mut self) -> &mut Parts {
+        &mut self.parts
+    }
+
+    /// Returns the raw body, made of length-prefixed gRPC messages.
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// Returns the trailers sent after the body, if any.
+    pub fn trailers(&self) -> Option<&HeaderMap> {
+        self.trailers.as_ref()
+    }
+
+    /// Splits the body into its gRPC messages, failing with `Status::internal` if the body ends
+    /// with an incomplete message.
+    pub fn frames(&self) -> Result<Vec<GrpcFrame>, Status> {
+        let mut body = self.body.clone();
+        let mut frames = Vec::new();
+        while !body.is_empty() {
+            if body.len() < GRPC_HEADER_SIZE {
+                return Err(Status::internal("Stream ended with an incomplete message"));
+            }
+            let (compressed, len) = parse_frame_header(&body);
+            body.advance(GRPC_HEADER_SIZE);
+            if body.len() < len {
+                return Err(Status::internal("Stream ended with an incomplete message"));
+            }
+            frames.push(GrpcFrame {
+                compressed,
+                data: body.split_to(len),
+            });
+        }
+        Ok(frames)
+    }
+
+    /// Rebuilds the request, replaying the buffered body and trailers, for forwarding.
+    pub fn into_request(self) -> Request<Body> {
+        Request::from_parts(
+            self.parts,
+            Body::new(ReplayBody::new(self.body, self.trailers)),
+        )
+    }
+}
+
+/// Reads `body` to its end, returning its data and trailers, failing if the data exceeds
+/// `max_size` bytes.
+pub(crate) async fn collect_body(
+    mut body: Body,
+    max_size: usize,
+) -> Result<(Bytes, Option<HeaderMap>), Status> {
+    let mut data = BytesMut::new();
+    let mut trailers = None;
+    while let Some(frame) = poll_fn(|cx| http_body::Body::poll_frame(Pin::new(&mut body), cx)).await
+    {
+        match frame?.into_data() {
+            Ok(chunk) => {
+                if data.len() + chunk.len() > max_size {
+                    return Err(Status::resource_exhausted(format!(
+                        "Message exceeds the buffering limit of {} bytes",
+                        max_size
+                    )));
+                }
+                data.extend_from_slice(&chunk);
+            }
+            Err(frame) => {
+                if let Ok(frame_trailers) = frame.into_trailers() {
+                    trailers = Some(frame_trailers);
+                }
+            }
+        }
+    }
+    Ok((data.freeze(), trailers))
+}
+
+/// Body replaying collected data followed by trailers.
+pub(crate) struct ReplayBody {
+    data: Option<Bytes>,
+    trailers: Option<HeaderMap>,
+}
+
+impl ReplayBody {
+    pub(crate) fn new(data: Bytes, trailers: Option<HeaderMap>) -> Self {
+        ReplayBody {
+            data: Some(data).filter(|data| !data.is_empty()),
+            trailers,
+        }
+    }
+}
+
+impl http_body::Body for ReplayBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if let Some(data) = self.data.take() {
+            return Poll::Ready(Some(Ok(Frame::data(data))));
+        }
+        Poll::Ready(
+            self.trailers
+                .take()
+                .map(|trailers| Ok(Frame::trailers(trailers))),
+        )
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.data.is_none() && self.trailers.is_none()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        let len = self.data.as_ref().map_or(0, |data| data.len() as u64);
+        http_body::SizeHint::with_exact(len)
+    }
+}
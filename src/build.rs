@@ -0,0 +1,169 @@
+//! Build-time helpers, for use from `build.rs` with the `build` feature enabled in
+//! `[build-dependencies]`.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use prost_reflect::{DescriptorPool, DynamicMessage, Kind, Value};
+
+use crate::OptionValue;
+
+/// Generates the [crate::MethodOptionsRegistry] of the custom options of all services and methods
+/// in a `FileDescriptorSet`.
+///
+/// The generated file defines `pub fn method_options() -> tonic_middleware::MethodOptionsRegistry`
+/// and is meant to be included with `include!`. Service options are set for the service wildcard
+/// (`/package.Service/*`), so they apply to all its methods. Message options are flattened into
+/// one option per field, and `bytes` and map options are skipped.
+///
+/// # Parameters
+///
+/// * `file_descriptor_set`: The path of the `FileDescriptorSet`, as written by
+///   `tonic-prost-build` with `file_descriptor_set_path`.
+/// * `out_file`: The path of the generated Rust file, usually in `OUT_DIR`.
+///
+/// # Example
+///
+/// ```ignore
+/// // build.rs
+/// use std::env;
+/// use std::path::PathBuf;
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+///     tonic_prost_build::configure()
+///         .file_descriptor_set_path(out_dir.join("estore.bin"))
+///         .compile_protos(&["proto/estore.proto"], &["proto"])?;
+///     tonic_middleware::build::generate_method_options(
+///         out_dir.join("estore.bin"),
+///         out_dir.join("method_options.rs"),
+///     )?;
+///     Ok(())
+/// }
+/// ```
+///
+/// ```ignore
+/// // main.rs
+/// include!(concat!(env!("OUT_DIR"), "/method_options.rs"));
+///
+/// let auth = ApiKeyInterceptor::new(store).method_options(&method_options());
+/// ```
+pub fn generate_method_options(
+    file_descriptor_set: impl AsRef<Path>,
+    out_file: impl AsRef<Path>,
+) -> io::Result<()> {
+    let bytes = fs::read(file_descriptor_set.as_ref())?;
+    let pool = DescriptorPool::decode(bytes.as_slice())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let mut options = Vec::new();
+    for service in pool.services() {
+        let wildcard = format!("/{}/*", service.full_name());
+        collect_options(&wildcard, &service.options(), &mut options);
+        for method in service.methods() {
+            let path = format!("/{}/{}", service.full_name(), method.name());
+            collect_options(&path, &method.options(), &mut options);
+        }
+    }
+
+    let mut code = String::from(
+        "// Generated by `tonic_middleware::build::generate_method_options`, do not edit.\n\n\
+         /// Returns the custom options of the methods.\n\
+         pub fn method_options() -> ::tonic_middleware::MethodOptionsRegistry {\n    \
+         ::tonic_middleware::MethodOptionsRegistry::new()",
+    );
+    for (method, name, value) in &options {
+        write!(
+            code,
+            "\n        .set({method:?}, {name:?}, {})",
+            value_expr(value)
+        )
+        .expect("Writing to a String cannot fail");
+    }
+    code.push_str("\n}\n");
+    fs::write(out_file, code)
+}
+
+/// Collects the extension options set in `options`, flattening message options.
+fn collect_options(
+    method: &str,
+    options: &DynamicMessage,
+    out: &mut Vec<(String, String, OptionValue)>,
+) {
+    for (extension, value) in options.extensions() {
+        collect_value(
+            method,
+            extension.full_name().to_string(),
+            &extension.kind(),
+            value,
+            out,
+        );
+    }
+}
+
+fn collect_value(
+    method: &str,
+    name: String,
+    kind: &Kind,
+    value: &Value,
+    out: &mut Vec<(String, String, OptionValue)>,
+) {
+    if let Value::Message(message) = value {
+        for (field, value) in message.fields() {
+            let name = format!("{name}.{}", field.name());
+            collect_value(method, name, &field.kind(), value, out);
+        }
+    } else if let Some(value) = option_value(kind, value) {
+        out.push((method.to_string(), name, value));
+    }
+}
+
+fn option_value(kind: &Kind, value: &Value) -> Option<OptionValue> {
+    Some(match value {
+        Value::Bool(value) => OptionValue::Bool(*value),
+        Value::I32(value) => OptionValue::Int((*value).into()),
+        Value::I64(value) => OptionValue::Int(*value),
+        Value::U32(value) => OptionValue::UInt((*value).into()),
+        Value::U64(value) => OptionValue::UInt(*value),
+        Value::F32(value) => OptionValue::Float((*value).into()),
+        Value::F64(value) => OptionValue::Float(*value),
+        Value::String(value) => OptionValue::String(value.clone()),
+        Value::EnumNumber(number) => match kind.as_enum().and_then(|e| e.get_value(*number)) {
+            Some(value) => OptionValue::String(value.name().to_string()),
+            None => OptionValue::Int((*number).into()),
+        },
+        Value::List(values) => OptionValue::List(
+            values
+                .iter()
+                .filter_map(|value| option_value(kind, value))
+                .collect(),
+        ),
+        Value::Bytes(_) | Value::Message(_) | Value::Map(_) => return None,
+    })
+}
+
+/// Returns the Rust expression building `value`.
+fn value_expr(value: &OptionValue) -> String {
+    match value {
+        OptionValue::Bool(value) => format!("::tonic_middleware::OptionValue::Bool({value})"),
+        OptionValue::Int(value) => format!("::tonic_middleware::OptionValue::Int({value})"),
+        OptionValue::UInt(value) => format!("::tonic_middleware::OptionValue::UInt({value})"),
+        OptionValue::Float(value) if value.is_nan() => {
+            "::tonic_middleware::OptionValue::Float(f64::NAN)".to_string()
+        }
+        OptionValue::Float(value) if value.is_infinite() => format!(
+            "::tonic_middleware::OptionValue::Float({}f64::INFINITY)",
+            if *value < 0.0 { "-" } else { "" }
+        ),
+        OptionValue::Float(value) => format!("::tonic_middleware::OptionValue::Float({value:?})"),
+        OptionValue::String(value) => {
+            format!("::tonic_middleware::OptionValue::String({value:?}.to_string())")
+        }
+        OptionValue::List(values) => format!(
+            "::tonic_middleware::OptionValue::List(vec![{}])",
+            values.iter().map(value_expr).collect::<Vec<_>>().join(", ")
+        ),
+    }
+}
@@ -0,0 +1,46 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Services that [crate::RequestInterceptorLayer] and [crate::MiddlewareLayer] do not apply to
+/// by default: the gRPC health checking and reflection services, which load balancers and
+/// tooling call without credentials.
+pub const DEFAULT_BYPASS_SERVICES: &[&str] = &[
+    "grpc.health.v1.Health",
+    "grpc.reflection.v1.ServerReflection",
+    "grpc.reflection.v1alpha.ServerReflection",
+];
+
+/// Fully qualified names of the services whose calls skip an interceptor or middleware.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Bypass {
+    services: Arc<HashSet<String>>,
+}
+
+impl Bypass {
+    /// Returns the bypass list of layers, [DEFAULT_BYPASS_SERVICES].
+    pub(crate) fn defaults() -> Self {
+        Bypass {
+            services: Arc::new(
+                DEFAULT_BYPASS_SERVICES
+                    .iter()
+                    .map(|service| service.to_string())
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Adds `service` to the list.
+    pub(crate) fn insert(&mut self, service: String) {
+        Arc::make_mut(&mut self.services).insert(service);
+    }
+
+    /// Returns `true` if the method at `path` (`/package.Service/Method`) belongs to a bypassed
+    /// service.
+    pub(crate) fn matches(&self, path: &str) -> bool {
+        !self.services.is_empty()
+            && path
+                .strip_prefix('/')
+                .and_then(|path| path.split_once('/'))
+                .is_some_and(|(service, _method)| self.services.contains(service))
+    }
+}
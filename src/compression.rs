@@ -0,0 +1,452 @@
+use std::collections::HashSet;
+use std::fmt;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+use std::pin::Pin;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+use std::task::{Context, Poll};
+
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+use crate::grpc_frame::{parse_frame_header, GRPC_HEADER_SIZE};
+use crate::grpc_method::method_path;
+use crate::method_map::MethodMap;
+use crate::{Middleware, ServiceBound};
+use async_trait::async_trait;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+use http_body::Frame;
+use tonic::body::Body;
+use tonic::codegen::http::{HeaderMap, HeaderValue, Request, Response};
+use tonic::Status;
+
+const GRPC_ENCODING: &str = "grpc-encoding";
+const GRPC_ACCEPT_ENCODING: &str = "grpc-accept-encoding";
+const IDENTITY: &str = "identity";
+
+/// Default limit of a decompressed request message, matching tonic's default decoding limit.
+const DEFAULT_MAX_DECOMPRESSED_SIZE: usize = 4 * 1024 * 1024;
+
+/// Compression encoding the [CompressionPolicyMiddleware] can compress and decompress messages
+/// with. Each encoding is enabled by the feature of the same name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Encoding {
+    /// `gzip` compression. Requires the `gzip` feature.
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// `zstd` compression. Requires the `zstd` feature.
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Encoding {
+    /// Returns the name of the encoding used in `grpc-encoding` headers.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => "gzip",
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => "zstd",
+        }
+    }
+
+    /// Returns the encoding named `name`, if it is supported.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            #[cfg(feature = "gzip")]
+            "gzip" => Some(Encoding::Gzip),
+            #[cfg(feature = "zstd")]
+            "zstd" => Some(Encoding::Zstd),
+            _ => None,
+        }
+    }
+
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    fn compress(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match *self {
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => {
+                use std::io::Write;
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => zstd::bulk::compress(data, 0),
+        }
+    }
+
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    fn decompress(&self, data: &[u8], limit: usize) -> std::io::Result<Vec<u8>> {
+        use std::io::Read;
+        let mut decompressed = Vec::new();
+        match *self {
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => flate2::read::GzDecoder::new(data)
+                .take(limit as u64 + 1)
+                .read_to_end(&mut decompressed)?,
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => zstd::stream::read::Decoder::new(data)?
+                .take(limit as u64 + 1)
+                .read_to_end(&mut decompressed)?,
+        };
+        Ok(decompressed)
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct MethodPolicy {
+    allowed_encodings: Option<HashSet<String>>,
+    compress_responses: Option<Encoding>,
+}
+
+/// `CompressionPolicyMiddleware` enables or forbids `grpc-encoding` compression per method or
+/// service, without configuring every generated server.
+///
+/// For each method, the encodings clients may compress requests with can be restricted; calls
+/// compressed with any other encoding are rejected with `Status::unimplemented`, and encodings
+/// that are not allowed are removed from `grpc-accept-encoding`, so the service does not
+/// compress responses with them either. Methods without a policy accept any encoding.
+///
+/// Where the service does not support an encoding itself, the middleware can also decompress
+/// requests before they reach it, and compress responses of selected methods. This requires
+/// the `gzip` or `zstd` feature.
+///
+/// # Example
+///
+/// ```
+/// use tonic_middleware::CompressionPolicyMiddleware;
+///
+/// let compression = CompressionPolicyMiddleware::new()
+///     // Small, latency-sensitive calls are never compressed
+///     .forbid("/estore.OrderService/GetOrderStatus")
+///     .allow_encodings("/estore.ProductService/*", ["gzip"]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct CompressionPolicyMiddleware {
+    policies: MethodMap<MethodPolicy>,
+    decompress_requests: bool,
+    max_decompressed_size: usize,
+}
+
+impl Default for CompressionPolicyMiddleware {
+    fn default() -> Self {
+        CompressionPolicyMiddleware {
+            policies: MethodMap::default(),
+            decompress_requests: false,
+            max_decompressed_size: DEFAULT_MAX_DECOMPRESSED_SIZE,
+        }
+    }
+}
+
+impl CompressionPolicyMiddleware {
+    /// Creates a new `CompressionPolicyMiddleware` accepting any encoding for all methods.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restricts the encodings allowed for the given method or service.
+    ///
+    /// # Parameters
+    ///
+    /// * `method`: A full method path (`/package.Service/Method`), a service wildcard
+    ///   (`/package.Service/*`) or `*` for all methods.
+    /// * `encodings`: Names of the allowed encodings, e.g. `gzip`. Uncompressed calls are always
+    ///   allowed.
+    pub fn allow_encodings<I, T>(mut self, method: impl Into<String>, encodings: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        self.policies.entry(method).allowed_encodings = Some(
+            encodings
+                .into_iter()
+                .map(|e| e.as_ref().to_ascii_lowercase())
+                .collect(),
+        );
+        self
+    }
+
+    /// Forbids compression of requests and responses for the given method or service.
+    pub fn forbid(self, method: impl Into<String>) -> Self {
+        self.allow_encodings(method, [] as [&str; 0])
+    }
+
+    /// Compresses responses of the given method or service with `encoding`, if the client
+    /// accepts it and the service did not compress them already.
+    ///
+    /// # Parameters
+    ///
+    /// * `method`: A full method path (`/package.Service/Method`), a service wildcard
+    ///   (`/package.Service/*`) or `*` for all methods.
+    /// * `encoding`: The encoding to compress responses with.
+    pub fn compress_responses(mut self, method: impl Into<String>, encoding: Encoding) -> Self {
+        self.policies.entry(method).compress_responses = Some(encoding);
+        self
+    }
+
+    /// Enables or disables decompression of requests compressed with a supported encoding
+    /// before they reach the service, so services do not need to accept compressed requests.
+    /// Disabled by default.
+    pub fn decompress_requests(mut self, decompress_requests: bool) -> Self {
+        self.decompress_requests = decompress_requests;
+        self
+    }
+
+    /// Sets the maximum size of a decompressed request message, 4 MiB by default.
+    pub fn max_decompressed_size(mut self, max_decompressed_size: usize) -> Self {
+        self.max_decompressed_size = max_decompressed_size;
+        self
+    }
+}
+
+/// Returns the encodings listed in the `header` of `headers`.
+fn listed_encodings(headers: &HeaderMap, header: &str) -> Vec<String> {
+    headers
+        .get_all(header)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|e| e.trim().to_ascii_lowercase())
+        .filter(|e| !e.is_empty())
+        .collect()
+}
+
+#[async_trait]
+impl<S> Middleware<S> for CompressionPolicyMiddleware
+where
+    S: ServiceBound,
+    S::Future: Send,
+{
+    async fn call(&self, req: Request<Body>, mut service: S) -> Result<Response<Body>, S::Error> {
+        let path = method_path(&req).to_string();
+        let policy = self.policies.get(&path).cloned().unwrap_or_default();
+        let allowed = |encoding: &str| {
+            encoding == IDENTITY
+                || policy
+                    .allowed_encodings
+                    .as_ref()
+                    .is_none_or(|allowed| allowed.contains(encoding))
+        };
+
+        let (mut parts, body) = req.into_parts();
+
+        let request_encoding = listed_encodings(&parts.headers, GRPC_ENCODING)
+            .into_iter()
+            .next()
+            .filter(|e| e != IDENTITY);
+        if let Some(encoding) = &request_encoding {
+            if !allowed(encoding) {
+                let mut status = Status::unimplemented(format!(
+                    "Content is compressed with `{}` which is not allowed for {}",
+                    encoding, path
+                ));
+                let accepted = policy
+                    .allowed_encodings
+                    .iter()
+                    .flatten()
+                    .map(String::as_str)
+                    .chain([IDENTITY])
+                    .collect::<Vec<_>>()
+                    .join(",");
+                if let Ok(accepted) = accepted.parse() {
+                    status.metadata_mut().insert(GRPC_ACCEPT_ENCODING, accepted);
+                }
+                return Ok(status.into_http());
+            }
+        }
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        let body = match request_encoding
+            .as_deref()
+            .and_then(Encoding::from_name)
+            .filter(|_| self.decompress_requests)
+        {
+            Some(codec) => {
+                parts.headers.remove(GRPC_ENCODING);
+                Body::new(RecodedBody::new(
+                    body,
+                    Recode::Decompress(codec, self.max_decompressed_size),
+                ))
+            }
+            None => body,
+        };
+
+        let client_accepts = listed_encodings(&parts.headers, GRPC_ACCEPT_ENCODING);
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        let response_codec = policy
+            .compress_responses
+            .filter(|codec| client_accepts.iter().any(|e| e == codec.as_str()));
+        if policy.allowed_encodings.is_some() {
+            parts.headers.remove(GRPC_ACCEPT_ENCODING);
+            let accepted = client_accepts
+                .iter()
+                .filter(|e| allowed(e))
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(",");
+            if let Ok(accepted) = HeaderValue::from_str(&accepted) {
+                if !accepted.is_empty() {
+                    parts.headers.insert(GRPC_ACCEPT_ENCODING, accepted);
+                }
+            }
+        }
+
+        let response = service.call(Request::from_parts(parts, body)).await?;
+
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        if let Some(codec) = response_codec.filter(|codec| {
+            allowed(codec.as_str()) && !response.headers().contains_key(GRPC_ENCODING)
+        }) {
+            let (mut parts, body) = response.into_parts();
+            parts
+                .headers
+                .insert(GRPC_ENCODING, HeaderValue::from_static(codec.as_str()));
+            let body = Body::new(RecodedBody::new(body, Recode::Compress(codec)));
+            return Ok(Response::from_parts(parts, body));
+        }
+        Ok(response)
+    }
+}
+
+/// Transformation applied to every gRPC message of a [RecodedBody].
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+#[derive(Clone, Copy)]
+enum Recode {
+    /// Compresses uncompressed messages.
+    Compress(Encoding),
+    /// Decompresses compressed messages, up to the given size.
+    Decompress(Encoding, usize),
+}
+
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+impl Recode {
+    /// Returns the maximum length of a message before it is re-encoded, if limited.
+    fn max_message_size(&self) -> Option<usize> {
+        match *self {
+            Recode::Compress(_) => None,
+            Recode::Decompress(_, limit) => Some(limit),
+        }
+    }
+
+    fn apply(&self, compressed: bool, message: &[u8]) -> Result<(bool, Vec<u8>), Status> {
+        match *self {
+            Recode::Compress(codec) if !compressed => codec
+                .compress(message)
+                .map(|message| (true, message))
+                .map_err(|e| Status::internal(format!("Failed to compress message: {}", e))),
+            Recode::Decompress(codec, limit) if compressed => {
+                let message = codec.decompress(message, limit).map_err(|e| {
+                    Status::invalid_argument(format!("Failed to decompress message: {}", e))
+                })?;
+                if message.len() > limit {
+                    return Err(Status::resource_exhausted(format!(
+                        "Decompressed message exceeds the limit of {} bytes",
+                        limit
+                    )));
+                }
+                Ok((false, message))
+            }
+            _ => Ok((compressed, message.to_vec())),
+        }
+    }
+}
+
+/// Body wrapper re-encoding the length-prefixed gRPC messages streamed through it.
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+struct RecodedBody {
+    inner: Body,
+    recode: Recode,
+    buffer: BytesMut,
+    failed: bool,
+}
+
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+impl RecodedBody {
+    fn new(inner: Body, recode: Recode) -> Self {
+        RecodedBody {
+            inner,
+            recode,
+            buffer: BytesMut::new(),
+            failed: false,
+        }
+    }
+
+    /// Re-encodes the complete messages in the buffer, leaving incomplete ones buffered.
+    ///
+    /// Messages longer than the limit of the recoding are rejected from their header, before
+    /// they are buffered.
+    fn recode_buffered(&mut self) -> Result<Bytes, Status> {
+        let mut out = BytesMut::new();
+        while self.buffer.len() >= GRPC_HEADER_SIZE {
+            let (compressed, len) = parse_frame_header(&self.buffer);
+            if let Some(max) = self.recode.max_message_size().filter(|max| len > *max) {
+                return Err(Status::resource_exhausted(format!(
+                    "Message of {} bytes exceeds the limit of {} bytes",
+                    len, max
+                )));
+            }
+            if self.buffer.len() < GRPC_HEADER_SIZE + len {
+                break;
+            }
+            self.buffer.advance(GRPC_HEADER_SIZE);
+            let message = self.buffer.split_to(len);
+            let (compressed, message) = self.recode.apply(compressed, &message)?;
+            out.put_u8(compressed as u8);
+            out.put_u32(message.len() as u32);
+            out.put_slice(&message);
+        }
+        Ok(out.freeze())
+    }
+}
+
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+impl http_body::Body for RecodedBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if self.failed {
+            return Poll::Ready(None);
+        }
+        loop {
+            match Pin::new(&mut self.inner).poll_frame(cx) {
+                Poll::Ready(Some(Ok(frame))) => match frame.into_data() {
+                    Ok(data) => {
+                        self.buffer.extend_from_slice(&data);
+                        match self.recode_buffered() {
+                            // Wait for the rest of an incomplete message
+                            Ok(data) if data.is_empty() => continue,
+                            Ok(data) => return Poll::Ready(Some(Ok(Frame::data(data)))),
+                            Err(status) => {
+                                self.failed = true;
+                                return Poll::Ready(Some(Err(status)));
+                            }
+                        }
+                    }
+                    Err(frame) => return Poll::Ready(Some(Ok(frame))),
+                },
+                Poll::Ready(None) if !self.buffer.is_empty() => {
+                    self.failed = true;
+                    return Poll::Ready(Some(Err(Status::internal(
+                        "Stream ended with an incomplete message",
+                    ))));
+                }
+                other => return other,
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.failed || (self.buffer.is_empty() && self.inner.is_end_stream())
+    }
+}
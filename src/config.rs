@@ -0,0 +1,730 @@
+//! Declarative interceptor and middleware pipelines.
+//!
+//! A [PipelineConfig] describes which interceptors and middlewares a server applies, in which
+//! order, to which methods and with which parameters. [build_layer] turns it into a [DynStack]
+//! that can be passed to `Server::builder().layer(...)`, so the pipeline can differ between
+//! environments without recompiling.
+//!
+//! ```toml
+//! [[pipeline]]
+//! type = "metadata_policy"
+//! strip = ["user_id"]
+//!
+//! [[pipeline]]
+//! type = "max_message_size"
+//! methods = ["/estore.OrderService/*"]
+//! max_message_size = 65536
+//!
+//! [[pipeline]]
+//! type = "metrics"
+//! ```
+//!
+//! Every stage has a `type`, naming an entry of the [Registry], and optionally `methods`, a list
+//! of full method paths (`/package.Service/Method`), service wildcards (`/package.Service/*`) or
+//! `*`, restricting the stage to matching calls. All other keys are parameters of the stage.
+//!
+//! Like [crate::MiddlewareLayer], the pipeline skips the services in
+//! [crate::DEFAULT_BYPASS_SERVICES], i.e. health checks and reflection. The top-level `bypass` key
+//! lists more services to skip, and `no_bypass = true` applies the pipeline to all services.
+//!
+//! Built-in stages:
+//!
+//! * `max_message_size`: [MaxMessageSizeInterceptor] with `max_message_size`, `max_call_bytes`,
+//!   `method_limits` and `method_max_call_bytes` (tables of method to bytes).
+//! * `metadata_policy`: [MetadataPolicy] with `require` (table of method to keys),
+//!   `allowed_values` (table of key to pattern), `strip`, `trim`, `max_header_size`,
+//!   `max_total_size` and `validate_binary`.
+//! * `api_key`: [ApiKeyInterceptor] backed by an [InMemoryApiKeyStore] loaded from `keys_file`,
+//!   with `header` and `required_scopes` (table of method to scopes).
+//! * `authorization`: [AuthorizationInterceptor] with a [RolePolicy] given inline as `roles`
+//!   (table of role to methods) or loaded from `policy_file`, and `dry_run`.
+//! * `peer_identity`: [crate::PeerIdentityInterceptor] with `allow` (table of method to
+//!   identities) and `insert_principal`. Requires the `mtls` feature.
+//! * `signature`: [SignatureInterceptor] backed by an [InMemoryNonceStore], reading its secret
+//!   from the environment variable named by `secret_env`, with `previous_secret_env`,
+//!   `max_skew_secs` and `max_body_size`.
+//! * `rate_limit`: [RateLimitInterceptor] with `method_limits` (table of method to `rps` and
+//!   optional `burst`).
+//! * `tenant`: [TenantMiddleware] backed by an [InMemoryTenantResolver] holding `tenants`
+//!   (table of tenant id to `enabled_methods`, `disabled_methods`, `max_message_size`,
+//!   `max_call_bytes` and `rate_limit`), with `header`, `principal_attribute` and
+//!   `allow_missing`.
+//! * `idempotency`: [IdempotencyMiddleware] backed by an [InMemoryIdempotencyStore], with
+//!   `apply_to`, `ttl_secs`, `wait_for_in_flight_ms` and `max_body_size`.
+//! * `compression`: [CompressionPolicyMiddleware] with `allow_encodings` (table of method to
+//!   encodings), `forbid`, `compress_responses` (table of method to encoding),
+//!   `decompress_requests` and `max_decompressed_size`.
+//! * `redact_errors`: [RedactErrorsMiddleware] with `codes` (e.g. `internal`), `message` and
+//!   `strip_details`.
+//!
+//! [crate::DrainMiddleware] and [crate::HealthReportingMiddleware] are not built-in stages, as
+//! they are driven by a handle or a `HealthReporter` owned by the application; they, and custom
+//! interceptors and middlewares, are made available under a name with
+//! [Registry::register_interceptor] and [Registry::register_middleware].
+//!
+//! Requires the `config` feature.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::grpc_method::method_path;
+use crate::method_map::MethodMap;
+use crate::{
+    ApiKeyInterceptor, AuthorizationInterceptor, BoxInterceptor, BoxMiddleware, CallStats,
+    CompressionPolicyMiddleware, DynService, DynStack, Encoding, IdempotencyMiddleware,
+    InMemoryApiKeyStore, InMemoryIdempotencyStore, InMemoryNonceStore, InMemoryTenantResolver,
+    MaxMessageSizeInterceptor, MetadataPolicy, Middleware, RateLimit, RateLimitInterceptor,
+    RedactErrorsMiddleware, RequestInterceptor, RolePolicy, SignatureInterceptor, StackEntry,
+    Tenant, TenantMiddleware, TenantPolicy,
+};
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tonic::body::Body;
+use tonic::codegen::http::{HeaderMap, Request, Response};
+use tonic::codegen::Service;
+use tonic::{Code, Status};
+
+/// Description of an interceptor and middleware pipeline, executed in order: the first stage is
+/// the outermost and sees the request first.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PipelineConfig {
+    /// Stages of the pipeline.
+    #[serde(default)]
+    pub pipeline: Vec<StageConfig>,
+    /// Fully qualified names of services whose calls skip the pipeline, in addition to
+    /// [crate::DEFAULT_BYPASS_SERVICES].
+    #[serde(default)]
+    pub bypass: Vec<String>,
+    /// Applies the pipeline to all services, including health checks and reflection.
+    #[serde(default)]
+    pub no_bypass: bool,
+}
+
+impl PipelineConfig {
+    /// Parses the pipeline from TOML.
+    pub fn from_toml(s: &str) -> io::Result<Self> {
+        toml::from_str(s).map_err(invalid_data)
+    }
+
+    /// Parses the pipeline from YAML.
+    pub fn from_yaml(s: &str) -> io::Result<Self> {
+        serde_yaml::from_str(s).map_err(invalid_data)
+    }
+
+    /// Loads the pipeline from a `.toml`, `.yaml` or `.yml` file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml(&content),
+            Some("yaml") | Some("yml") => Self::from_yaml(&content),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unsupported pipeline file format: {}", path.display()),
+            )),
+        }
+    }
+}
+
+/// A single stage of a [PipelineConfig].
+#[derive(Clone, Debug, Deserialize)]
+pub struct StageConfig {
+    /// Name under which the interceptor or middleware is registered in the [Registry].
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Methods or services the stage applies to. Applies to all methods if empty.
+    #[serde(default)]
+    pub methods: Vec<String>,
+    #[serde(flatten)]
+    params: serde_yaml::Mapping,
+}
+
+impl StageConfig {
+    /// Deserializes the parameters of the stage, i.e. all keys except `type` and `methods`.
+    pub fn params<T: DeserializeOwned>(&self) -> io::Result<T> {
+        serde_yaml::from_value(serde_yaml::Value::Mapping(self.params.clone())).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid parameters of `{}`: {}", self.kind, e),
+            )
+        })
+    }
+}
+
+type Factory = Arc<dyn Fn(&StageConfig) -> io::Result<StackEntry> + Send + Sync>;
+
+/// `Registry` maps stage types of a [PipelineConfig] to factories building the corresponding
+/// interceptors and middlewares.
+///
+/// # Example
+///
+/// ```
+/// use tonic_middleware::config::{PipelineConfig, Registry};
+/// use tonic_middleware::MetadataPolicy;
+///
+/// #[derive(serde::Deserialize)]
+/// struct RequireHeaderParams {
+///     header: String,
+/// }
+///
+/// let registry = Registry::new().register_interceptor("require_header", |stage| {
+///     let params: RequireHeaderParams = stage.params()?;
+///     Ok(MetadataPolicy::new().require("*", params.header))
+/// });
+/// let config = PipelineConfig::from_toml(
+///     r#"
+///     [[pipeline]]
+///     type = "require_header"
+///     header = "x-request-id"
+///     "#,
+/// )
+/// .unwrap();
+/// let layer = registry.build_layer(&config).unwrap();
+/// ```
+#[derive(Clone)]
+pub struct Registry {
+    factories: HashMap<String, Factory>,
+}
+
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Registry")
+            .field("types", &self.factories.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let registry = Registry {
+            factories: HashMap::new(),
+        }
+        .register_interceptor("max_message_size", max_message_size)
+        .register_interceptor("metadata_policy", metadata_policy)
+        .register_interceptor("api_key", api_key)
+        .register_interceptor("authorization", authorization)
+        .register_interceptor("signature", signature)
+        .register_interceptor("rate_limit", rate_limit)
+        .register_middleware("tenant", tenant)
+        .register_middleware("idempotency", idempotency)
+        .register_middleware("compression", compression)
+        .register_middleware("redact_errors", redact_errors);
+        #[cfg(feature = "mtls")]
+        let registry = registry.register_interceptor("peer_identity", peer_identity);
+        registry
+    }
+}
+
+impl Registry {
+    /// Creates a new `Registry` containing the built-in interceptors and middlewares.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a factory building a stack entry under the name `kind`, replacing any
+    /// previously registered factory with the same name.
+    pub fn register<F>(mut self, kind: impl Into<String>, factory: F) -> Self
+    where
+        F: Fn(&StageConfig) -> io::Result<StackEntry> + Send + Sync + 'static,
+    {
+        self.factories.insert(kind.into(), Arc::new(factory));
+        self
+    }
+
+    /// Registers a factory building a request interceptor under the name `kind`.
+    pub fn register_interceptor<I, F>(self, kind: impl Into<String>, factory: F) -> Self
+    where
+        I: RequestInterceptor + Send + Sync + 'static,
+        F: Fn(&StageConfig) -> io::Result<I> + Send + Sync + 'static,
+    {
+        self.register(kind, move |stage| {
+            factory(stage).map(|interceptor| BoxInterceptor::new(interceptor).into())
+        })
+    }
+
+    /// Registers a factory building a middleware under the name `kind`.
+    pub fn register_middleware<M, F>(self, kind: impl Into<String>, factory: F) -> Self
+    where
+        M: Middleware<DynService> + Send + Sync + 'static,
+        F: Fn(&StageConfig) -> io::Result<M> + Send + Sync + 'static,
+    {
+        self.register(kind, move |stage| {
+            factory(stage).map(|middleware| BoxMiddleware::new(middleware).into())
+        })
+    }
+
+    /// Builds the stages of `config` into a [DynStack].
+    ///
+    /// Returns an error if a stage has an unknown type or invalid parameters.
+    pub fn build_layer(&self, config: &PipelineConfig) -> io::Result<DynStack> {
+        let stack: DynStack = config
+            .pipeline
+            .iter()
+            .map(|stage| {
+                let factory = self.factories.get(&stage.kind).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Unknown pipeline stage type `{}`", stage.kind),
+                    )
+                })?;
+                let entry = factory(stage)?;
+                if stage.methods.is_empty() {
+                    return Ok(entry);
+                }
+                let mut methods = MethodMap::default();
+                for method in &stage.methods {
+                    methods.entry(method.as_str());
+                }
+                Ok(match entry {
+                    StackEntry::Interceptor(inner) => BoxInterceptor::new(MethodScoped {
+                        inner,
+                        methods: methods.clone(),
+                    })
+                    .into(),
+                    StackEntry::Middleware(inner) => {
+                        BoxMiddleware::new(MethodScoped { inner, methods }).into()
+                    }
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        let stack = match config.no_bypass {
+            true => stack.no_bypass(),
+            false => stack,
+        };
+        Ok(config
+            .bypass
+            .iter()
+            .fold(stack, |stack, service| stack.bypass(service.as_str())))
+    }
+}
+
+/// Builds the stages of `config` into a [DynStack] using the built-in interceptors and
+/// middlewares only.
+///
+/// Use [Registry::build_layer] to include custom interceptors and middlewares.
+pub fn build_layer(config: &PipelineConfig) -> io::Result<DynStack> {
+    Registry::new().build_layer(config)
+}
+
+/// Applies the wrapped interceptor or middleware only to calls of the given methods.
+struct MethodScoped<T> {
+    inner: T,
+    methods: MethodMap<()>,
+}
+
+#[async_trait]
+impl RequestInterceptor for MethodScoped<BoxInterceptor> {
+    async fn intercept(&self, req: Request<Body>) -> Result<Request<Body>, Status> {
+        if self.methods.get(method_path(&req)).is_some() {
+            self.inner.intercept(req).await
+        } else {
+            Ok(req)
+        }
+    }
+}
+
+#[async_trait]
+impl Middleware<DynService> for MethodScoped<BoxMiddleware> {
+    async fn call(
+        &self,
+        req: Request<Body>,
+        mut service: DynService,
+    ) -> Result<Response<Body>, <DynService as Service<Request<Body>>>::Error> {
+        if self.methods.get(method_path(&req)).is_some() {
+            self.inner.call(req, service).await
+        } else {
+            service.call(req).await
+        }
+    }
+
+    fn observe_completion(&self) -> bool {
+        self.inner.observe_completion()
+    }
+
+    fn on_complete(&self, status: &Status, trailers: Option<&HeaderMap>, stats: &CallStats) {
+        if self.methods.get(&stats.method).is_some() {
+            self.inner.on_complete(status, trailers, stats)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaxMessageSizeParams {
+    max_message_size: usize,
+    max_call_bytes: Option<usize>,
+    #[serde(default)]
+    method_limits: HashMap<String, usize>,
+    #[serde(default)]
+    method_max_call_bytes: HashMap<String, usize>,
+}
+
+fn max_message_size(stage: &StageConfig) -> io::Result<MaxMessageSizeInterceptor> {
+    let params: MaxMessageSizeParams = stage.params()?;
+    let mut interceptor = MaxMessageSizeInterceptor::new(params.max_message_size);
+    if let Some(max_call_bytes) = params.max_call_bytes {
+        interceptor = interceptor.max_call_bytes(max_call_bytes);
+    }
+    for (method, limit) in params.method_limits {
+        interceptor = interceptor.method_limit(method, limit);
+    }
+    for (method, limit) in params.method_max_call_bytes {
+        interceptor = interceptor.method_max_call_bytes(method, limit);
+    }
+    Ok(interceptor)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MetadataPolicyParams {
+    #[serde(default)]
+    require: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    allowed_values: HashMap<String, String>,
+    #[serde(default)]
+    strip: Vec<String>,
+    #[serde(default)]
+    trim: Vec<String>,
+    max_header_size: Option<usize>,
+    max_total_size: Option<usize>,
+    validate_binary: Option<bool>,
+}
+
+fn metadata_policy(stage: &StageConfig) -> io::Result<MetadataPolicy> {
+    let params: MetadataPolicyParams = stage.params()?;
+    let mut policy = MetadataPolicy::new();
+    for (method, keys) in params.require {
+        for key in keys {
+            policy = policy.require(method.clone(), key);
+        }
+    }
+    for (key, pattern) in params.allowed_values {
+        policy = policy.allowed_values(key, &pattern).map_err(invalid_data)?;
+    }
+    for key in params.strip {
+        policy = policy.strip(key);
+    }
+    for key in params.trim {
+        policy = policy.trim(key);
+    }
+    if let Some(max_header_size) = params.max_header_size {
+        policy = policy.max_header_size(max_header_size);
+    }
+    if let Some(max_total_size) = params.max_total_size {
+        policy = policy.max_total_size(max_total_size);
+    }
+    if let Some(validate_binary) = params.validate_binary {
+        policy = policy.validate_binary(validate_binary);
+    }
+    Ok(policy)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ApiKeyParams {
+    keys_file: String,
+    header: Option<String>,
+    #[serde(default)]
+    required_scopes: HashMap<String, Vec<String>>,
+}
+
+fn api_key(stage: &StageConfig) -> io::Result<ApiKeyInterceptor<InMemoryApiKeyStore>> {
+    let params: ApiKeyParams = stage.params()?;
+    let mut interceptor = ApiKeyInterceptor::new(InMemoryApiKeyStore::load(&params.keys_file)?);
+    if let Some(header) = params.header {
+        interceptor = interceptor.header(header);
+    }
+    for (method, scopes) in params.required_scopes {
+        for scope in scopes {
+            interceptor = interceptor.require_scope(method.clone(), scope);
+        }
+    }
+    Ok(interceptor)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AuthorizationParams {
+    #[serde(default)]
+    roles: HashMap<String, Vec<String>>,
+    policy_file: Option<String>,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+fn authorization(stage: &StageConfig) -> io::Result<AuthorizationInterceptor<RolePolicy>> {
+    let params: AuthorizationParams = stage.params()?;
+    let mut policy = match &params.policy_file {
+        Some(path) => RolePolicy::load(path)?,
+        None => RolePolicy::new(),
+    };
+    for (role, methods) in params.roles {
+        for method in methods {
+            policy = policy.allow(role.clone(), method);
+        }
+    }
+    Ok(AuthorizationInterceptor::new(policy).dry_run(params.dry_run))
+}
+
+#[cfg(feature = "mtls")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PeerIdentityParams {
+    #[serde(default)]
+    allow: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    insert_principal: bool,
+}
+
+#[cfg(feature = "mtls")]
+fn peer_identity(stage: &StageConfig) -> io::Result<crate::PeerIdentityInterceptor> {
+    let params: PeerIdentityParams = stage.params()?;
+    let mut interceptor = crate::PeerIdentityInterceptor::new();
+    for (method, identities) in params.allow {
+        for identity in identities {
+            interceptor = interceptor.allow(method.clone(), identity);
+        }
+    }
+    Ok(interceptor.insert_principal(params.insert_principal))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SignatureParams {
+    secret_env: String,
+    previous_secret_env: Option<String>,
+    max_skew_secs: Option<u64>,
+    max_body_size: Option<usize>,
+}
+
+fn signature(stage: &StageConfig) -> io::Result<SignatureInterceptor<InMemoryNonceStore>> {
+    let params: SignatureParams = stage.params()?;
+    let mut interceptor =
+        SignatureInterceptor::new(secret(&params.secret_env)?, InMemoryNonceStore::new());
+    if let Some(name) = &params.previous_secret_env {
+        interceptor = interceptor.previous_secret(secret(name)?);
+    }
+    if let Some(max_skew_secs) = params.max_skew_secs {
+        interceptor = interceptor.max_skew(Duration::from_secs(max_skew_secs));
+    }
+    if let Some(max_body_size) = params.max_body_size {
+        interceptor = interceptor.max_body_size(max_body_size);
+    }
+    Ok(interceptor)
+}
+
+/// Reads a secret from the environment variable `name`, so that it is not stored in the
+/// pipeline file.
+fn secret(name: &str) -> io::Result<String> {
+    std::env::var(name).map_err(|e| invalid_data(format!("Invalid secret `{}`: {}", name, e)))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RateLimitParams {
+    rps: f64,
+    burst: Option<u32>,
+}
+
+impl RateLimitParams {
+    fn rate_limit(&self) -> RateLimit {
+        match self.burst {
+            Some(burst) => RateLimit::new(self.rps, burst),
+            None => RateLimit::per_second(self.rps),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RateLimitInterceptorParams {
+    #[serde(default)]
+    method_limits: HashMap<String, RateLimitParams>,
+}
+
+fn rate_limit(stage: &StageConfig) -> io::Result<RateLimitInterceptor> {
+    let params: RateLimitInterceptorParams = stage.params()?;
+    Ok(params.method_limits.iter().fold(
+        RateLimitInterceptor::new(),
+        |interceptor, (method, limit)| {
+            interceptor.method_limit(method.as_str(), limit.rate_limit())
+        },
+    ))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TenantPolicyParams {
+    #[serde(default)]
+    enabled_methods: Vec<String>,
+    #[serde(default)]
+    disabled_methods: Vec<String>,
+    max_message_size: Option<usize>,
+    max_call_bytes: Option<usize>,
+    rate_limit: Option<RateLimitParams>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TenantParams {
+    tenants: HashMap<String, TenantPolicyParams>,
+    header: Option<String>,
+    principal_attribute: Option<String>,
+    #[serde(default)]
+    allow_missing: Vec<String>,
+}
+
+fn tenant(stage: &StageConfig) -> io::Result<TenantMiddleware<InMemoryTenantResolver>> {
+    let params: TenantParams = stage.params()?;
+    let mut tenants = InMemoryTenantResolver::new();
+    for (id, params) in params.tenants {
+        let mut policy = TenantPolicy::new();
+        if !params.enabled_methods.is_empty() {
+            policy = policy.with_enabled_methods(params.enabled_methods);
+        }
+        for method in params.disabled_methods {
+            policy = policy.with_disabled_method(method);
+        }
+        if let Some(max_message_size) = params.max_message_size {
+            policy = policy.with_max_message_size(max_message_size);
+        }
+        if let Some(max_call_bytes) = params.max_call_bytes {
+            policy = policy.with_max_call_bytes(max_call_bytes);
+        }
+        if let Some(rate_limit) = &params.rate_limit {
+            policy = policy.with_rate_limit(rate_limit.rate_limit());
+        }
+        tenants.insert(Tenant::new(id).with_policy(policy));
+    }
+    let mut middleware = TenantMiddleware::new(tenants);
+    if let Some(header) = &params.header {
+        middleware = middleware.header(Some(header));
+    }
+    if let Some(attribute) = &params.principal_attribute {
+        middleware = middleware.principal_attribute(Some(attribute));
+    }
+    for method in params.allow_missing {
+        middleware = middleware.allow_missing(method);
+    }
+    Ok(middleware)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IdempotencyParams {
+    #[serde(default)]
+    apply_to: Vec<String>,
+    ttl_secs: Option<u64>,
+    wait_for_in_flight_ms: Option<u64>,
+    max_body_size: Option<usize>,
+}
+
+fn idempotency(stage: &StageConfig) -> io::Result<IdempotencyMiddleware> {
+    let params: IdempotencyParams = stage.params()?;
+    let mut middleware = IdempotencyMiddleware::new(InMemoryIdempotencyStore::new());
+    for method in params.apply_to {
+        middleware = middleware.apply_to(method);
+    }
+    if let Some(ttl_secs) = params.ttl_secs {
+        middleware = middleware.ttl(Duration::from_secs(ttl_secs));
+    }
+    if let Some(timeout) = params.wait_for_in_flight_ms {
+        middleware = middleware.wait_for_in_flight(Duration::from_millis(timeout));
+    }
+    if let Some(max_body_size) = params.max_body_size {
+        middleware = middleware.max_body_size(max_body_size);
+    }
+    Ok(middleware)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CompressionParams {
+    #[serde(default)]
+    allow_encodings: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    forbid: Vec<String>,
+    #[serde(default)]
+    compress_responses: HashMap<String, String>,
+    #[serde(default)]
+    decompress_requests: bool,
+    max_decompressed_size: Option<usize>,
+}
+
+fn compression(stage: &StageConfig) -> io::Result<CompressionPolicyMiddleware> {
+    let params: CompressionParams = stage.params()?;
+    let mut middleware = CompressionPolicyMiddleware::new();
+    for (method, encodings) in params.allow_encodings {
+        middleware = middleware.allow_encodings(method, encodings);
+    }
+    for method in params.forbid {
+        middleware = middleware.forbid(method);
+    }
+    for (method, encoding) in params.compress_responses {
+        let encoding = Encoding::from_name(&encoding)
+            .ok_or_else(|| invalid_data(format!("Unsupported encoding `{}`", encoding)))?;
+        middleware = middleware.compress_responses(method, encoding);
+    }
+    if let Some(max_decompressed_size) = params.max_decompressed_size {
+        middleware = middleware.max_decompressed_size(max_decompressed_size);
+    }
+    Ok(middleware.decompress_requests(params.decompress_requests))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RedactErrorsParams {
+    codes: Option<Vec<String>>,
+    message: Option<String>,
+    #[serde(default)]
+    strip_details: bool,
+}
+
+fn redact_errors(stage: &StageConfig) -> io::Result<RedactErrorsMiddleware> {
+    let params: RedactErrorsParams = stage.params()?;
+    let mut middleware = RedactErrorsMiddleware::new();
+    if let Some(codes) = &params.codes {
+        let codes = codes
+            .iter()
+            .map(|name| code(name))
+            .collect::<io::Result<Vec<_>>>()?;
+        middleware = middleware.codes(codes);
+    }
+    if let Some(message) = params.message {
+        middleware = middleware.message(message);
+    }
+    Ok(middleware.strip_details(params.strip_details))
+}
+
+/// Returns the status code named `name`, e.g. `internal` or `INTERNAL`.
+fn code(name: &str) -> io::Result<Code> {
+    Ok(match name.to_ascii_lowercase().as_str() {
+        "ok" => Code::Ok,
+        "cancelled" => Code::Cancelled,
+        "unknown" => Code::Unknown,
+        "invalid_argument" => Code::InvalidArgument,
+        "deadline_exceeded" => Code::DeadlineExceeded,
+        "not_found" => Code::NotFound,
+        "already_exists" => Code::AlreadyExists,
+        "permission_denied" => Code::PermissionDenied,
+        "resource_exhausted" => Code::ResourceExhausted,
+        "failed_precondition" => Code::FailedPrecondition,
+        "aborted" => Code::Aborted,
+        "out_of_range" => Code::OutOfRange,
+        "unimplemented" => Code::Unimplemented,
+        "internal" => Code::Internal,
+        "unavailable" => Code::Unavailable,
+        "data_loss" => Code::DataLoss,
+        "unauthenticated" => Code::Unauthenticated,
+        _ => return Err(invalid_data(format!("Unknown status code `{}`", name))),
+    })
+}
+
+fn invalid_data(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
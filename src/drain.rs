@@ -0,0 +1,219 @@
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::grpc_method::method_path;
+use crate::{Middleware, ObservedBody, ServiceBound};
+use async_trait::async_trait;
+use tokio::sync::Notify;
+use tonic::body::Body;
+use tonic::codegen::http::{Request, Response};
+use tonic::metadata::MetadataValue;
+use tonic::Status;
+
+/// Metadata key telling gRPC clients when to retry a rejected call, in milliseconds.
+const RETRY_PUSHBACK_HEADER: &str = "grpc-retry-pushback-ms";
+
+/// Rejects new calls with `Status::unavailable` once its [DrainHandle] starts draining, while the
+/// calls in flight, including streams, run to completion.
+///
+/// Rejected calls carry a `grpc-retry-pushback-ms` retry hint, so that clients with a retry
+/// policy retry them right away, landing on another instance behind the load balancer.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use tonic::service::Routes;
+/// use tonic::transport::Server;
+/// use tonic_middleware::{DrainMiddleware, MiddlewareLayer};
+///
+/// # async fn run(routes: Routes) -> Result<(), Box<dyn std::error::Error>> {
+/// let drain = DrainMiddleware::new().retry_after(Duration::from_millis(100));
+/// let handle = drain.handle();
+/// // Sent on SIGTERM, e.g. from a `tokio::signal` handler
+/// let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+/// let shutdown = handle.drain_on(shutdown_rx, Duration::from_secs(30));
+///
+/// Server::builder()
+///     .layer(MiddlewareLayer::new(drain))
+///     .add_routes(routes)
+///     .serve_with_shutdown("[::1]:50051".parse()?, shutdown)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct DrainMiddleware {
+    state: Arc<DrainState>,
+    retry_after: Duration,
+}
+
+/// Handle starting the drain of a [DrainMiddleware] and waiting for the calls in flight.
+#[derive(Clone)]
+pub struct DrainHandle {
+    state: Arc<DrainState>,
+}
+
+#[derive(Default)]
+struct DrainState {
+    draining: AtomicBool,
+    in_flight: AtomicUsize,
+    idle: Notify,
+}
+
+/// Counts a call in flight until dropped.
+struct InFlight {
+    state: Arc<DrainState>,
+}
+
+impl InFlight {
+    fn new(state: Arc<DrainState>) -> Self {
+        state.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight { state }
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if self.state.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.state.idle.notify_waiters();
+        }
+    }
+}
+
+impl Default for DrainMiddleware {
+    fn default() -> Self {
+        DrainMiddleware {
+            state: Arc::default(),
+            retry_after: Duration::ZERO,
+        }
+    }
+}
+
+impl DrainMiddleware {
+    /// Creates a new `DrainMiddleware`, accepting calls until its handle starts draining.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the delay after which clients should retry rejected calls, zero by default.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Returns a handle to drain this middleware and its clones.
+    pub fn handle(&self) -> DrainHandle {
+        DrainHandle {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for DrainMiddleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DrainMiddleware")
+            .field("draining", &self.state.draining.load(Ordering::SeqCst))
+            .field("in_flight", &self.state.in_flight.load(Ordering::SeqCst))
+            .field("retry_after", &self.retry_after)
+            .finish()
+    }
+}
+
+impl DrainHandle {
+    /// Starts draining: new calls are rejected from now on.
+    pub fn drain(&self) {
+        self.state.draining.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns `true` once draining started.
+    pub fn is_draining(&self) -> bool {
+        self.state.draining.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of calls in flight.
+    pub fn in_flight(&self) -> usize {
+        self.state.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once there are no calls in flight. Usually awaited after [DrainHandle::drain],
+    /// as new calls keep arriving otherwise.
+    pub async fn drained(&self) {
+        loop {
+            let idle = self.state.idle.notified();
+            if self.in_flight() == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+
+    /// Returns a shutdown signal for tonic's `serve_with_shutdown`: once `signal` resolves, it
+    /// starts draining and resolves when the calls in flight completed, or after `timeout`.
+    /// tonic then stops accepting connections and closes the existing ones with an HTTP/2
+    /// `GOAWAY`.
+    ///
+    /// # Parameters
+    ///
+    /// * `signal`: The signal to start draining on, e.g. a `tokio::signal` handler.
+    /// * `timeout`: The maximum time to wait for the calls in flight.
+    pub async fn drain_on<F>(self, signal: F, timeout: Duration)
+    where
+        F: Future,
+    {
+        signal.await;
+        self.drain();
+        let started = Instant::now();
+        if tokio::time::timeout(timeout, self.drained()).await.is_err() {
+            tracing::warn!(
+                in_flight = self.in_flight(),
+                waited = ?started.elapsed(),
+                "Shutting down with calls in flight"
+            );
+        }
+    }
+}
+
+impl fmt::Debug for DrainHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DrainHandle")
+            .field("draining", &self.is_draining())
+            .field("in_flight", &self.in_flight())
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<S> Middleware<S> for DrainMiddleware
+where
+    S: ServiceBound,
+    S::Future: Send,
+{
+    async fn call(&self, req: Request<Body>, mut service: S) -> Result<Response<Body>, S::Error> {
+        // Counted before checking, so that a call is either rejected or awaited by `drained`
+        let in_flight = InFlight::new(self.state.clone());
+        if self.state.draining.load(Ordering::SeqCst) {
+            let mut status = Status::unavailable("Server is shutting down, retry the call");
+            status.metadata_mut().insert(
+                RETRY_PUSHBACK_HEADER,
+                MetadataValue::from(
+                    u64::try_from(self.retry_after.as_millis()).unwrap_or(u64::MAX),
+                ),
+            );
+            return Ok(status.into_http());
+        }
+
+        let started = Instant::now();
+        let method = method_path(&req).to_string();
+        let response = service.call(req).await?;
+        Ok(ObservedBody::observe(
+            response,
+            method,
+            started,
+            move |_status, _trailers, _stats| drop(in_flight),
+        ))
+    }
+}
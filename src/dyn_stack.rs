@@ -0,0 +1,313 @@
+use std::convert::Infallible;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use crate::bypass::Bypass;
+use crate::{
+    CallStats, InterceptorFor, Middleware, MiddlewareFor, RequestInterceptor, ServiceBound,
+};
+use async_trait::async_trait;
+use tonic::body::Body;
+use tonic::codegen::http::{HeaderMap, Request, Response};
+use tonic::codegen::Service;
+use tonic::server::NamedService;
+use tonic::Status;
+use tower::util::BoxCloneSyncService;
+use tower::Layer;
+
+/// Type-erased service that middlewares of a [DynStack] are applied to.
+pub type DynService<E = Infallible> = BoxCloneSyncService<Request<Body>, Response<Body>, E>;
+
+/// `BoxInterceptor` is a type-erased, cheaply cloneable [RequestInterceptor].
+///
+/// It allows interceptors of different types to be stored together, e.g. in a [DynStack].
+#[derive(Clone)]
+pub struct BoxInterceptor {
+    inner: Arc<dyn RequestInterceptor + Send + Sync>,
+}
+
+impl BoxInterceptor {
+    /// Creates a new `BoxInterceptor` erasing the type of the given interceptor.
+    pub fn new<I>(interceptor: I) -> Self
+    where
+        I: RequestInterceptor + Send + Sync + 'static,
+    {
+        BoxInterceptor {
+            inner: Arc::new(interceptor),
+        }
+    }
+}
+
+#[async_trait]
+impl RequestInterceptor for BoxInterceptor {
+    async fn intercept(&self, req: Request<Body>) -> Result<Request<Body>, Status> {
+        self.inner.intercept(req).await
+    }
+}
+
+/// `BoxMiddleware` is a type-erased, cheaply cloneable [Middleware] applied to a [DynService].
+///
+/// It allows middlewares of different types to be stored together, e.g. in a [DynStack].
+///
+/// # Type Parameters
+///
+/// * `E`: The error type of the wrapped services, `Infallible` for tonic services.
+pub struct BoxMiddleware<E = Infallible> {
+    inner: Arc<dyn Middleware<DynService<E>> + Send + Sync>,
+}
+
+impl<E> Clone for BoxMiddleware<E> {
+    fn clone(&self) -> Self {
+        BoxMiddleware {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<E: 'static> BoxMiddleware<E> {
+    /// Creates a new `BoxMiddleware` erasing the type of the given middleware.
+    pub fn new<M>(middleware: M) -> Self
+    where
+        M: Middleware<DynService<E>> + Send + Sync + 'static,
+    {
+        BoxMiddleware {
+            inner: Arc::new(middleware),
+        }
+    }
+}
+
+#[async_trait]
+impl<E: 'static> Middleware<DynService<E>> for BoxMiddleware<E> {
+    async fn call(&self, req: Request<Body>, service: DynService<E>) -> Result<Response<Body>, E> {
+        self.inner.call(req, service).await
+    }
+
+    fn observe_completion(&self) -> bool {
+        self.inner.observe_completion()
+    }
+
+    fn on_complete(&self, status: &Status, trailers: Option<&HeaderMap>, stats: &CallStats) {
+        self.inner.on_complete(status, trailers, stats)
+    }
+}
+
+/// A single interceptor or middleware of a [DynStack].
+pub enum StackEntry<E = Infallible> {
+    /// A type-erased request interceptor.
+    Interceptor(BoxInterceptor),
+    /// A type-erased middleware.
+    Middleware(BoxMiddleware<E>),
+}
+
+impl<E> Clone for StackEntry<E> {
+    fn clone(&self) -> Self {
+        match self {
+            StackEntry::Interceptor(interceptor) => StackEntry::Interceptor(interceptor.clone()),
+            StackEntry::Middleware(middleware) => StackEntry::Middleware(middleware.clone()),
+        }
+    }
+}
+
+impl<E> From<BoxInterceptor> for StackEntry<E> {
+    fn from(interceptor: BoxInterceptor) -> Self {
+        StackEntry::Interceptor(interceptor)
+    }
+}
+
+impl<E> From<BoxMiddleware<E>> for StackEntry<E> {
+    fn from(middleware: BoxMiddleware<E>) -> Self {
+        StackEntry::Middleware(middleware)
+    }
+}
+
+/// `DynStack` is a list of interceptors and middlewares assembled at runtime, e.g. from
+/// configuration, and applied to services as a single tower `Layer`.
+///
+/// Entries are executed in order: the first entry is the outermost and sees the request first.
+/// The resulting service keeps the `NamedService` name of the wrapped service, so the stack can
+/// be applied to individual services as well as through `Server::layer`.
+///
+/// As with [crate::MiddlewareLayer], calls to the services in [crate::DEFAULT_BYPASS_SERVICES],
+/// i.e. health checks and reflection, and to the services added with [DynStack::bypass] skip the
+/// entries of the stack.
+///
+/// # Example
+///
+/// ```
+/// use tonic_middleware::{DynStack, MaxMessageSizeInterceptor, MetadataPolicy};
+///
+/// let production = true;
+/// let mut stack: DynStack = DynStack::new().push_interceptor(MetadataPolicy::new().strip("user_id"));
+/// if production {
+///     stack = stack.push_interceptor(MaxMessageSizeInterceptor::new(1024 * 1024));
+/// }
+/// ```
+pub struct DynStack<E = Infallible> {
+    entries: Vec<StackEntry<E>>,
+    bypass: Bypass,
+}
+
+impl<E> Clone for DynStack<E> {
+    fn clone(&self) -> Self {
+        DynStack {
+            entries: self.entries.clone(),
+            bypass: self.bypass.clone(),
+        }
+    }
+}
+
+impl<E> Default for DynStack<E> {
+    fn default() -> Self {
+        DynStack {
+            entries: Vec::new(),
+            bypass: Bypass::defaults(),
+        }
+    }
+}
+
+impl<E: 'static> DynStack<E> {
+    /// Creates a new empty `DynStack`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends an entry, which will be executed after the entries added before it.
+    pub fn push(mut self, entry: impl Into<StackEntry<E>>) -> Self {
+        self.entries.push(entry.into());
+        self
+    }
+
+    /// Appends an interceptor, which will be executed after the entries added before it.
+    pub fn push_interceptor<I>(self, interceptor: I) -> Self
+    where
+        I: RequestInterceptor + Send + Sync + 'static,
+    {
+        self.push(BoxInterceptor::new(interceptor))
+    }
+
+    /// Appends a middleware, which will be executed after the entries added before it.
+    pub fn push_middleware<M>(self, middleware: M) -> Self
+    where
+        M: Middleware<DynService<E>> + Send + Sync + 'static,
+    {
+        self.push(BoxMiddleware::new(middleware))
+    }
+
+    /// Skips the entries of the stack for calls to the given service.
+    ///
+    /// # Parameters
+    ///
+    /// * `service`: The fully qualified service name (`package.Service`), as in
+    ///   `NamedService::NAME`.
+    pub fn bypass(mut self, service: impl Into<String>) -> Self {
+        self.bypass.insert(service.into());
+        self
+    }
+
+    /// Applies the entries of the stack to all services, including health checks and reflection.
+    pub fn no_bypass(mut self) -> Self {
+        self.bypass = Bypass::default();
+        self
+    }
+
+    /// Returns the number of entries in the stack.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the stack has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<E> From<Vec<StackEntry<E>>> for DynStack<E> {
+    fn from(entries: Vec<StackEntry<E>>) -> Self {
+        DynStack {
+            entries,
+            bypass: Bypass::defaults(),
+        }
+    }
+}
+
+impl<E> FromIterator<StackEntry<E>> for DynStack<E> {
+    fn from_iter<T: IntoIterator<Item = StackEntry<E>>>(iter: T) -> Self {
+        DynStack {
+            entries: iter.into_iter().collect(),
+            bypass: Bypass::defaults(),
+        }
+    }
+}
+
+impl<S, E> Layer<S> for DynStack<E>
+where
+    S: ServiceBound<Error = E> + Sync,
+    S::Future: Send,
+    E: Send + 'static,
+{
+    type Service = DynStackService<S, E>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        let mut service = DynService::new(inner);
+        for entry in self.entries.iter().rev() {
+            service = match entry {
+                StackEntry::Interceptor(interceptor) => DynService::new(InterceptorFor {
+                    inner: service,
+                    interceptor: interceptor.clone(),
+                    bypass: self.bypass.clone(),
+                }),
+                StackEntry::Middleware(middleware) => DynService::new(MiddlewareFor {
+                    inner: service,
+                    middleware: middleware.clone(),
+                    bypass: self.bypass.clone(),
+                }),
+            };
+        }
+        DynStackService {
+            service,
+            _inner: PhantomData,
+        }
+    }
+}
+
+/// `DynStackService` is a service wrapped by a [DynStack].
+///
+/// # Type Parameters
+///
+/// * `S`: The wrapped service, whose `NamedService` name is preserved.
+/// * `E`: The error type of the wrapped service.
+pub struct DynStackService<S, E = Infallible> {
+    service: DynService<E>,
+    _inner: PhantomData<fn() -> S>,
+}
+
+impl<S, E> Clone for DynStackService<S, E> {
+    fn clone(&self) -> Self {
+        DynStackService {
+            service: self.service.clone(),
+            _inner: PhantomData,
+        }
+    }
+}
+
+impl<S, E> Service<Request<Body>> for DynStackService<S, E> {
+    type Response = Response<Body>;
+    type Error = E;
+    type Future = <DynService<E> as Service<Request<Body>>>::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        self.service.call(req)
+    }
+}
+
+impl<S, E> NamedService for DynStackService<S, E>
+where
+    S: NamedService,
+{
+    const NAME: &'static str = S::NAME;
+}
@@ -0,0 +1,349 @@
+use std::any::type_name;
+use std::fmt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+use std::ops::Deref;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::grpc_method::method_path;
+use crate::RequestInterceptor;
+use async_trait::async_trait;
+use tonic::body::Body;
+use tonic::codegen::http::Request;
+use tonic::transport::server::TcpConnectInfo;
+#[cfg(feature = "mtls")]
+use tonic::transport::server::TlsConnectInfo;
+use tonic::Status;
+
+const GRPC_TIMEOUT: &str = "grpc-timeout";
+
+/// Types that can be extracted from a request, as arguments of interceptors created with
+/// [extract_fn] or directly with [FromRequest::from_request].
+///
+/// Extraction fails with the status to reject the request with. Wrap an extractor in `Option`
+/// to make it optional.
+pub trait FromRequest: Sized {
+    /// Extracts the value from `req`.
+    fn from_request(req: &Request<Body>) -> Result<Self, Status>;
+}
+
+impl<T: FromRequest> FromRequest for Option<T> {
+    fn from_request(req: &Request<Body>) -> Result<Self, Status> {
+        Ok(T::from_request(req).ok())
+    }
+}
+
+/// Name of a metadata key extracted with [Metadata], usually declared with [crate::metadata_key].
+pub trait MetadataKey: Send + Sync + 'static {
+    /// The metadata key, in lowercase.
+    const NAME: &'static str;
+
+    /// Returns the status rejecting requests without a valid value. Defaults to
+    /// `Status::invalid_argument`.
+    fn invalid() -> Status {
+        Status::invalid_argument(format!("Missing or invalid `{}` metadata", Self::NAME))
+    }
+}
+
+/// Declares a [MetadataKey] type for the [Metadata] extractor.
+///
+/// # Example
+///
+/// ```
+/// use tonic_middleware::{metadata_key, Metadata};
+///
+/// metadata_key!(
+///     /// Tenant of the caller.
+///     pub TenantId = "x-tenant-id"
+/// );
+///
+/// type Tenant = Metadata<TenantId>;
+/// ```
+#[macro_export]
+macro_rules! metadata_key {
+    ($(#[$attr:meta])* $vis:vis $name:ident = $key:literal) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug, Default)]
+        $vis struct $name;
+
+        impl $crate::MetadataKey for $name {
+            const NAME: &'static str = $key;
+        }
+    };
+}
+
+/// The `authorization` metadata key. Requests without a valid value are rejected with
+/// `Status::unauthenticated`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Authorization;
+
+impl MetadataKey for Authorization {
+    const NAME: &'static str = "authorization";
+
+    fn invalid() -> Status {
+        Status::unauthenticated("Missing or invalid `authorization` metadata")
+    }
+}
+
+/// Extracts the ASCII value of the metadata key `K`, rejecting requests without it with
+/// [MetadataKey::invalid].
+pub struct Metadata<K> {
+    value: String,
+    _key: PhantomData<fn() -> K>,
+}
+
+impl<K> Metadata<K> {
+    /// Returns the metadata value.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Returns the metadata value, consuming the extractor.
+    pub fn into_value(self) -> String {
+        self.value
+    }
+}
+
+impl<K: MetadataKey> FromRequest for Metadata<K> {
+    fn from_request(req: &Request<Body>) -> Result<Self, Status> {
+        let value = req
+            .headers()
+            .get(K::NAME)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(K::invalid)?;
+        Ok(Metadata {
+            value: value.to_string(),
+            _key: PhantomData,
+        })
+    }
+}
+
+impl<K> Deref for Metadata<K> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.value
+    }
+}
+
+impl<K> Clone for Metadata<K> {
+    fn clone(&self) -> Self {
+        Metadata {
+            value: self.value.clone(),
+            _key: PhantomData,
+        }
+    }
+}
+
+impl<K: MetadataKey> fmt::Debug for Metadata<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Metadata")
+            .field(&K::NAME)
+            .field(&self.value)
+            .finish()
+    }
+}
+
+/// Extracts the path of the called method (`/package.Service/Method`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MethodPath(pub String);
+
+impl FromRequest for MethodPath {
+    fn from_request(req: &Request<Body>) -> Result<Self, Status> {
+        Ok(MethodPath(method_path(req).to_string()))
+    }
+}
+
+/// Extracts the address of the remote peer, rejecting requests with `Status::internal` when
+/// the transport does not provide it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PeerAddr(pub SocketAddr);
+
+impl FromRequest for PeerAddr {
+    fn from_request(req: &Request<Body>) -> Result<Self, Status> {
+        let extensions = req.extensions();
+        let addr = extensions
+            .get::<TcpConnectInfo>()
+            .and_then(TcpConnectInfo::remote_addr);
+        #[cfg(feature = "mtls")]
+        let addr = addr.or_else(|| {
+            extensions
+                .get::<TlsConnectInfo<TcpConnectInfo>>()
+                .and_then(|info| info.get_ref().remote_addr())
+        });
+        addr.map(PeerAddr)
+            .ok_or_else(|| Status::internal("Peer address is not available"))
+    }
+}
+
+/// Extracts a clone of the request extension `T`, e.g. a [crate::Principal] inserted by an
+/// earlier interceptor, rejecting requests without it with `Status::internal`.
+#[derive(Clone, Debug)]
+pub struct Extension<T>(pub T);
+
+impl<T> FromRequest for Extension<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    fn from_request(req: &Request<Body>) -> Result<Self, Status> {
+        req.extensions()
+            .get::<T>()
+            .cloned()
+            .map(Extension)
+            .ok_or_else(|| {
+                Status::internal(format!("Missing request extension `{}`", type_name::<T>()))
+            })
+    }
+}
+
+/// Extracts the deadline set by the client with `grpc-timeout`, `None` if it set none.
+/// Requests with a malformed timeout are rejected with `Status::invalid_argument`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Deadline(pub Option<Instant>);
+
+impl Deadline {
+    /// Returns the time left until the deadline, zero once it passed, or `None` without
+    /// deadline.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.0
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+}
+
+impl FromRequest for Deadline {
+    fn from_request(req: &Request<Body>) -> Result<Self, Status> {
+        let Some(value) = req.headers().get(GRPC_TIMEOUT) else {
+            return Ok(Deadline(None));
+        };
+        let timeout = value
+            .to_str()
+            .ok()
+            .and_then(parse_timeout)
+            .ok_or_else(|| Status::invalid_argument("Malformed `grpc-timeout` metadata"))?;
+        Ok(Deadline(Instant::now().checked_add(timeout)))
+    }
+}
+
+/// Parses a `grpc-timeout` value: up to 8 digits followed by a unit.
+fn parse_timeout(value: &str) -> Option<Duration> {
+    let unit_index = value.len().checked_sub(1)?;
+    let (digits, unit) = value.split_at(unit_index);
+    if digits.is_empty() || digits.len() > 8 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let amount: u64 = digits.parse().ok()?;
+    match unit {
+        "H" => Some(Duration::from_secs(amount * 60 * 60)),
+        "M" => Some(Duration::from_secs(amount * 60)),
+        "S" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_millis(amount)),
+        "u" => Some(Duration::from_micros(amount)),
+        "n" => Some(Duration::from_nanos(amount)),
+        _ => None,
+    }
+}
+
+/// Async function taking the request followed by extractors `Args`, usable with [extract_fn].
+/// Implemented for closures and functions with up to 8 extractors.
+pub trait ExtractHandler<Args>: Send + Sync + 'static {
+    /// Future returned by the handler.
+    type Future: Future<Output = Result<Request<Body>, Status>> + Send;
+
+    /// Extracts the arguments from `req` and calls the handler.
+    fn call(&self, req: Request<Body>) -> Result<Self::Future, Status>;
+}
+
+macro_rules! impl_extract_handler {
+    ($($arg:ident),*) => {
+        #[allow(non_snake_case, unused_variables)]
+        impl<F, Fut, $($arg,)*> ExtractHandler<($($arg,)*)> for F
+        where
+            F: Fn(Request<Body>, $($arg,)*) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = Result<Request<Body>, Status>> + Send,
+            $($arg: FromRequest,)*
+        {
+            type Future = Fut;
+
+            fn call(&self, req: Request<Body>) -> Result<Self::Future, Status> {
+                $(let $arg = $arg::from_request(&req)?;)*
+                Ok(self(req, $($arg,)*))
+            }
+        }
+    };
+}
+
+impl_extract_handler!();
+impl_extract_handler!(A1);
+impl_extract_handler!(A1, A2);
+impl_extract_handler!(A1, A2, A3);
+impl_extract_handler!(A1, A2, A3, A4);
+impl_extract_handler!(A1, A2, A3, A4, A5);
+impl_extract_handler!(A1, A2, A3, A4, A5, A6);
+impl_extract_handler!(A1, A2, A3, A4, A5, A6, A7);
+impl_extract_handler!(A1, A2, A3, A4, A5, A6, A7, A8);
+
+/// [RequestInterceptor] created from a function taking extractors with [extract_fn].
+pub struct ExtractInterceptor<H, Args> {
+    handler: Arc<H>,
+    _args: PhantomData<fn() -> Args>,
+}
+
+impl<H, Args> Clone for ExtractInterceptor<H, Args> {
+    fn clone(&self) -> Self {
+        ExtractInterceptor {
+            handler: self.handler.clone(),
+            _args: PhantomData,
+        }
+    }
+}
+
+impl<H, Args> fmt::Debug for ExtractInterceptor<H, Args> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtractInterceptor").finish_non_exhaustive()
+    }
+}
+
+/// Creates a [RequestInterceptor] from an async function taking the request followed by the
+/// extractors it needs, such as [Metadata], [MethodPath], [PeerAddr], [Extension] and
+/// [Deadline]. Requests are rejected with the status of the first failing extractor before the
+/// function is called.
+///
+/// # Example
+///
+/// ```
+/// use tonic::body::Body;
+/// use tonic::codegen::http::Request;
+/// use tonic::Status;
+/// use tonic_middleware::{extract_fn, Authorization, Metadata, MethodPath};
+///
+/// let auth = extract_fn(
+///     |req: Request<Body>, token: Metadata<Authorization>, MethodPath(method): MethodPath| async move {
+///         if token.value() == "Bearer secret" || method.starts_with("/estore.ProductService/") {
+///             Ok(req)
+///         } else {
+///             Err(Status::unauthenticated("Invalid token"))
+///         }
+///     },
+/// );
+/// ```
+pub fn extract_fn<H, Args>(handler: H) -> ExtractInterceptor<H, Args>
+where
+    H: ExtractHandler<Args>,
+{
+    ExtractInterceptor {
+        handler: Arc::new(handler),
+        _args: PhantomData,
+    }
+}
+
+#[async_trait]
+impl<H, Args> RequestInterceptor for ExtractInterceptor<H, Args>
+where
+    H: ExtractHandler<Args>,
+{
+    async fn intercept(&self, req: Request<Body>) -> Result<Request<Body>, Status> {
+        self.handler.call(req)?.await
+    }
+}
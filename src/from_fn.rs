@@ -0,0 +1,203 @@
+use std::convert::Infallible;
+use std::fmt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use crate::{Middleware, RequestInterceptor, ServiceBound};
+use async_trait::async_trait;
+use tonic::body::Body;
+use tonic::codegen::http::{Request, Response};
+use tonic::Status;
+use tower::util::BoxCloneService;
+use tower::ServiceExt;
+
+/// The rest of the service pipeline, passed to middlewares created with [middleware_fn] and the
+/// `#[middleware]` attribute macro.
+///
+/// # Type Parameters
+///
+/// * `E`: The error type of the wrapped services, `Infallible` for tonic services.
+pub struct Next<E = Infallible> {
+    service: BoxCloneService<Request<Body>, Response<Body>, E>,
+}
+
+impl<E> Next<E> {
+    /// Creates a new `Next` erasing the type of `service`.
+    pub fn new<S>(service: S) -> Self
+    where
+        S: ServiceBound<Error = E>,
+        S::Future: Send,
+    {
+        Next {
+            service: BoxCloneService::new(service),
+        }
+    }
+
+    /// Forwards `req` to the rest of the pipeline and returns its response.
+    pub async fn run(self, req: Request<Body>) -> Result<Response<Body>, E> {
+        self.service.oneshot(req).await
+    }
+}
+
+impl<E> Clone for Next<E> {
+    fn clone(&self) -> Self {
+        Next {
+            service: self.service.clone(),
+        }
+    }
+}
+
+impl<E> fmt::Debug for Next<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Next").finish_non_exhaustive()
+    }
+}
+
+/// [RequestInterceptor] created from an async closure with [from_fn] or [interceptor_fn].
+pub struct InterceptorFn<F> {
+    f: Arc<F>,
+}
+
+impl<F> Clone for InterceptorFn<F> {
+    fn clone(&self) -> Self {
+        InterceptorFn { f: self.f.clone() }
+    }
+}
+
+impl<F> fmt::Debug for InterceptorFn<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InterceptorFn").finish_non_exhaustive()
+    }
+}
+
+/// Creates a [RequestInterceptor] from an async closure, without defining a struct, like axum's
+/// `from_fn`.
+///
+/// The closure is shared between clones of the interceptor, so it does not need to be `Clone`
+/// itself, and the interceptor is `Clone + Send + Sync + 'static` as required by
+/// [crate::InterceptorFor] and [crate::RequestInterceptorLayer].
+///
+/// # Example
+///
+/// ```
+/// use tonic::Status;
+/// use tonic_middleware::from_fn;
+///
+/// let require_tenant = from_fn(|req| async move {
+///     if req.headers().contains_key("x-tenant-id") {
+///         Ok(req)
+///     } else {
+///         Err(Status::invalid_argument("Missing x-tenant-id"))
+///     }
+/// });
+/// ```
+pub fn from_fn<F, Fut>(f: F) -> InterceptorFn<F>
+where
+    F: Fn(Request<Body>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Request<Body>, Status>> + Send,
+{
+    InterceptorFn { f: Arc::new(f) }
+}
+
+/// Creates a [RequestInterceptor] from an async closure, the same as [from_fn].
+pub fn interceptor_fn<F, Fut>(f: F) -> InterceptorFn<F>
+where
+    F: Fn(Request<Body>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Request<Body>, Status>> + Send,
+{
+    from_fn(f)
+}
+
+#[async_trait]
+impl<F, Fut> RequestInterceptor for InterceptorFn<F>
+where
+    F: Fn(Request<Body>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Request<Body>, Status>> + Send,
+{
+    async fn intercept(&self, req: Request<Body>) -> Result<Request<Body>, Status> {
+        (self.f)(req).await
+    }
+}
+
+/// [Middleware] created from an async closure with [middleware_fn].
+///
+/// # Type Parameters
+///
+/// * `F`: The closure.
+/// * `E`: The error type of the wrapped services, `Infallible` for tonic services.
+pub struct MiddlewareFn<F, E = Infallible> {
+    f: Arc<F>,
+    _error: PhantomData<fn() -> E>,
+}
+
+impl<F, E> Clone for MiddlewareFn<F, E> {
+    fn clone(&self) -> Self {
+        MiddlewareFn {
+            f: self.f.clone(),
+            _error: PhantomData,
+        }
+    }
+}
+
+impl<F, E> fmt::Debug for MiddlewareFn<F, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MiddlewareFn").finish_non_exhaustive()
+    }
+}
+
+impl<F, E> MiddlewareFn<F, E> {
+    /// Creates a middleware from an async closure for services with the error type `E`. Use
+    /// [middleware_fn] for tonic services.
+    pub fn new<Fut>(f: F) -> Self
+    where
+        F: Fn(Request<Body>, Next<E>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Response<Body>, E>> + Send,
+    {
+        MiddlewareFn {
+            f: Arc::new(f),
+            _error: PhantomData,
+        }
+    }
+}
+
+/// Creates a [Middleware] for tonic services from an async closure receiving the request and the
+/// [Next] part of the pipeline, without defining a struct.
+///
+/// The closure is shared between clones of the middleware, so it does not need to be `Clone`
+/// itself, and the middleware is `Clone + Send + Sync + 'static` as required by
+/// [crate::MiddlewareFor] and [crate::MiddlewareLayer].
+///
+/// # Example
+///
+/// ```
+/// use tonic_middleware::middleware_fn;
+///
+/// let server_header = middleware_fn(|req, next| async move {
+///     let mut response = next.run(req).await?;
+///     response
+///         .headers_mut()
+///         .insert("x-served-by", "orders-1".parse().unwrap());
+///     Ok(response)
+/// });
+/// ```
+pub fn middleware_fn<F, Fut>(f: F) -> MiddlewareFn<F>
+where
+    F: Fn(Request<Body>, Next) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Response<Body>, Infallible>> + Send,
+{
+    MiddlewareFn::new(f)
+}
+
+#[async_trait]
+impl<S, F, Fut> Middleware<S> for MiddlewareFn<F, S::Error>
+where
+    S: ServiceBound,
+    S::Future: Send,
+    F: Fn(Request<Body>, Next<S::Error>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Response<Body>, S::Error>> + Send,
+{
+    async fn call(&self, req: Request<Body>, service: S) -> Result<Response<Body>, S::Error> {
+        (self.f)(req, Next::new(service)).await
+    }
+}
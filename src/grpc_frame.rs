@@ -0,0 +1,11 @@
+/// Size of the header prefixing every gRPC message: a 1-byte compression flag and a 4-byte
+/// big-endian length.
+pub(crate) const GRPC_HEADER_SIZE: usize = 5;
+
+/// Parses the header of a gRPC message, returning whether the message is compressed and its
+/// length. `header` must hold at least [GRPC_HEADER_SIZE] bytes.
+pub(crate) fn parse_frame_header(header: &[u8]) -> (bool, usize) {
+    let compressed = header[0] & 1 == 1;
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    (compressed, len)
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+use crate::FromRequest;
+use arc_swap::ArcSwap;
+use tonic::body::Body;
+use tonic::codegen::http::request::Parts;
+use tonic::codegen::http::Request;
+use tonic::Status;
+
+/// Streaming kinds of methods, keyed by full method path, registered from generated descriptors.
+///
+/// Registration replaces the whole map, so requests read it without locking.
+static STREAMING_KINDS: OnceLock<ArcSwap<HashMap<String, StreamingKind>>> = OnceLock::new();
+
+fn streaming_kinds() -> &'static ArcSwap<HashMap<String, StreamingKind>> {
+    STREAMING_KINDS.get_or_init(Default::default)
+}
+
+/// Adds streaming kinds to the registry.
+fn register_streaming_kinds(kinds: Vec<(String, StreamingKind)>) {
+    streaming_kinds().rcu(|current| {
+        let mut current = HashMap::clone(current);
+        current.extend(kinds.iter().cloned());
+        current
+    });
+}
+
+/// Whether the client, the server or both stream messages in a method.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StreamingKind {
+    /// A single request and a single response message.
+    Unary,
+    /// A stream of request messages and a single response message.
+    ClientStreaming,
+    /// A single request message and a stream of response messages.
+    ServerStreaming,
+    /// Streams of request and response messages.
+    Bidirectional,
+}
+
+impl StreamingKind {
+    /// Returns the streaming kind of a method from the streaming flags of its descriptor.
+    pub fn from_flags(client_streaming: bool, server_streaming: bool) -> Self {
+        match (client_streaming, server_streaming) {
+            (false, false) => StreamingKind::Unary,
+            (true, false) => StreamingKind::ClientStreaming,
+            (false, true) => StreamingKind::ServerStreaming,
+            (true, true) => StreamingKind::Bidirectional,
+        }
+    }
+}
+
+/// `GrpcMethod` is the called method parsed from the request path
+/// (`/package.Service/Method`).
+///
+/// [crate::InterceptorFor] and [crate::MiddlewareFor], and thus the layers, parse it once and
+/// store it in the request extensions, where interceptors and middlewares read it with
+/// [GrpcMethodExt::grpc_method] instead of parsing the path again. Requests with a malformed
+/// path are rejected with `Status::unimplemented` before reaching any of them.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GrpcMethod {
+    full_path: String,
+    package_len: usize,
+    method_start: usize,
+    streaming_kind: Option<StreamingKind>,
+}
+
+impl GrpcMethod {
+    /// Parses a request path, returning `Status::unimplemented` if it is not a gRPC method path.
+    ///
+    /// # Parameters
+    ///
+    /// * `path`: The request path, e.g. `/package.Service/Method`.
+    pub fn parse(path: &str) -> Result<Self, Status> {
+        let malformed = || Status::unimplemented(format!("Malformed gRPC method path `{path}`"));
+        let (service, method) = path
+            .strip_prefix('/')
+            .and_then(|path| path.split_once('/'))
+            .ok_or_else(malformed)?;
+        if !is_identifier(method) || !service.split('.').all(is_identifier) {
+            return Err(malformed());
+        }
+        let package_len = service.rfind('.').unwrap_or(0);
+        let streaming_kind = streaming_kinds().load().get(path).copied();
+        Ok(GrpcMethod {
+            full_path: path.to_string(),
+            package_len,
+            method_start: service.len() + 2,
+            streaming_kind,
+        })
+    }
+
+    /// Returns the package, e.g. `package`, empty for services declared without package.
+    pub fn package(&self) -> &str {
+        &self.full_path[1..1 + self.package_len]
+    }
+
+    /// Returns the service name without package, e.g. `Service`.
+    pub fn service(&self) -> &str {
+        let start = if self.package_len == 0 {
+            1
+        } else {
+            self.package_len + 2
+        };
+        &self.full_path[start..self.method_start - 1]
+    }
+
+    /// Returns the fully qualified service name, e.g. `package.Service`, as in
+    /// `NamedService::NAME`.
+    pub fn qualified_service(&self) -> &str {
+        &self.full_path[1..self.method_start - 1]
+    }
+
+    /// Returns the method name, e.g. `Method`.
+    pub fn method(&self) -> &str {
+        &self.full_path[self.method_start..]
+    }
+
+    /// Returns the full method path, e.g. `/package.Service/Method`.
+    pub fn full_path(&self) -> &str {
+        &self.full_path
+    }
+
+    /// Returns the streaming kind of the method, or `None` if no descriptor was registered for
+    /// it with [GrpcMethod::register_streaming_kind] or
+    /// [GrpcMethod::register_file_descriptor_set].
+    pub fn streaming_kind(&self) -> Option<StreamingKind> {
+        self.streaming_kind
+    }
+
+    /// Registers the streaming kind of a method, reported by [GrpcMethod::streaming_kind] for
+    /// subsequent requests.
+    ///
+    /// # Parameters
+    ///
+    /// * `full_path`: The full method path, e.g. `/package.Service/Method`.
+    /// * `kind`: The streaming kind of the method.
+    pub fn register_streaming_kind(full_path: impl Into<String>, kind: StreamingKind) {
+        register_streaming_kinds(vec![(full_path.into(), kind)]);
+    }
+
+    /// Registers the streaming kinds of all methods of an encoded `FileDescriptorSet`, as
+    /// generated by `tonic-prost-build` with `file_descriptor_set_path` and included with
+    /// `tonic::include_file_descriptor_set!`. Returns the number of registered methods.
+    ///
+    /// # Parameters
+    ///
+    /// * `file_descriptor_set`: The encoded `FileDescriptorSet`.
+    #[cfg(feature = "descriptors")]
+    pub fn register_file_descriptor_set(
+        file_descriptor_set: &[u8],
+    ) -> Result<usize, prost::DecodeError> {
+        use prost::Message;
+
+        let set = prost_types::FileDescriptorSet::decode(file_descriptor_set)?;
+        let mut kinds = Vec::new();
+        for file in &set.file {
+            for service in &file.service {
+                let service_name = match file.package() {
+                    "" => service.name().to_string(),
+                    package => format!("{package}.{}", service.name()),
+                };
+                for method in &service.method {
+                    kinds.push((
+                        format!("/{service_name}/{}", method.name()),
+                        StreamingKind::from_flags(
+                            method.client_streaming(),
+                            method.server_streaming(),
+                        ),
+                    ));
+                }
+            }
+        }
+        let registered = kinds.len();
+        register_streaming_kinds(kinds);
+        Ok(registered)
+    }
+}
+
+impl fmt::Display for GrpcMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.full_path)
+    }
+}
+
+/// Extracts the [GrpcMethod] stored by the wrappers, parsing the path if there is none.
+impl FromRequest for GrpcMethod {
+    fn from_request(req: &Request<Body>) -> Result<Self, Status> {
+        match req.grpc_method() {
+            Some(method) => Ok(method.clone()),
+            None => GrpcMethod::parse(req.uri().path()),
+        }
+    }
+}
+
+/// Gives access to the [GrpcMethod] stored in the request extensions.
+pub trait GrpcMethodExt {
+    /// Returns the called method, or `None` if the request did not go through
+    /// [crate::InterceptorFor] or [crate::MiddlewareFor].
+    fn grpc_method(&self) -> Option<&GrpcMethod>;
+}
+
+impl<B> GrpcMethodExt for Request<B> {
+    fn grpc_method(&self) -> Option<&GrpcMethod> {
+        self.extensions().get::<GrpcMethod>()
+    }
+}
+
+impl GrpcMethodExt for Parts {
+    fn grpc_method(&self) -> Option<&GrpcMethod> {
+        self.extensions.get::<GrpcMethod>()
+    }
+}
+
+/// Parses the method of `req` and stores it in the extensions, unless an outer wrapper already
+/// did.
+pub(crate) fn insert_grpc_method(req: &mut Request<Body>) -> Result<(), Status> {
+    if req.extensions().get::<GrpcMethod>().is_none() {
+        let method = GrpcMethod::parse(req.uri().path())?;
+        req.extensions_mut().insert(method);
+    }
+    Ok(())
+}
+
+/// Returns the full path of the called method, from the [GrpcMethod] stored by the wrappers or
+/// from the request path if there is none, e.g. when an interceptor is called directly.
+pub(crate) fn method_path<B>(req: &Request<B>) -> &str {
+    req.grpc_method()
+        .map_or_else(|| req.uri().path(), GrpcMethod::full_path)
+}
+
+/// Returns the full path of the called method of a request split into its parts, as
+/// [method_path].
+pub(crate) fn parts_method_path(parts: &Parts) -> &str {
+    parts
+        .grpc_method()
+        .map_or_else(|| parts.uri.path(), GrpcMethod::full_path)
+}
+
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
@@ -0,0 +1,257 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{Middleware, ServiceBound};
+use async_trait::async_trait;
+use tonic::body::Body;
+use tonic::codegen::http::{header, HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
+use tonic::codegen::Service;
+use tonic_web::GrpcWebLayer;
+use tower::Layer;
+
+const DEFAULT_ALLOWED_HEADERS: [&str; 4] =
+    ["x-grpc-web", "content-type", "x-user-agent", "grpc-timeout"];
+const DEFAULT_EXPOSED_HEADERS: [&str; 3] =
+    ["grpc-status", "grpc-message", "grpc-status-details-bin"];
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// `GrpcWebMiddleware` translates gRPC-Web requests from browser clients into gRPC requests
+/// and their responses back into gRPC-Web.
+///
+/// Both the binary (`application/grpc-web`, `application/grpc-web+proto`) and the base64
+/// encoded text (`application/grpc-web-text`) modes are supported; trailers are sent as a final
+/// length-prefixed frame of the response body, as gRPC-Web requires. Other HTTP/2 requests are
+/// passed through unchanged, so gRPC and gRPC-Web clients can share a server.
+///
+/// Browsers send gRPC-Web over HTTP/1.1, which must be enabled with
+/// `Server::builder().accept_http1(true)` unless TLS is used. Cross-origin clients also need
+/// [CorsMiddleware], which can be applied outside or inside of this middleware, as preflight
+/// requests are passed through. Interceptors applied inside of it see regular gRPC requests.
+/// Requires the `grpc-web` feature.
+///
+/// # Example
+///
+/// ```
+/// use tonic_middleware::{CorsMiddleware, GrpcWebMiddleware, MiddlewareLayer};
+///
+/// let cors = CorsMiddleware::new().allow_origin("https://estore.example.com");
+/// let cors_layer = MiddlewareLayer::new(cors);
+/// let grpc_web_layer = MiddlewareLayer::new(GrpcWebMiddleware::new());
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GrpcWebMiddleware;
+
+impl GrpcWebMiddleware {
+    /// Creates a new `GrpcWebMiddleware`.
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait]
+impl<S> Middleware<S> for GrpcWebMiddleware
+where
+    S: ServiceBound,
+    S::Future: Send,
+{
+    async fn call(&self, req: Request<Body>, mut service: S) -> Result<Response<Body>, S::Error> {
+        // Preflight requests are no gRPC-Web requests, let a `CorsMiddleware` applied inside
+        // answer them.
+        if is_preflight(&req) {
+            return service.call(req).await;
+        }
+        let mut grpc_web = GrpcWebLayer::new().layer(service);
+        grpc_web.call(req).await
+    }
+}
+
+/// `CorsMiddleware` answers CORS preflight requests and adds CORS headers to responses, so
+/// browser clients served from other origins can call the services with gRPC-Web.
+///
+/// Preflight requests are answered without reaching the service: with `204 No Content` if the
+/// origin is allowed, and `403 Forbidden` otherwise. Responses to allowed origins expose the
+/// `grpc-status`, `grpc-message` and `grpc-status-details-bin` headers, so clients can read the
+/// status of calls failing before any response message. Responses to other origins get no CORS
+/// headers, so browsers do not expose them.
+///
+/// Apply it to all services through a layer, or with [crate::MiddlewareFor] for per-service
+/// policies. Requires the `grpc-web` feature.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use tonic_middleware::CorsMiddleware;
+///
+/// let cors = CorsMiddleware::new()
+///     .allow_origin("https://estore.example.com")
+///     .allow_header("authorization")
+///     .allow_credentials(true)
+///     .max_age(Duration::from_secs(600));
+/// ```
+#[derive(Clone, Debug)]
+pub struct CorsMiddleware {
+    policy: Arc<CorsPolicy>,
+}
+
+#[derive(Clone, Debug)]
+struct CorsPolicy {
+    allowed_origins: Option<HashSet<String>>,
+    allowed_headers: Vec<String>,
+    exposed_headers: Vec<String>,
+    allow_credentials: bool,
+    max_age: Duration,
+}
+
+impl Default for CorsMiddleware {
+    fn default() -> Self {
+        CorsMiddleware {
+            policy: Arc::new(CorsPolicy {
+                allowed_origins: None,
+                allowed_headers: DEFAULT_ALLOWED_HEADERS.map(String::from).to_vec(),
+                exposed_headers: DEFAULT_EXPOSED_HEADERS.map(String::from).to_vec(),
+                allow_credentials: false,
+                max_age: DEFAULT_MAX_AGE,
+            }),
+        }
+    }
+}
+
+impl CorsMiddleware {
+    /// Creates a new `CorsMiddleware` allowing any origin.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows requests from `origin`, e.g. `https://estore.example.com`. Once an origin is
+    /// allowed, requests from origins that were not allowed are refused.
+    pub fn allow_origin(mut self, origin: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.policy)
+            .allowed_origins
+            .get_or_insert_with(HashSet::new)
+            .insert(origin.into());
+        self
+    }
+
+    /// Allows clients to send the request header `name`, e.g. `authorization`, in addition to
+    /// the headers used by gRPC-Web.
+    pub fn allow_header(mut self, name: impl AsRef<str>) -> Self {
+        Arc::make_mut(&mut self.policy)
+            .allowed_headers
+            .push(name.as_ref().to_ascii_lowercase());
+        self
+    }
+
+    /// Allows clients to read the response header `name`, in addition to the status headers.
+    pub fn expose_header(mut self, name: impl AsRef<str>) -> Self {
+        Arc::make_mut(&mut self.policy)
+            .exposed_headers
+            .push(name.as_ref().to_ascii_lowercase());
+        self
+    }
+
+    /// Allows or disallows requests with credentials, such as cookies. Disallowed by default.
+    ///
+    /// Credentials are only allowed for origins allowed with [CorsMiddleware::allow_origin];
+    /// without any, all cross-origin requests are refused, as any website could otherwise make
+    /// calls with the cookies of the user.
+    pub fn allow_credentials(mut self, allow_credentials: bool) -> Self {
+        Arc::make_mut(&mut self.policy).allow_credentials = allow_credentials;
+        self
+    }
+
+    /// Sets how long browsers may cache the result of a preflight request. Defaults to 24 hours.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        Arc::make_mut(&mut self.policy).max_age = max_age;
+        self
+    }
+}
+
+impl CorsPolicy {
+    /// Returns the `access-control-allow-origin` value for `origin`, if it is allowed.
+    fn allow_origin_value(&self, origin: &HeaderValue) -> Option<HeaderValue> {
+        match &self.allowed_origins {
+            None if !self.allow_credentials => Some(HeaderValue::from_static("*")),
+            // Credentials are never allowed for any origin
+            None => None,
+            Some(allowed) => {
+                let origin_str = origin.to_str().ok()?;
+                allowed.contains(origin_str).then(|| origin.clone())
+            }
+        }
+    }
+
+    fn add_common_headers(&self, headers: &mut HeaderMap, allow_origin: HeaderValue) {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+        if self.allow_credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+        headers.append(header::VARY, HeaderValue::from_static("origin"));
+    }
+
+    fn preflight_response(&self, allow_origin: Option<HeaderValue>) -> Response<Body> {
+        let Some(allow_origin) = allow_origin else {
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::FORBIDDEN;
+            return response;
+        };
+
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::NO_CONTENT;
+        let headers = response.headers_mut();
+        self.add_common_headers(headers, allow_origin);
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static("POST, OPTIONS"),
+        );
+        if let Ok(allowed_headers) = HeaderValue::from_str(&self.allowed_headers.join(", ")) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allowed_headers);
+        }
+        headers.insert(
+            header::ACCESS_CONTROL_MAX_AGE,
+            HeaderValue::from(self.max_age.as_secs()),
+        );
+        response
+    }
+}
+
+fn is_preflight(req: &Request<Body>) -> bool {
+    req.method() == Method::OPTIONS
+        && req
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+}
+
+#[async_trait]
+impl<S> Middleware<S> for CorsMiddleware
+where
+    S: ServiceBound,
+    S::Future: Send,
+{
+    async fn call(&self, req: Request<Body>, mut service: S) -> Result<Response<Body>, S::Error> {
+        let allow_origin = req
+            .headers()
+            .get(header::ORIGIN)
+            .and_then(|origin| self.policy.allow_origin_value(origin));
+
+        if is_preflight(&req) {
+            return Ok(self.policy.preflight_response(allow_origin));
+        }
+
+        let mut response = service.call(req).await?;
+        if let Some(allow_origin) = allow_origin {
+            let headers = response.headers_mut();
+            self.policy.add_common_headers(headers, allow_origin);
+            if let Ok(exposed_headers) =
+                HeaderValue::from_str(&self.policy.exposed_headers.join(", "))
+            {
+                headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, exposed_headers);
+            }
+        }
+        Ok(response)
+    }
+}
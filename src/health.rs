@@ -0,0 +1,267 @@
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::time::{Duration, Instant};
+
+use crate::{CallStats, Middleware, ServiceBound};
+use async_trait::async_trait;
+use tonic::body::Body;
+use tonic::codegen::http::{HeaderMap, Request, Response};
+use tonic::{Code, Status};
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
+
+/// Number of buckets the error rate window is divided into.
+const WINDOW_BUCKETS: u32 = 10;
+
+/// Reports a service as `NOT_SERVING` through a tonic-health [HealthReporter] while its error
+/// rate, or any of its custom signals such as the state of a circuit breaker, crosses a
+/// threshold, so that load balancers stop routing calls to the instance until it recovers.
+///
+/// The error rate is computed over a sliding window from the final status of every call,
+/// including calls rejected by interceptors applied inside of this middleware. The status is
+/// reevaluated periodically by a background task started by the first call, so the service is
+/// reported as `SERVING` again once the errors leave the window, even if no calls arrive.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use tonic_middleware::HealthReportingMiddleware;
+///
+/// let (reporter, health_service) = tonic_health::server::health_reporter();
+/// let health_reporting = HealthReportingMiddleware::new(reporter, "estore.OrderService")
+///     .error_rate_threshold(0.5)
+///     .min_calls(20)
+///     .window(Duration::from_secs(30));
+/// ```
+#[derive(Clone)]
+pub struct HealthReportingMiddleware {
+    config: Arc<Config>,
+    window: Arc<Mutex<ErrorWindow>>,
+    started: Arc<AtomicBool>,
+}
+
+type Signal = Arc<dyn Fn() -> bool + Send + Sync>;
+
+#[derive(Clone)]
+struct Config {
+    reporter: HealthReporter,
+    service_name: String,
+    error_rate_threshold: f64,
+    min_calls: u64,
+    window: Duration,
+    check_interval: Duration,
+    error_codes: HashSet<Code>,
+    signals: Vec<Signal>,
+}
+
+impl HealthReportingMiddleware {
+    /// Creates a new `HealthReportingMiddleware` with an error rate threshold of 50% over 30
+    /// seconds and at least 20 calls, counting `Unknown`, `DeadlineExceeded`, `Internal`,
+    /// `Unavailable` and `DataLoss` as errors.
+    ///
+    /// # Parameters
+    ///
+    /// * `reporter`: The reporter of the health service, from `tonic_health::server::health_reporter`.
+    /// * `service_name`: The service name to report the status of, as in `NamedService::NAME`,
+    ///   or `""` for the whole server.
+    pub fn new(reporter: HealthReporter, service_name: impl Into<String>) -> Self {
+        HealthReportingMiddleware {
+            config: Arc::new(Config {
+                reporter,
+                service_name: service_name.into(),
+                error_rate_threshold: 0.5,
+                min_calls: 20,
+                window: Duration::from_secs(30),
+                check_interval: Duration::from_secs(1),
+                error_codes: HashSet::from([
+                    Code::Unknown,
+                    Code::DeadlineExceeded,
+                    Code::Internal,
+                    Code::Unavailable,
+                    Code::DataLoss,
+                ]),
+                signals: Vec::new(),
+            }),
+            window: Arc::default(),
+            started: Arc::default(),
+        }
+    }
+
+    /// Sets the share of failed calls, between 0 and 1, from which the service is reported as
+    /// `NOT_SERVING`.
+    pub fn error_rate_threshold(mut self, error_rate_threshold: f64) -> Self {
+        Arc::make_mut(&mut self.config).error_rate_threshold = error_rate_threshold;
+        self
+    }
+
+    /// Sets the minimum number of calls in the window for the error rate to be considered.
+    pub fn min_calls(mut self, min_calls: u64) -> Self {
+        Arc::make_mut(&mut self.config).min_calls = min_calls;
+        self
+    }
+
+    /// Sets the duration of the sliding window the error rate is computed over.
+    pub fn window(mut self, window: Duration) -> Self {
+        Arc::make_mut(&mut self.config).window = window;
+        self
+    }
+
+    /// Sets how often the status is reevaluated and reported, every second by default.
+    pub fn check_interval(mut self, check_interval: Duration) -> Self {
+        Arc::make_mut(&mut self.config).check_interval = check_interval;
+        self
+    }
+
+    /// Replaces the status codes counted as errors.
+    pub fn error_codes(mut self, error_codes: impl IntoIterator<Item = Code>) -> Self {
+        Arc::make_mut(&mut self.config).error_codes = error_codes.into_iter().collect();
+        self
+    }
+
+    /// Reports the service as `NOT_SERVING` while `signal` returns `true`, e.g. while a circuit
+    /// breaker is open.
+    pub fn unhealthy_when<F>(mut self, signal: F) -> Self
+    where
+        F: Fn() -> bool + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.config)
+            .signals
+            .push(Arc::new(signal));
+        self
+    }
+
+    /// Starts the task reevaluating the status, which stops once all clones of the middleware
+    /// are dropped.
+    fn start(&self) {
+        if self.started.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let config = self.config.clone();
+        let window = Arc::downgrade(&self.window);
+        tokio::spawn(report_status(config, window));
+    }
+}
+
+async fn report_status(config: Arc<Config>, window: Weak<Mutex<ErrorWindow>>) {
+    let mut reported = None;
+    loop {
+        let Some(window) = window.upgrade() else {
+            return;
+        };
+        let (calls, errors) = window
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .counts(Instant::now(), config.window);
+        drop(window);
+
+        let error_rate_exceeded = calls > 0
+            && calls >= config.min_calls
+            && errors as f64 / calls as f64 >= config.error_rate_threshold;
+        let status = if error_rate_exceeded || config.signals.iter().any(|signal| signal()) {
+            ServingStatus::NotServing
+        } else {
+            ServingStatus::Serving
+        };
+        if reported != Some(status) {
+            if status == ServingStatus::NotServing {
+                tracing::warn!(
+                    service = config.service_name,
+                    calls,
+                    errors,
+                    "Reporting service as not serving"
+                );
+            }
+            config
+                .reporter
+                .set_service_status(&config.service_name, status)
+                .await;
+            reported = Some(status);
+        }
+        tokio::time::sleep(config.check_interval).await;
+    }
+}
+
+impl fmt::Debug for HealthReportingMiddleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HealthReportingMiddleware")
+            .field("service_name", &self.config.service_name)
+            .field("error_rate_threshold", &self.config.error_rate_threshold)
+            .field("min_calls", &self.config.min_calls)
+            .field("window", &self.config.window)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<S> Middleware<S> for HealthReportingMiddleware
+where
+    S: ServiceBound,
+    S::Future: Send,
+{
+    async fn call(&self, req: Request<Body>, mut service: S) -> Result<Response<Body>, S::Error> {
+        self.start();
+        service.call(req).await
+    }
+
+    fn observe_completion(&self) -> bool {
+        true
+    }
+
+    fn on_complete(&self, status: &Status, _trailers: Option<&HeaderMap>, _stats: &CallStats) {
+        let error = self.config.error_codes.contains(&status.code());
+        self.window
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record(Instant::now(), self.config.window, error);
+    }
+}
+
+/// Calls and errors over a sliding window, counted in buckets.
+#[derive(Default)]
+struct ErrorWindow {
+    buckets: VecDeque<Bucket>,
+}
+
+struct Bucket {
+    start: Instant,
+    calls: u64,
+    errors: u64,
+}
+
+impl ErrorWindow {
+    fn record(&mut self, now: Instant, window: Duration, error: bool) {
+        self.expire(now, window);
+        let bucket_len = window / WINDOW_BUCKETS;
+        match self.buckets.back_mut() {
+            Some(bucket) if now.duration_since(bucket.start) < bucket_len => {
+                bucket.calls += 1;
+                bucket.errors += u64::from(error);
+            }
+            _ => self.buckets.push_back(Bucket {
+                start: now,
+                calls: 1,
+                errors: u64::from(error),
+            }),
+        }
+    }
+
+    fn counts(&mut self, now: Instant, window: Duration) -> (u64, u64) {
+        self.expire(now, window);
+        self.buckets.iter().fold((0, 0), |(calls, errors), bucket| {
+            (calls + bucket.calls, errors + bucket.errors)
+        })
+    }
+
+    fn expire(&mut self, now: Instant, window: Duration) {
+        while self
+            .buckets
+            .front()
+            .is_some_and(|bucket| now.duration_since(bucket.start) >= window)
+        {
+            self.buckets.pop_front();
+        }
+    }
+}
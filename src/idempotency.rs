@@ -0,0 +1,522 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::buffered_request::{collect_body, ReplayBody};
+use crate::grpc_method::method_path;
+use crate::method_map::MethodMap;
+use crate::{MethodOptionsRegistry, Middleware, Principal, ServiceBound, Tenant};
+use async_trait::async_trait;
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+use tonic::body::Body;
+use tonic::codegen::http::{HeaderMap, HeaderValue, Request, Response, StatusCode};
+use tonic::{Code, Status};
+
+/// Metadata key carrying the idempotency key of a call.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+/// Response metadata key set to `true` on responses replayed from the store.
+pub const IDEMPOTENCY_REPLAYED_HEADER: &str = "idempotency-replayed";
+
+const MAX_KEY_LENGTH: usize = 255;
+const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const DEFAULT_MAX_BODY_SIZE: usize = 4 * 1024 * 1024;
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(25);
+
+/// Status codes of transient failures, whose responses are not stored so retries are executed.
+const RETRYABLE_CODES: [Code; 5] = [
+    Code::Cancelled,
+    Code::DeadlineExceeded,
+    Code::ResourceExhausted,
+    Code::Aborted,
+    Code::Unavailable,
+];
+
+/// Complete response of a call, stored by an [IdempotencyStore] to be replayed.
+#[derive(Clone, Debug)]
+pub struct StoredResponse {
+    /// HTTP status of the response.
+    pub status: StatusCode,
+    /// Response headers.
+    pub headers: HeaderMap,
+    /// Response body, the length-prefixed response message.
+    pub body: Bytes,
+    /// Response trailers carrying the final status, if any.
+    pub trailers: Option<HeaderMap>,
+}
+
+/// State of an idempotency key in an [IdempotencyStore].
+#[derive(Clone, Debug)]
+pub enum IdempotencyEntry {
+    /// A call with the key is in progress.
+    InFlight {
+        /// SHA-256 hash of the request body of the call.
+        fingerprint: [u8; 32],
+    },
+    /// A call with the key completed with `response`.
+    Completed {
+        /// SHA-256 hash of the request body of the call.
+        fingerprint: [u8; 32],
+        /// The response to replay.
+        response: Box<StoredResponse>,
+    },
+}
+
+impl IdempotencyEntry {
+    fn fingerprint(&self) -> &[u8; 32] {
+        match self {
+            IdempotencyEntry::InFlight { fingerprint } => fingerprint,
+            IdempotencyEntry::Completed { fingerprint, .. } => fingerprint,
+        }
+    }
+}
+
+/// Storage of idempotency keys and the responses of their calls.
+///
+/// Implementations backed by a shared database or cache let several server instances
+/// deduplicate calls; `begin` must then reserve keys atomically.
+#[async_trait]
+pub trait IdempotencyStore: Send + Sync + 'static {
+    /// Reserves `key` for a call with the request `fingerprint` if it is unknown, returning
+    /// `None`, or returns the existing entry otherwise.
+    ///
+    /// # Parameters
+    ///
+    /// * `key`: The scoped idempotency key.
+    /// * `fingerprint`: SHA-256 hash of the request body.
+    /// * `ttl`: How long the reservation, and later the stored response, are kept.
+    async fn begin(
+        &self,
+        key: &str,
+        fingerprint: [u8; 32],
+        ttl: Duration,
+    ) -> Result<Option<IdempotencyEntry>, Status>;
+
+    /// Returns the entry of `key`, if any.
+    async fn get(&self, key: &str) -> Result<Option<IdempotencyEntry>, Status>;
+
+    /// Stores the response of the call which reserved `key`.
+    async fn complete(
+        &self,
+        key: &str,
+        response: StoredResponse,
+        ttl: Duration,
+    ) -> Result<(), Status>;
+
+    /// Releases the reservation of `key` after a call which must not be replayed, so the key
+    /// can be retried.
+    async fn release(&self, key: &str) -> Result<(), Status>;
+}
+
+/// `InMemoryIdempotencyStore` keeps idempotency keys in memory, for single-instance servers.
+/// Expired entries are removed when their key is accessed, and all of them every 1024 new keys.
+#[derive(Clone, Default)]
+pub struct InMemoryIdempotencyStore {
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl InMemoryIdempotencyStore {
+    /// Creates a new empty `InMemoryIdempotencyStore`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Number of keys inserted between two sweeps of the expired entries.
+const SWEEP_INTERVAL: usize = 1024;
+
+/// Entries of an [InMemoryIdempotencyStore] with their expiry.
+#[derive(Default)]
+struct Entries {
+    entries: HashMap<String, (Instant, IdempotencyEntry)>,
+    inserted: usize,
+}
+
+impl Entries {
+    /// Returns the entry of `key`, removing it if it expired.
+    fn get_mut(&mut self, key: &str, now: Instant) -> Option<&mut (Instant, IdempotencyEntry)> {
+        if self
+            .entries
+            .get(key)
+            .is_some_and(|(expires, _)| *expires <= now)
+        {
+            self.entries.remove(key);
+        }
+        self.entries.get_mut(key)
+    }
+
+    /// Inserts the entry of `key`, removing all expired entries every [SWEEP_INTERVAL] keys.
+    fn insert(&mut self, key: &str, expires: Instant, entry: IdempotencyEntry, now: Instant) {
+        self.inserted += 1;
+        if self.inserted.is_multiple_of(SWEEP_INTERVAL) {
+            self.entries.retain(|_, (expires, _)| *expires > now);
+        }
+        self.entries.insert(key.to_string(), (expires, entry));
+    }
+}
+
+impl fmt::Debug for InMemoryIdempotencyStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InMemoryIdempotencyStore")
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for InMemoryIdempotencyStore {
+    async fn begin(
+        &self,
+        key: &str,
+        fingerprint: [u8; 32],
+        ttl: Duration,
+    ) -> Result<Option<IdempotencyEntry>, Status> {
+        let mut entries = self.entries();
+        let now = Instant::now();
+        if let Some((_, entry)) = entries.get_mut(key, now) {
+            return Ok(Some(entry.clone()));
+        }
+        entries.insert(
+            key,
+            now + ttl,
+            IdempotencyEntry::InFlight { fingerprint },
+            now,
+        );
+        Ok(None)
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<IdempotencyEntry>, Status> {
+        Ok(self
+            .entries()
+            .get_mut(key, Instant::now())
+            .map(|(_, entry)| entry.clone()))
+    }
+
+    async fn complete(
+        &self,
+        key: &str,
+        response: StoredResponse,
+        ttl: Duration,
+    ) -> Result<(), Status> {
+        let mut entries = self.entries();
+        let now = Instant::now();
+        if let Some((expires, entry)) = entries.get_mut(key, now) {
+            *expires = now + ttl;
+            *entry = IdempotencyEntry::Completed {
+                fingerprint: *entry.fingerprint(),
+                response: Box::new(response),
+            };
+        }
+        Ok(())
+    }
+
+    async fn release(&self, key: &str) -> Result<(), Status> {
+        self.entries().entries.remove(key);
+        Ok(())
+    }
+}
+
+/// `IdempotencyMiddleware` deduplicates retried calls of unary mutations, such as
+/// `CreateOrder`, carrying the same `idempotency-key` metadata value.
+///
+/// The first call with a key is executed and its complete response, headers, message and
+/// trailers, is stored; repeated calls get the stored response replayed, marked with
+/// `idempotency-replayed: true`, without reaching the service. Repeated calls while the first
+/// one is still in progress are rejected with `Status::aborted`, or wait for it to complete if
+/// [IdempotencyMiddleware::wait_for_in_flight] is set. Reusing a key with a different request
+/// message is rejected with `Status::invalid_argument`.
+///
+/// Keys are scoped by method, by the [crate::Tenant] and by the id of the authenticated
+/// [Principal], if any, so callers cannot replay each other's responses. Responses with
+/// transient failures (`Cancelled`, `DeadlineExceeded`, `ResourceExhausted`, `Aborted` and
+/// `Unavailable`) are not stored, so retries are executed, and keys of calls which fail or are
+/// cancelled before completing are released. Calls without a key are passed through.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use tonic_middleware::{IdempotencyMiddleware, InMemoryIdempotencyStore};
+///
+/// let idempotency = IdempotencyMiddleware::new(InMemoryIdempotencyStore::new())
+///     .apply_to("/estore.OrderService/CreateOrder")
+///     .ttl(Duration::from_secs(3600))
+///     .wait_for_in_flight(Duration::from_secs(5));
+/// ```
+#[derive(Clone)]
+pub struct IdempotencyMiddleware {
+    store: Arc<dyn IdempotencyStore>,
+    methods: MethodMap<bool>,
+    ttl: Duration,
+    wait_for_in_flight: Option<Duration>,
+    max_body_size: usize,
+}
+
+impl IdempotencyMiddleware {
+    /// Creates a new `IdempotencyMiddleware` storing keys in `store`, applied to no method until
+    /// [IdempotencyMiddleware::apply_to] is called.
+    pub fn new(store: impl IdempotencyStore) -> Self {
+        IdempotencyMiddleware {
+            store: Arc::new(store),
+            methods: MethodMap::default(),
+            ttl: DEFAULT_TTL,
+            wait_for_in_flight: None,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Deduplicates calls of the given method or service.
+    ///
+    /// # Parameters
+    ///
+    /// * `method`: A full method path (`/package.Service/Method`), a service wildcard
+    ///   (`/package.Service/*`) or `*` for all methods. Only unary methods are supported.
+    pub fn apply_to(mut self, method: impl Into<String>) -> Self {
+        *self.methods.entry(method) = true;
+        self
+    }
+
+    /// Deduplicates calls of the methods and services with `(idempotency.enabled) = true`, and
+    /// stops deduplicating those with `(idempotency.enabled) = false`.
+    pub fn method_options(mut self, options: &MethodOptionsRegistry) -> Self {
+        for (method, enabled) in options.methods_with("idempotency.enabled") {
+            if let Some(enabled) = enabled.as_bool() {
+                self.methods.insert(method, enabled);
+            }
+        }
+        self
+    }
+
+    /// Sets how long keys and their responses are kept, 24 hours by default.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Makes repeated calls wait up to `timeout` for an in-flight call with the same key to
+    /// complete and replay its response, instead of rejecting them with `Status::aborted`.
+    pub fn wait_for_in_flight(mut self, timeout: Duration) -> Self {
+        self.wait_for_in_flight = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum size of request and response bodies, 4 MiB by default.
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Returns the response to a repeated call, waiting for an in-flight call if configured.
+    async fn repeated(
+        &self,
+        key: &str,
+        fingerprint: [u8; 32],
+        mut entry: IdempotencyEntry,
+    ) -> Result<Response<Body>, Status> {
+        let deadline = self
+            .wait_for_in_flight
+            .map(|timeout| Instant::now() + timeout);
+        loop {
+            if *entry.fingerprint() != fingerprint {
+                return Err(Status::invalid_argument(
+                    "Idempotency key was already used with a different request",
+                ));
+            }
+            match entry {
+                IdempotencyEntry::Completed { response, .. } => return Ok(replay(*response)),
+                IdempotencyEntry::InFlight { .. } => {
+                    if deadline.is_none_or(|deadline| Instant::now() >= deadline) {
+                        return Err(Status::aborted(
+                            "A call with the same idempotency key is in progress",
+                        ));
+                    }
+                    tokio::time::sleep(WAIT_POLL_INTERVAL).await;
+                    match self.store.get(key).await? {
+                        Some(current) => entry = current,
+                        // The in-flight call was released, the caller may retry
+                        None => {
+                            return Err(Status::aborted(
+                                "A call with the same idempotency key failed, retry the call",
+                            ))
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for IdempotencyMiddleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IdempotencyMiddleware")
+            .field("methods", &self.methods)
+            .field("ttl", &self.ttl)
+            .field("wait_for_in_flight", &self.wait_for_in_flight)
+            .field("max_body_size", &self.max_body_size)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<S> Middleware<S> for IdempotencyMiddleware
+where
+    S: ServiceBound,
+    S::Future: Send,
+{
+    async fn call(&self, req: Request<Body>, mut service: S) -> Result<Response<Body>, S::Error> {
+        let method = method_path(&req).to_string();
+        if self.methods.get(&method) != Some(&true) {
+            return service.call(req).await;
+        }
+        let Some(key) = req.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+            return service.call(req).await;
+        };
+        let key = match key.to_str() {
+            Ok(key) if !key.is_empty() && key.len() <= MAX_KEY_LENGTH => key,
+            _ => {
+                return Ok(Status::invalid_argument(format!(
+                    "Idempotency key must be 1 to {} visible ASCII characters",
+                    MAX_KEY_LENGTH
+                ))
+                .into_http())
+            }
+        };
+        let principal = req
+            .extensions()
+            .get::<Principal>()
+            .map_or("", |p| p.id.as_str());
+        let tenant = req
+            .extensions()
+            .get::<Tenant>()
+            .map_or("", |t| t.id.as_str());
+        let scoped_key = scoped_key(&[&method, tenant, principal, key]);
+
+        let (parts, body) = req.into_parts();
+        let (body, request_trailers) = match collect_body(body, self.max_body_size).await {
+            Ok(collected) => collected,
+            Err(status) => return Ok(status.into_http()),
+        };
+        let fingerprint: [u8; 32] = Sha256::digest(&body).into();
+
+        match self.store.begin(&scoped_key, fingerprint, self.ttl).await {
+            Ok(None) => {}
+            Ok(Some(entry)) => {
+                return Ok(self
+                    .repeated(&scoped_key, fingerprint, entry)
+                    .await
+                    .unwrap_or_else(|status| status.into_http()))
+            }
+            Err(status) => return Ok(status.into_http()),
+        }
+        // Releases the key if the call fails or is cancelled before its response is stored
+        let reservation = Reservation {
+            store: self.store.clone(),
+            key: Some(scoped_key.clone()),
+        };
+
+        let response = service
+            .call(Request::from_parts(
+                parts,
+                Body::new(ReplayBody::new(body, request_trailers)),
+            ))
+            .await?;
+
+        let (parts, body) = response.into_parts();
+        let (body, trailers) = match collect_body(body, self.max_body_size).await {
+            Ok(collected) => collected,
+            Err(status) => {
+                reservation.release().await;
+                return Ok(status.into_http());
+            }
+        };
+        let status = trailers
+            .as_ref()
+            .and_then(Status::from_header_map)
+            .or_else(|| Status::from_header_map(&parts.headers));
+        let stored = StoredResponse {
+            status: parts.status,
+            headers: parts.headers.clone(),
+            body: body.clone(),
+            trailers: trailers.clone(),
+        };
+        if status.is_some_and(|status| RETRYABLE_CODES.contains(&status.code())) {
+            reservation.release().await;
+        } else if let Err(status) = self.store.complete(&scoped_key, stored, self.ttl).await {
+            tracing::error!(method = %method, "Failed to store idempotent response: {}", status);
+            reservation.release().await;
+        } else {
+            reservation.disarm();
+        }
+
+        Ok(Response::from_parts(
+            parts,
+            Body::new(ReplayBody::new(body, trailers)),
+        ))
+    }
+}
+
+/// Reservation of an idempotency key by an in-flight call, released when dropped unless the
+/// response of the call was stored.
+struct Reservation {
+    store: Arc<dyn IdempotencyStore>,
+    key: Option<String>,
+}
+
+impl Reservation {
+    /// Releases the key so the call can be retried.
+    async fn release(mut self) {
+        if let Some(key) = self.key.take() {
+            release(&*self.store, &key).await;
+        }
+    }
+
+    /// Keeps the key, whose response was stored.
+    fn disarm(mut self) {
+        self.key = None;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        // The call failed or its future was dropped, release the key in the background
+        if let Some(key) = self.key.take() {
+            let store = self.store.clone();
+            if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+                runtime.spawn(async move { release(&*store, &key).await });
+            }
+        }
+    }
+}
+
+/// Joins the components of a scoped key, each prefixed with its length so that different
+/// components cannot produce the same key.
+fn scoped_key(components: &[&str]) -> String {
+    let mut scoped = String::new();
+    for component in components {
+        write!(scoped, "{}:{}", component.len(), component)
+            .expect("Writing to a String cannot fail");
+    }
+    scoped
+}
+
+async fn release(store: &dyn IdempotencyStore, key: &str) {
+    if let Err(status) = store.release(key).await {
+        tracing::error!("Failed to release idempotency key: {}", status);
+    }
+}
+
+fn replay(stored: StoredResponse) -> Response<Body> {
+    let mut response = Response::new(Body::new(ReplayBody::new(stored.body, stored.trailers)));
+    *response.status_mut() = stored.status;
+    *response.headers_mut() = stored.headers;
+    response.headers_mut().insert(
+        IDEMPOTENCY_REPLAYED_HEADER,
+        HeaderValue::from_static("true"),
+    );
+    response
+}
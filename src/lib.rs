@@ -1,16 +1,114 @@
+pub use api_key::{
+    ApiKeyEntry, ApiKeyInterceptor, ApiKeyStore, HashedApiKey, InMemoryApiKeyStore, API_KEY_HEADER,
+};
+#[cfg(feature = "audit")]
+pub use audit::{verify_audit_log, AuditMiddleware, AuditSink, FileAuditSink};
+pub use authorization::{AuthorizationInterceptor, Decision, Policy, RolePolicy};
+pub use buffered_request::{BufferedRequest, GrpcFrame};
+pub use bypass::DEFAULT_BYPASS_SERVICES;
+pub use compression::{CompressionPolicyMiddleware, Encoding};
+pub use drain::{DrainHandle, DrainMiddleware};
+pub use dyn_stack::{
+    BoxInterceptor, BoxMiddleware, DynService, DynStack, DynStackService, StackEntry,
+};
+pub use extract::{
+    extract_fn, Authorization, Deadline, Extension, ExtractHandler, ExtractInterceptor,
+    FromRequest, Metadata, MetadataKey, MethodPath, PeerAddr,
+};
+pub use from_fn::{from_fn, interceptor_fn, middleware_fn, InterceptorFn, MiddlewareFn, Next};
+pub use grpc_method::{GrpcMethod, GrpcMethodExt, StreamingKind};
+#[cfg(feature = "grpc-web")]
+pub use grpc_web::{CorsMiddleware, GrpcWebMiddleware};
+#[cfg(feature = "health")]
+pub use health::HealthReportingMiddleware;
+pub use idempotency::{
+    IdempotencyEntry, IdempotencyMiddleware, IdempotencyStore, InMemoryIdempotencyStore,
+    StoredResponse, IDEMPOTENCY_KEY_HEADER, IDEMPOTENCY_REPLAYED_HEADER,
+};
+pub use message_size::MaxMessageSizeInterceptor;
+pub use metadata_policy::MetadataPolicy;
+pub use method_options::{MethodOptionsRegistry, OptionValue};
 pub use middleware::Middleware;
 pub use middleware::MiddlewareFor;
 pub use middleware::MiddlewareLayer;
+pub use observed_body::{CallStats, ObservedBody};
+#[cfg(feature = "mtls")]
+pub use peer_identity::{PeerIdentity, PeerIdentityInterceptor};
+pub use principal::Principal;
+pub use rate_limit::{RateLimit, RateLimitInterceptor};
+pub use redact_errors::RedactErrorsMiddleware;
+pub use reloadable::Reloadable;
 pub use request_interceptor::InterceptorFor;
 pub use request_interceptor::RequestInterceptor;
 pub use request_interceptor::RequestInterceptorLayer;
+pub use signature::{
+    InMemoryNonceStore, NonceStore, SignatureInterceptor, SigningMiddleware, SIGNATURE_HEADER,
+    SIGNATURE_NONCE_HEADER, SIGNATURE_TIMESTAMP_HEADER,
+};
+#[cfg(feature = "rich-errors")]
+pub use status_details::{StatusDetailsExt, StatusEnricherMiddleware};
+pub use tenant::{
+    InMemoryTenantResolver, Tenant, TenantMiddleware, TenantPolicy, TenantResolver,
+    TENANT_ATTRIBUTE, TENANT_HEADER,
+};
+#[cfg(feature = "macros")]
+pub use tonic_middleware_macros::{interceptor, middleware};
+#[cfg(feature = "rich-errors")]
+pub use tonic_types::ErrorDetails;
 
 use tonic::body::Body;
 use tonic::codegen::http::{Request, Response};
 use tonic::codegen::Service;
 
+mod api_key;
+#[cfg(feature = "audit")]
+mod audit;
+mod authorization;
+mod buffered_request;
+#[cfg(feature = "build")]
+pub mod build;
+mod bypass;
+mod compression;
+#[cfg(feature = "config")]
+pub mod config;
+mod drain;
+mod dyn_stack;
+mod extract;
+mod from_fn;
+mod grpc_frame;
+mod grpc_method;
+#[cfg(feature = "grpc-web")]
+mod grpc_web;
+#[cfg(feature = "health")]
+mod health;
+mod idempotency;
+mod message_size;
+mod metadata_policy;
+mod method_map;
+mod method_options;
 mod middleware;
+mod observed_body;
+#[cfg(feature = "mtls")]
+mod peer_identity;
+mod principal;
+mod rate_limit;
+mod redact_errors;
+mod reloadable;
 mod request_interceptor;
+mod signature;
+#[cfg(feature = "rich-errors")]
+mod status_details;
+mod tenant;
+
+/// Items used by the code generated by the `macros` feature, not part of the public API.
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub mod __private {
+    pub use async_trait::async_trait;
+    pub use tonic::body::Body;
+    pub use tonic::codegen::http::{Request, Response};
+    pub use tonic::Status;
+}
 
 pub trait ServiceBound:
     Service<Request<Body>, Response = Response<Body>> + Send + Clone + 'static
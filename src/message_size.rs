@@ -0,0 +1,293 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::grpc_frame::{parse_frame_header, GRPC_HEADER_SIZE};
+use crate::grpc_method::method_path;
+use crate::method_map::MethodMap;
+use crate::{MethodOptionsRegistry, RequestInterceptor, Tenant};
+use async_trait::async_trait;
+use bytes::Bytes;
+use http_body::Frame;
+use tonic::body::Body;
+use tonic::codegen::http::{header, Request};
+use tonic::Status;
+
+/// `MaxMessageSizeInterceptor` rejects requests whose gRPC messages exceed a configured size
+/// with `Status::resource_exhausted`.
+///
+/// Limits are checked against the length prefix of every gRPC frame as the request body is
+/// streamed to the service, so oversized messages are rejected before they are buffered or
+/// decoded. Optionally, the total number of bytes received during a single call can be capped as
+/// well, which is useful for client-streaming methods.
+///
+/// Tonic's own `max_decoding_message_size` applies to the whole server, whereas this interceptor
+/// allows limits to be set per method (`/package.Service/Method`) or per service
+/// (`/package.Service/*`). The quotas of the [crate::TenantPolicy] of the [Tenant] resolved by
+/// [crate::TenantMiddleware] take precedence over both.
+///
+/// # Example
+///
+/// ```
+/// use tonic_middleware::MaxMessageSizeInterceptor;
+///
+/// let interceptor = MaxMessageSizeInterceptor::new(4 * 1024 * 1024)
+///     .method_limit("/estore.ProductService/ListProducts", 16 * 1024)
+///     .max_call_bytes(64 * 1024 * 1024);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MaxMessageSizeInterceptor {
+    default_limits: SizeLimits,
+    method_limits: MethodMap<SizeLimits>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct SizeLimits {
+    max_message_size: Option<usize>,
+    max_call_bytes: Option<usize>,
+}
+
+impl SizeLimits {
+    fn or(self, other: SizeLimits) -> SizeLimits {
+        SizeLimits {
+            max_message_size: self.max_message_size.or(other.max_message_size),
+            max_call_bytes: self.max_call_bytes.or(other.max_call_bytes),
+        }
+    }
+
+    fn is_unlimited(&self) -> bool {
+        self.max_message_size.is_none() && self.max_call_bytes.is_none()
+    }
+}
+
+impl MaxMessageSizeInterceptor {
+    /// Creates a new `MaxMessageSizeInterceptor` with the default maximum message size in bytes.
+    ///
+    /// # Parameters
+    ///
+    /// * `max_message_size`: The maximum size of a single gRPC message for all methods that do
+    ///   not have a more specific limit.
+    pub fn new(max_message_size: usize) -> Self {
+        MaxMessageSizeInterceptor {
+            default_limits: SizeLimits {
+                max_message_size: Some(max_message_size),
+                max_call_bytes: None,
+            },
+            method_limits: MethodMap::default(),
+        }
+    }
+
+    /// Sets the maximum size of a single gRPC message for a method or service.
+    ///
+    /// # Parameters
+    ///
+    /// * `method`: Either a full method path (`/package.Service/Method`) or a service wildcard
+    ///   (`/package.Service/*`).
+    /// * `max_message_size`: The maximum message size in bytes.
+    pub fn method_limit(mut self, method: impl Into<String>, max_message_size: usize) -> Self {
+        self.method_limits.entry(method).max_message_size = Some(max_message_size);
+        self
+    }
+
+    /// Sets the maximum number of bytes a client may send during a single call, for all methods
+    /// that do not have a more specific limit.
+    pub fn max_call_bytes(mut self, max_call_bytes: usize) -> Self {
+        self.default_limits.max_call_bytes = Some(max_call_bytes);
+        self
+    }
+
+    /// Sets the maximum number of bytes a client may send during a single call to a method or
+    /// service.
+    ///
+    /// # Parameters
+    ///
+    /// * `method`: Either a full method path (`/package.Service/Method`) or a service wildcard
+    ///   (`/package.Service/*`).
+    /// * `max_call_bytes`: The maximum number of bytes, including gRPC frame headers.
+    pub fn method_max_call_bytes(
+        mut self,
+        method: impl Into<String>,
+        max_call_bytes: usize,
+    ) -> Self {
+        self.method_limits.entry(method).max_call_bytes = Some(max_call_bytes);
+        self
+    }
+
+    /// Applies the `limits.max_message_size` and `limits.max_call_bytes` options of the methods
+    /// and services, as [MaxMessageSizeInterceptor::method_limit] and
+    /// [MaxMessageSizeInterceptor::method_max_call_bytes].
+    pub fn method_options(mut self, options: &MethodOptionsRegistry) -> Self {
+        for (method, limit) in options.methods_with("limits.max_message_size") {
+            if let Some(limit) = limit.as_u64().and_then(|limit| usize::try_from(limit).ok()) {
+                self = self.method_limit(method, limit);
+            }
+        }
+        for (method, limit) in options.methods_with("limits.max_call_bytes") {
+            if let Some(limit) = limit.as_u64().and_then(|limit| usize::try_from(limit).ok()) {
+                self = self.method_max_call_bytes(method, limit);
+            }
+        }
+        self
+    }
+
+    fn limits_for(&self, path: &str) -> SizeLimits {
+        match self.method_limits.get(path) {
+            Some(limits) => limits.or(self.default_limits),
+            None => self.default_limits,
+        }
+    }
+}
+
+#[async_trait]
+impl RequestInterceptor for MaxMessageSizeInterceptor {
+    async fn intercept(&self, req: Request<Body>) -> Result<Request<Body>, Status> {
+        let path = method_path(&req).to_string();
+        let mut limits = self.limits_for(&path);
+        if let Some(tenant) = req.extensions().get::<Tenant>() {
+            limits = SizeLimits {
+                max_message_size: tenant.policy.max_message_size(),
+                max_call_bytes: tenant.policy.max_call_bytes(),
+            }
+            .or(limits);
+        }
+        if limits.is_unlimited() {
+            return Ok(req);
+        }
+
+        // Reject early when the client announces the body size upfront.
+        if let Some(max_call_bytes) = limits.max_call_bytes {
+            let content_length = req
+                .headers()
+                .get(header::CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<usize>().ok());
+            if let Some(content_length) = content_length {
+                if content_length > max_call_bytes {
+                    return Err(call_limit_exceeded(&path, max_call_bytes));
+                }
+            }
+        }
+
+        Ok(req.map(|body| {
+            Body::new(LimitedBody {
+                inner: body,
+                path,
+                limits,
+                frame: FrameState::default(),
+                received: 0,
+                failed: false,
+            })
+        }))
+    }
+}
+
+fn call_limit_exceeded(path: &str, max_call_bytes: usize) -> Status {
+    Status::resource_exhausted(format!(
+        "Request to {} exceeds the limit of {} bytes per call",
+        path, max_call_bytes
+    ))
+}
+
+/// Tracks the position within the stream of length-prefixed gRPC messages.
+#[derive(Default)]
+struct FrameState {
+    header: [u8; GRPC_HEADER_SIZE],
+    header_len: usize,
+    remaining_payload: usize,
+}
+
+impl FrameState {
+    /// Advances over `data`, calling `on_header` with the declared length of every message whose
+    /// header is completed by this chunk.
+    fn advance(
+        &mut self,
+        mut data: &[u8],
+        mut on_header: impl FnMut(usize) -> Result<(), Status>,
+    ) -> Result<(), Status> {
+        while !data.is_empty() {
+            if self.remaining_payload > 0 {
+                let n = self.remaining_payload.min(data.len());
+                self.remaining_payload -= n;
+                data = &data[n..];
+                continue;
+            }
+
+            let n = (GRPC_HEADER_SIZE - self.header_len).min(data.len());
+            self.header[self.header_len..self.header_len + n].copy_from_slice(&data[..n]);
+            self.header_len += n;
+            data = &data[n..];
+
+            if self.header_len == GRPC_HEADER_SIZE {
+                let (_, len) = parse_frame_header(&self.header);
+                on_header(len)?;
+                self.header_len = 0;
+                self.remaining_payload = len;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Request body wrapper that enforces [SizeLimits] while frames are streamed through it.
+struct LimitedBody {
+    inner: Body,
+    path: String,
+    limits: SizeLimits,
+    frame: FrameState,
+    received: usize,
+    failed: bool,
+}
+
+impl LimitedBody {
+    fn check(&mut self, data: &[u8]) -> Result<(), Status> {
+        self.received += data.len();
+        if let Some(max_call_bytes) = self.limits.max_call_bytes {
+            if self.received > max_call_bytes {
+                return Err(call_limit_exceeded(&self.path, max_call_bytes));
+            }
+        }
+
+        let path = &self.path;
+        let max_message_size = self.limits.max_message_size;
+        self.frame.advance(data, |len| match max_message_size {
+            Some(max) if len > max => Err(Status::resource_exhausted(format!(
+                "Message of {} bytes sent to {} exceeds the limit of {} bytes",
+                len, path, max
+            ))),
+            _ => Ok(()),
+        })
+    }
+}
+
+impl http_body::Body for LimitedBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if self.failed {
+            return Poll::Ready(None);
+        }
+        match Pin::new(&mut self.inner).poll_frame(cx) {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(data) = frame.data_ref() {
+                    if let Err(status) = self.check(data) {
+                        self.failed = true;
+                        return Poll::Ready(Some(Err(status)));
+                    }
+                }
+                Poll::Ready(Some(Ok(frame)))
+            }
+            other => other,
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.failed || self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::HashSet;
+
+use crate::grpc_method::method_path;
+use crate::method_map::MethodMap;
+use crate::RequestInterceptor;
+use async_trait::async_trait;
+use base64::alphabet;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+use regex::Regex;
+use tonic::body::Body;
+use tonic::codegen::http::header::Entry;
+use tonic::codegen::http::{HeaderName, HeaderValue, Request};
+use tonic::Status;
+
+/// gRPC allows binary metadata values to be sent both with and without base64 padding.
+const BINARY_METADATA: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// `MetadataPolicy` is a [RequestInterceptor] that validates and normalizes request metadata
+/// before it reaches the service.
+///
+/// The policy is declared upfront and applied to every request in the following order:
+///
+/// 1. Denylisted headers are stripped, so clients cannot smuggle headers that are meant to be set
+///    by other interceptors (e.g. `user_id` set by an authentication interceptor).
+/// 2. Values of headers marked for trimming have surrounding whitespace removed.
+/// 3. Header sizes are checked against the configured limits.
+/// 4. Binary (`-bin`) metadata values are checked to be valid base64.
+/// 5. Values are matched against the allowed value patterns.
+/// 6. Keys required for the called method, its service or all methods are checked to be present.
+///
+/// Any violation rejects the request with `Status::invalid_argument`.
+///
+/// # Example
+///
+/// ```
+/// use tonic_middleware::MetadataPolicy;
+///
+/// let policy = MetadataPolicy::new()
+///     .strip("user_id")
+///     .require("/estore.OrderService/*", "authorization")
+///     .allowed_values("x-request-id", r"^[0-9a-f-]{36}$")
+///     .unwrap()
+///     .max_header_size(8 * 1024);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MetadataPolicy {
+    required: MethodMap<Vec<String>>,
+    allowed_values: Vec<(String, Regex)>,
+    stripped: HashSet<String>,
+    trimmed: HashSet<String>,
+    max_header_size: Option<usize>,
+    max_total_size: Option<usize>,
+    validate_binary: bool,
+}
+
+impl MetadataPolicy {
+    /// Creates a new `MetadataPolicy` that only validates binary (`-bin`) metadata.
+    pub fn new() -> Self {
+        MetadataPolicy {
+            validate_binary: true,
+            ..Default::default()
+        }
+    }
+
+    /// Requires the metadata `key` to be present on calls to the given method or service.
+    ///
+    /// # Parameters
+    ///
+    /// * `method`: A full method path (`/package.Service/Method`), a service wildcard
+    ///   (`/package.Service/*`) or `*` for all methods.
+    /// * `key`: The required metadata key.
+    pub fn require(mut self, method: impl Into<String>, key: impl AsRef<str>) -> Self {
+        self.required
+            .entry(method)
+            .push(key.as_ref().to_ascii_lowercase());
+        self
+    }
+
+    /// Restricts values of the metadata `key` to those matching `pattern`.
+    ///
+    /// Returns an error if `pattern` is not a valid regular expression.
+    pub fn allowed_values(
+        mut self,
+        key: impl AsRef<str>,
+        pattern: &str,
+    ) -> Result<Self, regex::Error> {
+        let regex = Regex::new(pattern)?;
+        self.allowed_values
+            .push((key.as_ref().to_ascii_lowercase(), regex));
+        Ok(self)
+    }
+
+    /// Removes the metadata `key` from every request before it reaches the service.
+    pub fn strip(mut self, key: impl AsRef<str>) -> Self {
+        self.stripped.insert(key.as_ref().to_ascii_lowercase());
+        self
+    }
+
+    /// Removes leading and trailing whitespace from values of the metadata `key`.
+    pub fn trim(mut self, key: impl AsRef<str>) -> Self {
+        self.trimmed.insert(key.as_ref().to_ascii_lowercase());
+        self
+    }
+
+    /// Sets the maximum size in bytes of a single header, counting both its name and value.
+    pub fn max_header_size(mut self, max_header_size: usize) -> Self {
+        self.max_header_size = Some(max_header_size);
+        self
+    }
+
+    /// Sets the maximum combined size in bytes of all headers of a request.
+    pub fn max_total_size(mut self, max_total_size: usize) -> Self {
+        self.max_total_size = Some(max_total_size);
+        self
+    }
+
+    /// Enables or disables base64 validation of binary (`-bin`) metadata. Enabled by default.
+    pub fn validate_binary(mut self, validate_binary: bool) -> Self {
+        self.validate_binary = validate_binary;
+        self
+    }
+
+    fn normalize(&self, req: &mut Request<Body>) {
+        for key in &self.stripped {
+            req.headers_mut().remove(key.as_str());
+        }
+
+        for key in &self.trimmed {
+            let Ok(name) = HeaderName::from_bytes(key.as_bytes()) else {
+                continue;
+            };
+            if let Entry::Occupied(mut entry) = req.headers_mut().entry(name) {
+                for value in entry.iter_mut() {
+                    *value = trim_value(value);
+                }
+            }
+        }
+    }
+
+    fn validate(&self, req: &Request<Body>) -> Result<(), Status> {
+        let mut total_size = 0;
+        for (name, value) in req.headers() {
+            let size = name.as_str().len() + value.len();
+            total_size += size;
+            if let Some(max) = self.max_header_size {
+                if size > max {
+                    return Err(Status::invalid_argument(format!(
+                        "Metadata `{}` is {} bytes, exceeding the limit of {} bytes",
+                        name, size, max
+                    )));
+                }
+            }
+
+            if self.validate_binary
+                && name.as_str().ends_with("-bin")
+                && BINARY_METADATA.decode(value.as_bytes()).is_err()
+            {
+                return Err(Status::invalid_argument(format!(
+                    "Binary metadata `{}` is not valid base64",
+                    name
+                )));
+            }
+        }
+
+        if let Some(max) = self.max_total_size {
+            if total_size > max {
+                return Err(Status::invalid_argument(format!(
+                    "Metadata is {} bytes, exceeding the limit of {} bytes",
+                    total_size, max
+                )));
+            }
+        }
+
+        for (key, regex) in &self.allowed_values {
+            for value in req.headers().get_all(key.as_str()) {
+                let allowed = value.to_str().map(|v| regex.is_match(v)).unwrap_or(false);
+                if !allowed {
+                    return Err(Status::invalid_argument(format!(
+                        "Metadata `{}` does not match the allowed pattern `{}`",
+                        key,
+                        regex.as_str()
+                    )));
+                }
+            }
+        }
+
+        for keys in self.required.get_all(method_path(req)) {
+            for key in keys {
+                if !req.headers().contains_key(key.as_str()) {
+                    return Err(Status::invalid_argument(format!(
+                        "Missing required metadata `{}`",
+                        key
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn trim_value(value: &HeaderValue) -> HeaderValue {
+    let bytes = value.as_bytes();
+    let start = bytes
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    let end = bytes
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |i| i + 1);
+    HeaderValue::from_bytes(&bytes[start..end]).unwrap_or_else(|_| value.clone())
+}
+
+#[async_trait]
+impl RequestInterceptor for MetadataPolicy {
+    async fn intercept(&self, mut req: Request<Body>) -> Result<Request<Body>, Status> {
+        self.normalize(&mut req);
+        self.validate(&req)?;
+        Ok(req)
+    }
+}
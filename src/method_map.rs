@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+
+/// `MethodMap` stores per-method settings keyed by gRPC request path.
+///
+/// Keys are full method paths (`/package.Service/Method`), service wildcards
+/// (`/package.Service/*`) or `*` matching every method. Lookups prefer an exact method match over
+/// the service wildcard, and the service wildcard over `*`.
+#[derive(Clone, Debug)]
+pub(crate) struct MethodMap<T> {
+    entries: HashMap<String, T>,
+}
+
+impl<T> MethodMap<T> {
+    /// Returns a mutable reference to the value registered for exactly `pattern`, inserting the
+    /// default value first if there is none.
+    pub(crate) fn entry(&mut self, pattern: impl Into<String>) -> &mut T
+    where
+        T: Default,
+    {
+        self.entries.entry(pattern.into()).or_default()
+    }
+
+    /// Registers `value` for `pattern`, replacing the previous value, if any.
+    pub(crate) fn insert(&mut self, pattern: impl Into<String>, value: T) {
+        self.entries.insert(pattern.into(), value);
+    }
+
+    /// Returns the value registered for `path`, falling back to its service wildcard and then
+    /// to `*`.
+    pub(crate) fn get(&self, path: &str) -> Option<&T> {
+        if let Some(value) = self.entries.get(path) {
+            return Some(value);
+        }
+        if let Some((service, _method)) = path.rsplit_once('/') {
+            if let Some(value) = self.entries.get(&format!("{service}/*")) {
+                return Some(value);
+            }
+        }
+        self.entries.get("*")
+    }
+
+    /// Returns the values registered for `path`, its service wildcard and `*`, from the most to
+    /// the least specific.
+    pub(crate) fn get_all(&self, path: &str) -> Vec<&T> {
+        let service_wildcard = path
+            .rsplit_once('/')
+            .map(|(service, _method)| format!("{service}/*"));
+        [Some(path), service_wildcard.as_deref(), Some("*")]
+            .into_iter()
+            .flatten()
+            .filter_map(|pattern| self.entries.get(pattern))
+            .collect()
+    }
+
+    /// Returns the registered patterns with their values, in no particular order.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&str, &T)> {
+        self.entries
+            .iter()
+            .map(|(pattern, value)| (pattern.as_str(), value))
+    }
+}
+
+impl<T> Default for MethodMap<T> {
+    fn default() -> Self {
+        MethodMap {
+            entries: HashMap::new(),
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use crate::method_map::MethodMap;
+
+/// Value of a custom option of a method or service.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OptionValue {
+    /// A `bool` option.
+    Bool(bool),
+    /// A signed integer option.
+    Int(i64),
+    /// An unsigned integer option.
+    UInt(u64),
+    /// A `float` or `double` option.
+    Float(f64),
+    /// A `string` option, or the name of the value of an enum option.
+    String(String),
+    /// A `repeated` option.
+    List(Vec<OptionValue>),
+}
+
+impl OptionValue {
+    /// Returns the value of a `bool` option.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            OptionValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of an integer option, if it fits into an `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            OptionValue::Int(value) => Some(*value),
+            OptionValue::UInt(value) => i64::try_from(*value).ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a non-negative integer option.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            OptionValue::Int(value) => u64::try_from(*value).ok(),
+            OptionValue::UInt(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a numeric option.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            OptionValue::Int(value) => Some(*value as f64),
+            OptionValue::UInt(value) => Some(*value as f64),
+            OptionValue::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a `string` or enum option.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            OptionValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the string values of a `repeated string` option, or the single value of a
+    /// `string` option.
+    pub fn as_strings(&self) -> Vec<&str> {
+        match self {
+            OptionValue::String(value) => vec![value],
+            OptionValue::List(values) => values.iter().filter_map(OptionValue::as_str).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl From<bool> for OptionValue {
+    fn from(value: bool) -> Self {
+        OptionValue::Bool(value)
+    }
+}
+
+impl From<i64> for OptionValue {
+    fn from(value: i64) -> Self {
+        OptionValue::Int(value)
+    }
+}
+
+impl From<u64> for OptionValue {
+    fn from(value: u64) -> Self {
+        OptionValue::UInt(value)
+    }
+}
+
+impl From<f64> for OptionValue {
+    fn from(value: f64) -> Self {
+        OptionValue::Float(value)
+    }
+}
+
+impl From<&str> for OptionValue {
+    fn from(value: &str) -> Self {
+        OptionValue::String(value.to_string())
+    }
+}
+
+impl From<String> for OptionValue {
+    fn from(value: String) -> Self {
+        OptionValue::String(value)
+    }
+}
+
+impl<T: Into<OptionValue>> From<Vec<T>> for OptionValue {
+    fn from(values: Vec<T>) -> Self {
+        OptionValue::List(values.into_iter().map(Into::into).collect())
+    }
+}
+
+/// `MethodOptionsRegistry` maps methods to the custom options they are annotated with in their
+/// `.proto` files, e.g. `option (auth.required) = true;`.
+///
+/// It is usually generated from the descriptors of the services with
+/// `tonic_middleware::build::generate_method_options` in `build.rs`, and passed to
+/// the `method_options` builder methods of the built-in interceptors and middlewares, which read
+/// the options they document:
+///
+/// * `auth.required` and `auth.scopes`, read by [crate::ApiKeyInterceptor].
+/// * `auth.roles`, read by [crate::RolePolicy].
+/// * `limits.max_message_size` and `limits.max_call_bytes`, read by
+///   [crate::MaxMessageSizeInterceptor].
+/// * `ratelimit.rps` and `ratelimit.burst`, read by [crate::RateLimitInterceptor].
+/// * `idempotency.enabled`, read by [crate::IdempotencyMiddleware].
+///
+/// Other options are ignored by the built-ins and can be read by custom interceptors and
+/// middlewares with [MethodOptionsRegistry::get].
+///
+/// Options are named by the full name of their extension. Fields of message options are named
+/// by the extension name followed by the field name, e.g. `limits.rate_limit.rps` for
+/// `option (limits.rate_limit) = { rps: 10 };`. Options declared under other names are mapped
+/// to the names read by the built-ins with [MethodOptionsRegistry::rename]. Options set for a
+/// service wildcard apply to all its methods, unless a method overrides them.
+///
+/// # Example
+///
+/// ```
+/// use tonic_middleware::MethodOptionsRegistry;
+///
+/// let options = MethodOptionsRegistry::new()
+///     .set("/estore.OrderService/*", "auth.required", true)
+///     .set("/estore.OrderService/GetMyOrders", "limits.rate_limit.rps", 10u64)
+///     .rename("limits.rate_limit", "ratelimit");
+///
+/// let rps = options.get("/estore.OrderService/GetMyOrders", "ratelimit.rps");
+/// assert_eq!(rps.and_then(|rps| rps.as_u64()), Some(10));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MethodOptionsRegistry {
+    methods: MethodMap<HashMap<String, OptionValue>>,
+}
+
+impl MethodOptionsRegistry {
+    /// Creates a new empty `MethodOptionsRegistry`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets an option of a method or service.
+    ///
+    /// # Parameters
+    ///
+    /// * `method`: A full method path (`/package.Service/Method`), a service wildcard
+    ///   (`/package.Service/*`) or `*` for all methods.
+    /// * `name`: The full name of the option, e.g. `auth.required`.
+    /// * `value`: The value of the option.
+    pub fn set(
+        mut self,
+        method: impl Into<String>,
+        name: impl Into<String>,
+        value: impl Into<OptionValue>,
+    ) -> Self {
+        self.methods.entry(method).insert(name.into(), value.into());
+        self
+    }
+
+    /// Returns the value of the option `name` for the method `path`, falling back to the
+    /// options of its service.
+    pub fn get(&self, path: &str, name: &str) -> Option<&OptionValue> {
+        self.methods
+            .get_all(path)
+            .into_iter()
+            .find_map(|options| options.get(name))
+    }
+
+    /// Renames the options named `from`, or prefixed with `from` and a dot, e.g. to read the
+    /// options declared in the `estore.auth` proto package as the `auth` options of the
+    /// built-ins.
+    ///
+    /// # Parameters
+    ///
+    /// * `from`: The name or prefix of the options to rename, e.g. `estore.auth` or
+    ///   `estore.limits.rule`.
+    /// * `to`: The name or prefix replacing it, e.g. `auth` or `ratelimit`.
+    pub fn rename(self, from: &str, to: &str) -> Self {
+        let mut methods = MethodMap::default();
+        for (method, options) in self.methods.iter() {
+            let renamed = options
+                .iter()
+                .map(|(name, value)| {
+                    let name = match name.strip_prefix(from) {
+                        Some("") => to.to_string(),
+                        Some(rest) if rest.starts_with('.') => format!("{to}{rest}"),
+                        _ => name.clone(),
+                    };
+                    (name, value.clone())
+                })
+                .collect();
+            methods.insert(method, renamed);
+        }
+        MethodOptionsRegistry { methods }
+    }
+
+    /// Returns the methods and services setting the option `name`, with its value. Methods are
+    /// given as full method paths or service wildcards, as accepted by the built-in interceptors
+    /// and middlewares.
+    pub fn methods_with<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = (&'a str, &'a OptionValue)> + 'a {
+        self.methods
+            .iter()
+            .filter_map(move |(method, options)| Some((method, options.get(name)?)))
+    }
+}
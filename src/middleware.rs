@@ -1,13 +1,18 @@
 use std::task::{Context, Poll};
+use std::time::Instant;
 
-use crate::ServiceBound;
+use crate::bypass::Bypass;
+use crate::grpc_method::{insert_grpc_method, method_path};
+use crate::{CallStats, ObservedBody, ServiceBound};
 use async_trait::async_trait;
 use futures_util::future::BoxFuture;
 use tonic::body::Body;
+use tonic::codegen::http::HeaderMap;
 use tonic::codegen::http::Request;
 use tonic::codegen::http::Response;
 use tonic::codegen::Service;
 use tonic::server::NamedService;
+use tonic::Status;
 use tower::Layer;
 
 /// The `Middleware` trait defines a generic interface for middleware components
@@ -43,6 +48,31 @@ where
     /// A `Result` containing the response from the service or an error if one occurred
     /// during processing.
     async fn call(&self, req: Request<Body>, service: S) -> Result<Response<Body>, S::Error>;
+
+    /// Returns `true` if [Middleware::on_complete] should be called for this middleware.
+    ///
+    /// Observing the completion of calls requires wrapping every response body, so it must be
+    /// enabled explicitly.
+    fn observe_completion(&self) -> bool {
+        false
+    }
+
+    /// Called once the call is complete, i.e. after the last frame of the response was sent,
+    /// if [Middleware::observe_completion] returns `true`.
+    ///
+    /// Unlike the response returned by the service in [Middleware::call], which is available
+    /// before any response message is sent, this reports the real outcome and timing of both
+    /// unary and streaming calls.
+    ///
+    /// # Parameters
+    ///
+    /// * `status`: The final status of the call.
+    /// * `trailers`: The trailers carrying the status, or `None` if the status was sent in the
+    ///   response headers or the call failed or was cancelled.
+    /// * `stats`: Statistics of the call.
+    fn on_complete(&self, status: &Status, trailers: Option<&HeaderMap>, stats: &CallStats) {
+        let _ = (status, trailers, stats);
+    }
 }
 
 /// `MiddlewareFor` is a service wrapper that pairs a middleware with its target service.
@@ -59,6 +89,7 @@ where
 {
     pub inner: S,
     pub middleware: M,
+    pub(crate) bypass: Bypass,
 }
 
 impl<S, M> MiddlewareFor<S, M>
@@ -73,7 +104,11 @@ where
     /// * `inner`: The service that this middleware is wrapping.
     /// * `middleware`: The middleware that is being applied to the service.
     pub fn new(inner: S, middleware: M) -> Self {
-        MiddlewareFor { inner, middleware }
+        MiddlewareFor {
+            inner,
+            middleware,
+            bypass: Bypass::default(),
+        }
     }
 }
 
@@ -91,10 +126,31 @@ where
         self.inner.poll_ready(cx)
     }
 
-    fn call(&mut self, req: Request<Body>) -> Self::Future {
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
         let middleware = self.middleware.clone();
-        let inner = self.inner.clone();
-        Box::pin(async move { middleware.call(req, inner).await })
+        // Take the service driven to readiness by `poll_ready`, leaving a clone in its place
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        if self.bypass.matches(req.uri().path()) {
+            return Box::pin(async move { inner.call(req).await });
+        }
+        Box::pin(async move {
+            if let Err(status) = insert_grpc_method(&mut req) {
+                return Ok(status.into_http());
+            }
+            if !middleware.observe_completion() {
+                return middleware.call(req, inner).await;
+            }
+            let started = Instant::now();
+            let method = method_path(&req).to_string();
+            let response = middleware.call(req, inner).await?;
+            Ok(ObservedBody::observe(
+                response,
+                method,
+                started,
+                move |status, trailers, stats| middleware.on_complete(status, trailers, stats),
+            ))
+        })
     }
 }
 
@@ -108,9 +164,13 @@ where
 
 /// `MiddlewareLayer` provides a way to wrap services with a specific middleware using
 /// the tower `Layer` trait
+///
+/// Calls to the services in [crate::DEFAULT_BYPASS_SERVICES], i.e. health checks and reflection,
+/// and to the services added with [MiddlewareLayer::bypass] skip the middleware.
 #[derive(Clone)]
 pub struct MiddlewareLayer<M> {
     middleware: M,
+    bypass: Bypass,
 }
 
 impl<M> MiddlewareLayer<M> {
@@ -120,7 +180,27 @@ impl<M> MiddlewareLayer<M> {
     ///
     /// * `middleware`: The middleware to apply to services.
     pub fn new(middleware: M) -> Self {
-        MiddlewareLayer { middleware }
+        MiddlewareLayer {
+            middleware,
+            bypass: Bypass::defaults(),
+        }
+    }
+
+    /// Skips the middleware for calls to the given service.
+    ///
+    /// # Parameters
+    ///
+    /// * `service`: The fully qualified service name (`package.Service`), as in
+    ///   `NamedService::NAME`.
+    pub fn bypass(mut self, service: impl Into<String>) -> Self {
+        self.bypass.insert(service.into());
+        self
+    }
+
+    /// Applies the middleware to all services, including health checks and reflection.
+    pub fn no_bypass(mut self) -> Self {
+        self.bypass = Bypass::default();
+        self
     }
 }
 
@@ -132,6 +212,10 @@ where
     type Service = MiddlewareFor<S, M>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        MiddlewareFor::new(inner, self.middleware.clone())
+        MiddlewareFor {
+            inner,
+            middleware: self.middleware.clone(),
+            bypass: self.bypass.clone(),
+        }
     }
 }
@@ -0,0 +1,157 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use http_body::Frame;
+use tonic::body::Body;
+use tonic::codegen::http::{HeaderMap, Response};
+use tonic::Status;
+
+type OnComplete = Box<dyn FnOnce(&Status, Option<&HeaderMap>, &CallStats) + Send>;
+
+/// Statistics of a completed call, passed to [crate::Middleware::on_complete].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CallStats {
+    /// Path of the called method (`/package.Service/Method`).
+    pub method: String,
+    /// Time from the start of the call until the last frame of the response was sent.
+    pub duration: Duration,
+    /// Time from the start of the call until the first response message was sent, if any.
+    pub time_to_first_message: Option<Duration>,
+    /// Number of bytes of response messages sent, including gRPC frame headers.
+    pub response_bytes: u64,
+}
+
+/// `ObservedBody` wraps a response body to observe the final gRPC status of a call, which tonic
+/// sends in the HTTP/2 trailers after the last response message, or in the response headers
+/// when the call fails before any message.
+///
+/// The callback fires exactly once: when the trailers are sent, when the body fails, or when the
+/// body is dropped. A body dropped before the end of the response, e.g. because the client went
+/// away, completes with `Status::cancelled`.
+///
+/// Middlewares usually get it applied by overriding [crate::Middleware::on_complete], but it can
+/// also be used directly, e.g. from a plain tower service.
+pub struct ObservedBody {
+    inner: Body,
+    header_status: Option<Status>,
+    stats: CallStats,
+    started: Instant,
+    on_complete: Option<OnComplete>,
+}
+
+impl ObservedBody {
+    /// Wraps the body of `response`, calling `on_complete` with the final status, the trailers
+    /// carrying it, if any, and the call statistics once the response is complete.
+    ///
+    /// # Parameters
+    ///
+    /// * `response`: The response of the call.
+    /// * `method`: Path of the called method, reported in [CallStats::method].
+    /// * `started`: When the call started, used to measure [CallStats::duration].
+    /// * `on_complete`: Called once the call is complete.
+    pub fn observe<F>(
+        response: Response<Body>,
+        method: impl Into<String>,
+        started: Instant,
+        on_complete: F,
+    ) -> Response<Body>
+    where
+        F: FnOnce(&Status, Option<&HeaderMap>, &CallStats) + Send + 'static,
+    {
+        let header_status = Status::from_header_map(response.headers());
+        let method = method.into();
+        response.map(|inner| {
+            Body::new(ObservedBody {
+                inner,
+                header_status,
+                stats: CallStats {
+                    method,
+                    duration: Duration::ZERO,
+                    time_to_first_message: None,
+                    response_bytes: 0,
+                },
+                started,
+                on_complete: Some(Box::new(on_complete)),
+            })
+        })
+    }
+
+    fn complete(&mut self, status: &Status, trailers: Option<&HeaderMap>) {
+        if let Some(on_complete) = self.on_complete.take() {
+            self.stats.duration = self.started.elapsed();
+            on_complete(status, trailers, &self.stats);
+        }
+    }
+
+    /// Returns the status of a response that ended without trailers.
+    fn ended_status(&mut self) -> Status {
+        self.header_status
+            .take()
+            .unwrap_or_else(|| Status::unknown("Response ended without a status"))
+    }
+}
+
+impl http_body::Body for ObservedBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = &mut *self;
+        match Pin::new(&mut this.inner).poll_frame(cx) {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(data) = frame.data_ref() {
+                    if this.stats.time_to_first_message.is_none() {
+                        this.stats.time_to_first_message = Some(this.started.elapsed());
+                    }
+                    this.stats.response_bytes += data.len() as u64;
+                } else if let Some(trailers) = frame.trailers_ref() {
+                    let status = Status::from_header_map(trailers)
+                        .unwrap_or_else(|| Status::unknown("Trailers without a status"));
+                    this.complete(&status, Some(trailers));
+                }
+                Poll::Ready(Some(Ok(frame)))
+            }
+            Poll::Ready(Some(Err(status))) => {
+                this.complete(&status, None);
+                Poll::Ready(Some(Err(status)))
+            }
+            Poll::Ready(None) => {
+                if this.on_complete.is_some() {
+                    let status = this.ended_status();
+                    this.complete(&status, None);
+                }
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for ObservedBody {
+    fn drop(&mut self) {
+        if self.on_complete.is_none() {
+            return;
+        }
+        // The body may be dropped without being polled to its end once it reports the end of
+        // the stream, e.g. for responses carrying the status in the headers.
+        let status = if http_body::Body::is_end_stream(&self.inner) {
+            self.ended_status()
+        } else {
+            Status::cancelled("Call cancelled before completion")
+        };
+        self.complete(&status, None);
+    }
+}
@@ -0,0 +1,189 @@
+use std::sync::Arc;
+
+use crate::grpc_method::method_path;
+use crate::method_map::MethodMap;
+use crate::{Principal, RequestInterceptor};
+use async_trait::async_trait;
+use tonic::body::Body;
+use tonic::codegen::http::Request;
+use tonic::transport::server::{TcpConnectInfo, TlsConnectInfo};
+use tonic::transport::CertificateDer;
+use tonic::Status;
+use x509_parser::extensions::GeneralName;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+const SPIFFE_SCHEME: &str = "spiffe://";
+
+/// `PeerIdentity` describes the client of a mutually authenticated TLS connection, as presented
+/// by its certificate.
+///
+/// It is inserted into the request extensions by [PeerIdentityInterceptor].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerIdentity {
+    /// Subject distinguished name of the leaf certificate (RFC 4514 format).
+    pub subject: String,
+    /// Common name (CN) of the leaf certificate subject, if present.
+    pub common_name: Option<String>,
+    /// DNS subject alternative names of the leaf certificate.
+    pub dns_names: Vec<String>,
+    /// URI subject alternative names of the leaf certificate.
+    pub uris: Vec<String>,
+    /// SPIFFE ID of the peer, i.e. the first URI subject alternative name with the `spiffe`
+    /// scheme.
+    pub spiffe_id: Option<String>,
+    /// DER encoded certificate chain presented by the peer, leaf first.
+    pub chain: Arc<Vec<CertificateDer<'static>>>,
+}
+
+impl PeerIdentity {
+    /// Parses the identity from a DER encoded certificate chain, leaf first.
+    ///
+    /// Returns `None` if the chain is empty or the leaf certificate cannot be parsed.
+    pub fn from_chain(chain: Arc<Vec<CertificateDer<'static>>>) -> Option<Self> {
+        let leaf = chain.first()?;
+        let (_, cert) = X509Certificate::from_der(leaf.as_ref()).ok()?;
+
+        let subject = cert.subject().to_string();
+        let common_name = cert
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(String::from);
+
+        let mut dns_names = Vec::new();
+        let mut uris = Vec::new();
+        if let Ok(Some(san)) = cert.subject_alternative_name() {
+            for name in &san.value.general_names {
+                match name {
+                    GeneralName::DNSName(dns) => dns_names.push(dns.to_string()),
+                    GeneralName::URI(uri) => uris.push(uri.to_string()),
+                    _ => {}
+                }
+            }
+        }
+        let spiffe_id = uris
+            .iter()
+            .find(|uri| uri.starts_with(SPIFFE_SCHEME))
+            .cloned();
+
+        Some(PeerIdentity {
+            subject,
+            common_name,
+            dns_names,
+            uris,
+            spiffe_id,
+            chain,
+        })
+    }
+
+    /// Returns the most specific identifier of the peer: its SPIFFE ID, common name or subject,
+    /// in that order of preference.
+    pub fn id(&self) -> &str {
+        self.spiffe_id
+            .as_deref()
+            .or(self.common_name.as_deref())
+            .unwrap_or(&self.subject)
+    }
+
+    /// Returns `true` if the SPIFFE ID, any URI or DNS name, or the common name of the peer
+    /// matches `pattern`.
+    ///
+    /// A pattern ending with `*` matches any identifier starting with the preceding prefix,
+    /// e.g. `spiffe://example.org/ns/payments/*`.
+    pub fn matches(&self, pattern: &str) -> bool {
+        let matches = |id: &str| match pattern.strip_suffix('*') {
+            Some(prefix) => id.starts_with(prefix),
+            None => id == pattern,
+        };
+        self.uris.iter().any(|uri| matches(uri))
+            || self.dns_names.iter().any(|dns| matches(dns))
+            || self.common_name.as_deref().is_some_and(matches)
+    }
+}
+
+/// `PeerIdentityInterceptor` extracts the identity of the client from the certificate presented
+/// on a mutually authenticated TLS connection and inserts it into the request extensions as
+/// [PeerIdentity].
+///
+/// Peers can be restricted per method or service with an allowlist of identities (see
+/// [PeerIdentity::matches]); methods without an allowlist accept any peer with a valid
+/// certificate. Requests without a client certificate are rejected with
+/// `Status::unauthenticated`, and peers that are not allowed with `Status::permission_denied`.
+///
+/// The server must be configured with TLS and a client CA root, so that certificates are
+/// verified before they reach the interceptor. Requires the `mtls` feature.
+///
+/// # Example
+///
+/// ```
+/// use tonic_middleware::PeerIdentityInterceptor;
+///
+/// let interceptor = PeerIdentityInterceptor::new()
+///     .allow("/estore.OrderService/*", "spiffe://estore.internal/ns/checkout/*")
+///     .insert_principal(true);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct PeerIdentityInterceptor {
+    allowlist: MethodMap<Vec<String>>,
+    insert_principal: bool,
+}
+
+impl PeerIdentityInterceptor {
+    /// Creates a new `PeerIdentityInterceptor` accepting any peer with a valid certificate.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows peers matching `identity` to call the given method or service.
+    ///
+    /// # Parameters
+    ///
+    /// * `method`: A full method path (`/package.Service/Method`), a service wildcard
+    ///   (`/package.Service/*`) or `*` for all methods.
+    /// * `identity`: A SPIFFE ID, URI, DNS name or common name, optionally ending with `*` to
+    ///   match by prefix.
+    pub fn allow(mut self, method: impl Into<String>, identity: impl Into<String>) -> Self {
+        self.allowlist.entry(method).push(identity.into());
+        self
+    }
+
+    /// Also inserts a [Principal] identified by [PeerIdentity::id] into the request extensions,
+    /// unless an earlier interceptor already did, so the peer can be authorized by
+    /// [crate::AuthorizationInterceptor].
+    pub fn insert_principal(mut self, insert_principal: bool) -> Self {
+        self.insert_principal = insert_principal;
+        self
+    }
+}
+
+#[async_trait]
+impl RequestInterceptor for PeerIdentityInterceptor {
+    async fn intercept(&self, mut req: Request<Body>) -> Result<Request<Body>, Status> {
+        let identity = req
+            .extensions()
+            .get::<TlsConnectInfo<TcpConnectInfo>>()
+            .and_then(|info| info.peer_certs())
+            .and_then(PeerIdentity::from_chain)
+            .ok_or_else(|| Status::unauthenticated("Missing client certificate"))?;
+
+        let allowlists = self.allowlist.get_all(method_path(&req));
+        if !allowlists.is_empty()
+            && !allowlists
+                .iter()
+                .any(|allowed| allowed.iter().any(|pattern| identity.matches(pattern)))
+        {
+            return Err(Status::permission_denied(format!(
+                "Peer `{}` is not allowed to call {}",
+                identity.id(),
+                method_path(&req)
+            )));
+        }
+
+        if self.insert_principal && req.extensions().get::<Principal>().is_none() {
+            req.extensions_mut().insert(Principal::new(identity.id()));
+        }
+        req.extensions_mut().insert(identity);
+        Ok(req)
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::{HashMap, HashSet};
+
+/// `Principal` is the authenticated caller of a request.
+///
+/// Authentication interceptors insert it into the request extensions, where it can be read by
+/// subsequent interceptors, middlewares and by the service through
+/// `tonic::Request::extensions().get::<Principal>()`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Principal {
+    /// Identifier of the caller, e.g. user or client id.
+    pub id: String,
+    /// Scopes granted to the caller.
+    pub scopes: HashSet<String>,
+    /// Roles assigned to the caller.
+    pub roles: HashSet<String>,
+    /// Arbitrary attributes of the caller, e.g. department or tenant, used for attribute based
+    /// authorization.
+    pub attributes: HashMap<String, String>,
+}
+
+impl Principal {
+    /// Creates a new `Principal` with the given id and no scopes, roles or attributes.
+    pub fn new(id: impl Into<String>) -> Self {
+        Principal {
+            id: id.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Adds the given scopes to the principal.
+    pub fn with_scopes<I, T>(mut self, scopes: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.scopes.extend(scopes.into_iter().map(Into::into));
+        self
+    }
+
+    /// Returns `true` if the principal was granted `scope`.
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.contains(scope)
+    }
+
+    /// Adds the given roles to the principal.
+    pub fn with_roles<I, T>(mut self, roles: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.roles.extend(roles.into_iter().map(Into::into));
+        self
+    }
+
+    /// Returns `true` if the principal was assigned `role`.
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.contains(role)
+    }
+
+    /// Sets the attribute `key` of the principal.
+    pub fn with_attribute(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.attributes.insert(key.into(), value.into());
+        self
+    }
+
+    /// Returns the value of the attribute `key`, if set.
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes.get(key).map(String::as_str)
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::grpc_method::method_path;
+use crate::method_map::MethodMap;
+use crate::{MethodOptionsRegistry, Principal, RequestInterceptor, Tenant};
+use async_trait::async_trait;
+use tonic::body::Body;
+use tonic::codegen::http::Request;
+use tonic::metadata::MetadataValue;
+use tonic::Status;
+
+/// Metadata key telling gRPC clients when to retry a rejected call, in milliseconds.
+const RETRY_PUSHBACK_HEADER: &str = "grpc-retry-pushback-ms";
+
+/// Number of buckets created between two sweeps of the idle ones.
+const SWEEP_INTERVAL: usize = 1024;
+
+/// Maximum rate of calls: `rps` calls per second on average, with bursts of up to `burst` calls.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    rps: f64,
+    burst: u32,
+}
+
+impl RateLimit {
+    /// Creates a new `RateLimit`.
+    ///
+    /// # Parameters
+    ///
+    /// * `rps`: The average number of calls per second.
+    /// * `burst`: The number of calls allowed at once after a pause, at least 1.
+    pub fn new(rps: f64, burst: u32) -> Self {
+        RateLimit {
+            rps,
+            burst: burst.max(1),
+        }
+    }
+
+    /// Creates a new `RateLimit` of `rps` calls per second, allowing bursts of one second of
+    /// calls.
+    pub fn per_second(rps: f64) -> Self {
+        Self::new(rps, rps.ceil() as u32)
+    }
+
+    /// Returns the average number of calls per second.
+    pub fn rps(&self) -> f64 {
+        self.rps
+    }
+
+    /// Returns the number of calls allowed at once.
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+}
+
+/// `RateLimitInterceptor` rejects calls exceeding the rate limit of their method, or of their
+/// [Tenant], with `Status::resource_exhausted`.
+///
+/// Limits are token buckets set per method (`/package.Service/Method`) or per service
+/// (`/package.Service/*`), and calls are counted per method and per caller, i.e. per
+/// [Tenant] and authenticated [Principal], if any. The rate limit of the
+/// [crate::TenantPolicy] of the tenant resolved by [crate::TenantMiddleware] additionally
+/// applies to all calls of the tenant. A call is only counted if it is within all its limits.
+/// Rejected calls carry a `grpc-retry-pushback-ms` hint telling clients when the next call is
+/// allowed.
+///
+/// Limits are kept in memory, so each server instance enforces them on its own.
+///
+/// # Example
+///
+/// ```
+/// use tonic_middleware::{RateLimit, RateLimitInterceptor};
+///
+/// let rate_limit = RateLimitInterceptor::new()
+///     .method_limit("/estore.OrderService/*", RateLimit::per_second(100.0))
+///     .method_limit("/estore.OrderService/CreateOrder", RateLimit::new(1.0, 5));
+/// ```
+#[derive(Clone, Default)]
+pub struct RateLimitInterceptor {
+    limits: MethodMap<RateLimit>,
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl RateLimitInterceptor {
+    /// Creates a new `RateLimitInterceptor` limiting no method until
+    /// [RateLimitInterceptor::method_limit] is called. Rate limits of tenants always apply.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the rate limit of a method or service.
+    ///
+    /// # Parameters
+    ///
+    /// * `method`: A full method path (`/package.Service/Method`), a service wildcard
+    ///   (`/package.Service/*`) or `*` for all methods. Each method of a service gets its own
+    ///   limit.
+    /// * `limit`: The rate limit.
+    pub fn method_limit(mut self, method: impl Into<String>, limit: RateLimit) -> Self {
+        self.limits.insert(method, limit);
+        self
+    }
+
+    /// Applies the `ratelimit.rps` and `ratelimit.burst` options of the methods and services,
+    /// as [RateLimitInterceptor::method_limit]. Without `ratelimit.burst`, bursts of one second
+    /// of calls are allowed.
+    pub fn method_options(mut self, options: &MethodOptionsRegistry) -> Self {
+        for (method, rps) in options.methods_with("ratelimit.rps") {
+            let Some(rps) = rps.as_f64() else {
+                continue;
+            };
+            let limit = match options
+                .get(method, "ratelimit.burst")
+                .and_then(|burst| burst.as_u64())
+            {
+                Some(burst) => RateLimit::new(rps, u32::try_from(burst).unwrap_or(u32::MAX)),
+                None => RateLimit::per_second(rps),
+            };
+            self = self.method_limit(method, limit);
+        }
+        self
+    }
+}
+
+impl fmt::Debug for RateLimitInterceptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimitInterceptor")
+            .field("limits", &self.limits)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl RequestInterceptor for RateLimitInterceptor {
+    async fn intercept(&self, req: Request<Body>) -> Result<Request<Body>, Status> {
+        let path = method_path(&req);
+        let tenant = req.extensions().get::<Tenant>();
+        let tenant_id = tenant.map_or("", |t| t.id.as_str());
+        let principal = req
+            .extensions()
+            .get::<Principal>()
+            .map_or("", |p| p.id.as_str());
+
+        let mut limits = Vec::with_capacity(2);
+        if let Some(limit) = self.limits.get(path) {
+            let key = (
+                path.to_string(),
+                tenant_id.to_string(),
+                principal.to_string(),
+            );
+            limits.push((key, *limit));
+        }
+        // The limit of the tenant is counted over all its methods and principals
+        if let Some(limit) = tenant.and_then(|t| t.policy.rate_limit()) {
+            let key = (String::new(), tenant_id.to_string(), String::new());
+            limits.push((key, limit));
+        }
+        if limits.is_empty() {
+            return Ok(req);
+        }
+
+        let acquired = self
+            .buckets
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .acquire(limits, Instant::now());
+        match acquired {
+            Ok(()) => Ok(req),
+            Err(retry_after) => {
+                let mut status = Status::resource_exhausted(match tenant {
+                    Some(tenant) => format!("Rate limit of {} exceeded for {}", path, tenant.id),
+                    None => format!("Rate limit of {} exceeded", path),
+                });
+                let retry_after = u64::try_from(retry_after.as_millis()).unwrap_or(u64::MAX);
+                status
+                    .metadata_mut()
+                    .insert(RETRY_PUSHBACK_HEADER, MetadataValue::from(retry_after));
+                Err(status)
+            }
+        }
+    }
+}
+
+/// Key of a token bucket: method, tenant and principal. Tenant buckets have an empty method
+/// and principal.
+type BucketKey = (String, String, String);
+
+/// Token buckets, keyed by method, tenant and principal.
+#[derive(Default)]
+struct Buckets {
+    buckets: HashMap<BucketKey, Bucket>,
+    created: usize,
+}
+
+impl Buckets {
+    /// Takes a token from each bucket of `limits` if all have one, or returns how long until
+    /// they do.
+    fn acquire(
+        &mut self,
+        limits: Vec<(BucketKey, RateLimit)>,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let mut retry_after = Duration::ZERO;
+        for (key, limit) in &limits {
+            let bucket = self.bucket(key, *limit, now);
+            bucket.refill(*limit, now);
+            if bucket.tokens < 1.0 {
+                retry_after = retry_after.max(Duration::from_secs_f64(
+                    ((1.0 - bucket.tokens) / limit.rps).min(u32::MAX.into()),
+                ));
+            }
+        }
+        if !retry_after.is_zero() {
+            return Err(retry_after);
+        }
+        for (key, _) in &limits {
+            if let Some(bucket) = self.buckets.get_mut(key) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the bucket of `key`, creating a full one if needed.
+    fn bucket(&mut self, key: &BucketKey, limit: RateLimit, now: Instant) -> &mut Bucket {
+        if !self.buckets.contains_key(key) {
+            self.created += 1;
+            if self.created.is_multiple_of(SWEEP_INTERVAL) {
+                self.buckets.retain(|_, bucket| !bucket.is_full(now));
+            }
+        }
+        self.buckets.entry(key.clone()).or_insert_with(|| Bucket {
+            tokens: f64::from(limit.burst),
+            limit,
+            updated: now,
+        })
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    limit: RateLimit,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.rps).min(f64::from(limit.burst));
+        self.limit = limit;
+        self.updated = now;
+    }
+
+    /// Returns `true` if the bucket refilled completely, so dropping it changes nothing.
+    fn is_full(&self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens + elapsed * self.limit.rps >= f64::from(self.limit.burst)
+    }
+}
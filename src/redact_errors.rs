@@ -0,0 +1,238 @@
+use std::collections::HashSet;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use crate::grpc_method::method_path;
+use crate::{Middleware, ServiceBound};
+use async_trait::async_trait;
+use bytes::Bytes;
+use http_body::Frame;
+use tonic::body::Body;
+use tonic::codegen::http::{HeaderMap, Request, Response};
+use tonic::{Code, Status};
+
+const GRPC_MESSAGE: &str = "grpc-message";
+const GRPC_STATUS_DETAILS: &str = "grpc-status-details-bin";
+
+/// `RedactErrorsMiddleware` hides the messages of error statuses that may leak implementation
+/// details, such as database errors in `Status::internal`, from clients.
+///
+/// For every status with one of the configured codes, the message is replaced with a generic
+/// text followed by a random correlation id, and the original message is logged together with
+/// the correlation id, so reported errors can still be traced. Statuses are rewritten both in
+/// the response headers, for errors returned before any response message, and in the trailers
+/// at the end of the response body, for errors of streaming calls.
+///
+/// # Example
+///
+/// ```
+/// use tonic::Code;
+/// use tonic_middleware::{MiddlewareLayer, RedactErrorsMiddleware};
+///
+/// let redact = RedactErrorsMiddleware::new()
+///     .redact(Code::DataLoss)
+///     .message("Something went wrong")
+///     .strip_details(true);
+/// let layer = MiddlewareLayer::new(redact);
+/// ```
+#[derive(Clone, Debug)]
+pub struct RedactErrorsMiddleware {
+    redaction: Arc<Redaction>,
+}
+
+#[derive(Clone, Debug)]
+struct Redaction {
+    codes: HashSet<Code>,
+    message: String,
+    strip_details: bool,
+}
+
+impl Default for RedactErrorsMiddleware {
+    fn default() -> Self {
+        RedactErrorsMiddleware {
+            redaction: Arc::new(Redaction {
+                codes: HashSet::from([Code::Internal, Code::Unknown]),
+                message: "Internal error".to_string(),
+                strip_details: false,
+            }),
+        }
+    }
+}
+
+impl RedactErrorsMiddleware {
+    /// Creates a new `RedactErrorsMiddleware` redacting `Internal` and `Unknown` statuses.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also redacts statuses with `code`.
+    pub fn redact(mut self, code: Code) -> Self {
+        Arc::make_mut(&mut self.redaction).codes.insert(code);
+        self
+    }
+
+    /// Sets the codes of the statuses to redact, replacing the defaults.
+    pub fn codes(mut self, codes: impl IntoIterator<Item = Code>) -> Self {
+        Arc::make_mut(&mut self.redaction).codes = codes.into_iter().collect();
+        self
+    }
+
+    /// Sets the generic text replacing redacted messages. Defaults to `Internal error`.
+    pub fn message(mut self, message: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.redaction).message = message.into();
+        self
+    }
+
+    /// Enables or disables removal of `grpc-status-details-bin` from redacted statuses, as
+    /// details may leak the same information as the message. Disabled by default.
+    pub fn strip_details(mut self, strip_details: bool) -> Self {
+        Arc::make_mut(&mut self.redaction).strip_details = strip_details;
+        self
+    }
+}
+
+impl Redaction {
+    /// Logs the original message and returns the redacted message, if `status` must be redacted.
+    fn redacted_message(&self, status: &Status, method: &str) -> Option<String> {
+        if !self.codes.contains(&status.code()) {
+            return None;
+        }
+        let correlation_id = correlation_id();
+        tracing::error!(
+            %correlation_id,
+            method = %method,
+            code = ?status.code(),
+            message = %status.message(),
+            "Redacted error status"
+        );
+        Some(format!(
+            "{} (correlation id: {})",
+            self.message, correlation_id
+        ))
+    }
+
+    /// Rewrites the status carried by response headers or trailers in place, returning the
+    /// redacted message if it was redacted.
+    fn redact_headers(&self, headers: &mut HeaderMap, method: &str) -> Option<String> {
+        let status = Status::from_header_map(headers)?;
+        let message = self.redacted_message(&status, method)?;
+
+        // The original message is removed even if the redacted one cannot be encoded
+        headers.remove(GRPC_MESSAGE);
+        if self.strip_details {
+            headers.remove(GRPC_STATUS_DETAILS);
+        }
+        let mut redacted = HeaderMap::new();
+        if Status::new(status.code(), message.clone())
+            .add_header(&mut redacted)
+            .is_ok()
+        {
+            if let Some(value) = redacted.remove(GRPC_MESSAGE) {
+                headers.insert(GRPC_MESSAGE, value);
+            }
+        }
+        Some(message)
+    }
+
+    /// Returns the redacted version of a status returned as a body error.
+    fn redact_status(&self, status: Status, method: &str) -> Status {
+        match self.redacted_message(&status, method) {
+            Some(message) => self.with_message(status, message),
+            None => status,
+        }
+    }
+
+    /// Returns `status` with its message replaced by the redacted `message`.
+    fn with_message(&self, status: Status, message: String) -> Status {
+        if self.strip_details {
+            Status::with_metadata(status.code(), message, status.metadata().clone())
+        } else {
+            Status::with_details_and_metadata(
+                status.code(),
+                message,
+                Bytes::copy_from_slice(status.details()),
+                status.metadata().clone(),
+            )
+        }
+    }
+}
+
+fn correlation_id() -> String {
+    let mut bytes = [0u8; 8];
+    getrandom::getrandom(&mut bytes).expect("Failed to generate correlation id");
+    format!("{:016x}", u64::from_be_bytes(bytes))
+}
+
+#[async_trait]
+impl<S> Middleware<S> for RedactErrorsMiddleware
+where
+    S: ServiceBound,
+    S::Future: Send,
+{
+    async fn call(&self, req: Request<Body>, mut service: S) -> Result<Response<Body>, S::Error> {
+        let method = method_path(&req).to_string();
+        let mut response = service.call(req).await?;
+
+        let redacted = self
+            .redaction
+            .redact_headers(response.headers_mut(), &method);
+        if let Some(status) = response.extensions_mut().remove::<Status>() {
+            // The extension carries the status of the headers, it gets the same redacted message
+            let status = match redacted {
+                Some(message) => self.redaction.with_message(status, message),
+                None => self.redaction.redact_status(status, &method),
+            };
+            response.extensions_mut().insert(status);
+        }
+
+        let redaction = self.redaction.clone();
+        Ok(response.map(|body| {
+            Body::new(RedactedBody {
+                inner: body,
+                redaction,
+                method,
+            })
+        }))
+    }
+}
+
+/// Response body wrapper redacting the status sent in the trailers or as a body error.
+struct RedactedBody {
+    inner: Body,
+    redaction: Arc<Redaction>,
+    method: String,
+}
+
+impl http_body::Body for RedactedBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        match Pin::new(&mut self.inner).poll_frame(cx) {
+            Poll::Ready(Some(Ok(frame))) => match frame.into_trailers() {
+                Ok(mut trailers) => {
+                    self.redaction.redact_headers(&mut trailers, &self.method);
+                    Poll::Ready(Some(Ok(Frame::trailers(trailers))))
+                }
+                Err(frame) => Poll::Ready(Some(Ok(frame))),
+            },
+            Poll::Ready(Some(Err(status))) => {
+                let status = self.redaction.redact_status(status, &self.method);
+                Poll::Ready(Some(Err(status)))
+            }
+            other => other,
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
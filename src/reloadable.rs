@@ -0,0 +1,169 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::grpc_method::method_path;
+use crate::{Middleware, ObservedBody, RequestInterceptor, ServiceBound};
+use arc_swap::ArcSwap;
+use async_trait::async_trait;
+use tokio::task::JoinHandle;
+use tonic::body::Body;
+use tonic::codegen::http::{Request, Response};
+use tonic::Status;
+
+/// `Reloadable` holds a middleware or interceptor behind an atomically swappable pointer, so its
+/// configuration can be replaced while the server is running.
+///
+/// `Reloadable<M>` implements [Middleware] when `M` does, and [RequestInterceptor] when `M` does,
+/// so it can be used anywhere `M` could. Each request uses the value current at the time the
+/// request arrives: calls in flight finish with the old value, while subsequent calls pick up the
+/// new one.
+///
+/// Clones share the same pointer, so a clone kept aside can be used to [Reloadable::store] a new
+/// value or to [Reloadable::watch_file] for changes.
+///
+/// # Example
+///
+/// ```
+/// use tonic_middleware::{MaxMessageSizeInterceptor, Reloadable, RequestInterceptorLayer};
+///
+/// let interceptor = Reloadable::new(MaxMessageSizeInterceptor::new(1024));
+/// let layer = RequestInterceptorLayer::new(interceptor.clone());
+///
+/// // Later, e.g. on an admin request
+/// interceptor.store(MaxMessageSizeInterceptor::new(4 * 1024));
+/// ```
+pub struct Reloadable<M> {
+    current: Arc<ArcSwap<M>>,
+}
+
+impl<M> Clone for Reloadable<M> {
+    fn clone(&self) -> Self {
+        Reloadable {
+            current: self.current.clone(),
+        }
+    }
+}
+
+impl<M> Reloadable<M> {
+    /// Creates a new `Reloadable` with the given initial middleware or interceptor.
+    pub fn new(middleware: M) -> Self {
+        Reloadable {
+            current: Arc::new(ArcSwap::from_pointee(middleware)),
+        }
+    }
+
+    /// Returns the current middleware or interceptor.
+    pub fn load(&self) -> Arc<M> {
+        self.current.load_full()
+    }
+
+    /// Replaces the middleware or interceptor for subsequent requests.
+    pub fn store(&self, middleware: M) {
+        self.current.store(Arc::new(middleware));
+    }
+}
+
+impl<M> Reloadable<M>
+where
+    M: Send + Sync + 'static,
+{
+    /// Watches the file at `path` and, whenever it changes, rebuilds the middleware or
+    /// interceptor from its content with `build` and stores it.
+    ///
+    /// The file's modification time and length are checked every `interval`. If the file cannot
+    /// be read or `build` fails, the error is logged, the current value is kept and the file is
+    /// read again at the next check, e.g. once a partial write completed. Must be called
+    /// from within a tokio runtime; abort the returned handle to stop watching.
+    ///
+    /// # Parameters
+    ///
+    /// * `path`: The configuration file to watch.
+    /// * `interval`: How often to check the file for changes.
+    /// * `build`: Builds the middleware or interceptor from the file content.
+    pub fn watch_file<F, E>(
+        &self,
+        path: impl Into<PathBuf>,
+        interval: Duration,
+        build: F,
+    ) -> JoinHandle<()>
+    where
+        F: Fn(&str) -> Result<M, E> + Send + 'static,
+        E: Display,
+    {
+        let path = path.into();
+        let reloadable = self.clone();
+        tokio::spawn(async move {
+            let mut last_modified = modified(&path).await;
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let modified = modified(&path).await;
+                if modified.is_none() || modified == last_modified {
+                    continue;
+                }
+                let content = match tokio::fs::read_to_string(&path).await {
+                    Ok(content) => content,
+                    Err(e) => {
+                        tracing::warn!(path = %path.display(), error = %e, "Failed to read configuration file");
+                        continue;
+                    }
+                };
+                match build(&content) {
+                    Ok(middleware) => {
+                        reloadable.store(middleware);
+                        last_modified = modified;
+                        tracing::info!(path = %path.display(), "Reloaded configuration");
+                    }
+                    Err(e) => {
+                        tracing::warn!(path = %path.display(), error = %e, "Failed to reload configuration, keeping the current one");
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Returns the modification time and length of the file, used to detect changes.
+async fn modified(path: &PathBuf) -> Option<(SystemTime, u64)> {
+    let metadata = tokio::fs::metadata(path).await.ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[async_trait]
+impl<M> RequestInterceptor for Reloadable<M>
+where
+    M: RequestInterceptor + Send + Sync + 'static,
+{
+    async fn intercept(&self, req: Request<Body>) -> Result<Request<Body>, Status> {
+        let interceptor = self.load();
+        interceptor.intercept(req).await
+    }
+}
+
+#[async_trait]
+impl<S, M> Middleware<S> for Reloadable<M>
+where
+    S: ServiceBound,
+    M: Middleware<S> + Send + Sync + 'static,
+{
+    async fn call(&self, req: Request<Body>, service: S) -> Result<Response<Body>, S::Error> {
+        let middleware = self.load();
+        if !middleware.observe_completion() {
+            return middleware.call(req, service).await;
+        }
+        // The completion is reported to the value which handled the call, even if it was
+        // replaced in the meantime
+        let started = Instant::now();
+        let method = method_path(&req).to_string();
+        let response = middleware.call(req, service).await?;
+        Ok(ObservedBody::observe(
+            response,
+            method,
+            started,
+            move |status, trailers, stats| middleware.on_complete(status, trailers, stats),
+        ))
+    }
+}
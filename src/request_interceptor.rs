@@ -1,5 +1,7 @@
 use std::task::{Context, Poll};
 
+use crate::bypass::Bypass;
+use crate::grpc_method::insert_grpc_method;
 use crate::ServiceBound;
 use async_trait::async_trait;
 use futures_util::future::BoxFuture;
@@ -51,6 +53,7 @@ where
 {
     pub inner: S,
     pub interceptor: I,
+    pub(crate) bypass: Bypass,
 }
 
 impl<S, I> InterceptorFor<S, I>
@@ -64,7 +67,11 @@ where
     /// * `inner`: The service being wrapped.
     /// * `interceptor`: The interceptor that will preprocess the requests.
     pub fn new(inner: S, interceptor: I) -> Self {
-        InterceptorFor { inner, interceptor }
+        InterceptorFor {
+            inner,
+            interceptor,
+            bypass: Bypass::default(),
+        }
     }
 }
 
@@ -82,14 +89,23 @@ where
         self.inner.poll_ready(cx)
     }
 
-    fn call(&mut self, req: Request<Body>) -> Self::Future {
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
         let interceptor = self.interceptor.clone();
-        let mut inner = self.inner.clone();
+        // Take the service driven to readiness by `poll_ready`, leaving a clone in its place
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        if self.bypass.matches(req.uri().path()) {
+            return Box::pin(async move { inner.call(req).await });
+        }
         Box::pin(async move {
+            if let Err(status) = insert_grpc_method(&mut req) {
+                return Ok(status.into_http());
+            }
             match interceptor.intercept(req).await {
                 Ok(req) => inner.call(req).await,
                 Err(status) => {
-                    let response = status.into_http();
+                    let mut response = status.into_http();
+                    response.extensions_mut().insert(InterceptorRejection);
                     Ok(response)
                 }
             }
@@ -97,6 +113,11 @@ where
     }
 }
 
+/// Marks responses of requests rejected by an interceptor, as opposed to errors returned by the
+/// service.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct InterceptorRejection;
+
 impl<S, I> NamedService for InterceptorFor<S, I>
 where
     S: NamedService,
@@ -107,12 +128,16 @@ where
 
 /// `RequestInterceptorLayer` provides a way to wrap services with a specific interceptor using the tower `Layer` trait
 ///
+/// Calls to the services in [crate::DEFAULT_BYPASS_SERVICES], i.e. health checks and reflection,
+/// and to the services added with [RequestInterceptorLayer::bypass] skip the interceptor.
+///
 /// # Type Parameters
 ///
 /// * `I`: The `RequestInterceptor` implementation.
 #[derive(Clone)]
 pub struct RequestInterceptorLayer<I> {
     interceptor: I,
+    bypass: Bypass,
 }
 
 impl<I> RequestInterceptorLayer<I> {
@@ -122,7 +147,27 @@ impl<I> RequestInterceptorLayer<I> {
     ///
     /// * `interceptor`: The interceptor to apply to services.
     pub fn new(interceptor: I) -> Self {
-        RequestInterceptorLayer { interceptor }
+        RequestInterceptorLayer {
+            interceptor,
+            bypass: Bypass::defaults(),
+        }
+    }
+
+    /// Skips the interceptor for calls to the given service.
+    ///
+    /// # Parameters
+    ///
+    /// * `service`: The fully qualified service name (`package.Service`), as in
+    ///   `NamedService::NAME`.
+    pub fn bypass(mut self, service: impl Into<String>) -> Self {
+        self.bypass.insert(service.into());
+        self
+    }
+
+    /// Applies the interceptor to all services, including health checks and reflection.
+    pub fn no_bypass(mut self) -> Self {
+        self.bypass = Bypass::default();
+        self
     }
 }
 
@@ -133,6 +178,10 @@ where
     type Service = InterceptorFor<S, I>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        InterceptorFor::new(inner, self.interceptor.clone())
+        InterceptorFor {
+            inner,
+            interceptor: self.interceptor.clone(),
+            bypass: self.bypass.clone(),
+        }
     }
 }
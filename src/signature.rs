@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::buffered_request::BufferedRequest;
+use crate::grpc_method::method_path;
+use crate::{GrpcMethodExt, Middleware, RequestInterceptor, ServiceBound, StreamingKind};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+use tonic::body::Body;
+use tonic::codegen::http::{HeaderValue, Request, Response};
+use tonic::Status;
+
+/// Metadata key carrying the base64 encoded HMAC-SHA256 signature of a call.
+pub const SIGNATURE_HEADER: &str = "x-signature";
+/// Metadata key carrying the time a call was signed at, in seconds since the Unix epoch.
+pub const SIGNATURE_TIMESTAMP_HEADER: &str = "x-signature-timestamp";
+/// Metadata key carrying the random nonce of a signed call.
+pub const SIGNATURE_NONCE_HEADER: &str = "x-signature-nonce";
+
+const DEFAULT_MAX_SKEW: Duration = Duration::from_secs(5 * 60);
+const DEFAULT_MAX_BODY_SIZE: usize = 4 * 1024 * 1024;
+const MAX_NONCE_LENGTH: usize = 128;
+
+/// `NonceStore` remembers the nonces of verified calls, so that [SignatureInterceptor] rejects
+/// replayed calls.
+#[async_trait]
+pub trait NonceStore: Send + Sync + 'static {
+    /// Records `nonce` for `ttl`, returning `false` if it is already recorded.
+    ///
+    /// Returning a `Status` error rejects the call with that status, e.g. when the backing
+    /// storage is unavailable.
+    async fn insert(&self, nonce: &str, ttl: Duration) -> Result<bool, Status>;
+}
+
+/// `InMemoryNonceStore` keeps nonces in memory, for single-instance servers. An expired nonce is
+/// removed when it is reused, and all expired nonces every 1024 new nonces.
+#[derive(Clone, Default)]
+pub struct InMemoryNonceStore {
+    nonces: Arc<Mutex<Nonces>>,
+}
+
+/// Number of nonces inserted between two sweeps of the expired ones.
+const SWEEP_INTERVAL: usize = 1024;
+
+/// Nonces of an [InMemoryNonceStore] with their expiry.
+#[derive(Default)]
+struct Nonces {
+    nonces: HashMap<String, Instant>,
+    inserted: usize,
+}
+
+impl InMemoryNonceStore {
+    /// Creates a new empty `InMemoryNonceStore`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl fmt::Debug for InMemoryNonceStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InMemoryNonceStore").finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl NonceStore for InMemoryNonceStore {
+    async fn insert(&self, nonce: &str, ttl: Duration) -> Result<bool, Status> {
+        let mut nonces = self.nonces.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        if nonces
+            .nonces
+            .get(nonce)
+            .is_some_and(|expires| *expires > now)
+        {
+            return Ok(false);
+        }
+        nonces.inserted += 1;
+        if nonces.inserted.is_multiple_of(SWEEP_INTERVAL) {
+            nonces.nonces.retain(|_, expires| *expires > now);
+        }
+        nonces.nonces.insert(nonce.to_string(), now + ttl);
+        Ok(true)
+    }
+}
+
+/// `SignatureInterceptor` authenticates calls from internal callers signing them with a shared
+/// secret, e.g. with [SigningMiddleware].
+///
+/// Calls carry an HMAC-SHA256 signature in `x-signature`, computed over the method path, the
+/// timestamp from `x-signature-timestamp`, the nonce from `x-signature-nonce` and the request
+/// body. The interceptor buffers the body, up to a size limit, to verify the signature and
+/// compares it in constant time. Calls signed too long ago, or too far in the future, are
+/// rejected, and so are calls reusing the nonce of a verified call, which the [NonceStore]
+/// remembers for as long as their timestamp is accepted. All failures are rejected with
+/// `Status::unauthenticated`.
+///
+/// The body of client-streaming and bidirectional methods is not signed, as it cannot be
+/// buffered before the call proceeds. Methods are recognized as streaming once their descriptors
+/// are registered with [crate::GrpcMethod::register_streaming_kind] or
+/// `GrpcMethod::register_file_descriptor_set`, and treated as unary otherwise.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use tonic_middleware::{InMemoryNonceStore, SignatureInterceptor};
+///
+/// let interceptor = SignatureInterceptor::new(b"shared-secret", InMemoryNonceStore::new())
+///     .previous_secret(b"old-shared-secret")
+///     .max_skew(Duration::from_secs(60));
+/// ```
+pub struct SignatureInterceptor<N: NonceStore> {
+    secrets: Arc<Vec<Vec<u8>>>,
+    nonces: Arc<N>,
+    max_skew: Duration,
+    max_body_size: usize,
+}
+
+impl<N: NonceStore> Clone for SignatureInterceptor<N> {
+    fn clone(&self) -> Self {
+        SignatureInterceptor {
+            secrets: self.secrets.clone(),
+            nonces: self.nonces.clone(),
+            max_skew: self.max_skew,
+            max_body_size: self.max_body_size,
+        }
+    }
+}
+
+impl<N: NonceStore> SignatureInterceptor<N> {
+    /// Creates a new `SignatureInterceptor` accepting calls signed with `secret` within 5
+    /// minutes, with bodies up to 4 MiB.
+    ///
+    /// # Parameters
+    ///
+    /// * `secret`: The secret shared with the callers.
+    /// * `nonces`: The store remembering the nonces of verified calls.
+    pub fn new(secret: impl Into<Vec<u8>>, nonces: N) -> Self {
+        SignatureInterceptor {
+            secrets: Arc::new(vec![secret.into()]),
+            nonces: Arc::new(nonces),
+            max_skew: DEFAULT_MAX_SKEW,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Also accepts calls signed with `secret`, while callers switch to a new secret.
+    pub fn previous_secret(mut self, secret: impl Into<Vec<u8>>) -> Self {
+        Arc::make_mut(&mut self.secrets).push(secret.into());
+        self
+    }
+
+    /// Sets how far the signing time of a call may be from the time of the server.
+    pub fn max_skew(mut self, max_skew: Duration) -> Self {
+        self.max_skew = max_skew;
+        self
+    }
+
+    /// Sets the maximum size of request bodies, including gRPC frame headers. Larger calls are
+    /// rejected with `Status::resource_exhausted`.
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+}
+
+impl<N: NonceStore> fmt::Debug for SignatureInterceptor<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SignatureInterceptor")
+            .field("max_skew", &self.max_skew)
+            .field("max_body_size", &self.max_body_size)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<N: NonceStore> RequestInterceptor for SignatureInterceptor<N> {
+    async fn intercept(&self, req: Request<Body>) -> Result<Request<Body>, Status> {
+        let (signature, timestamp, nonce) = {
+            let header = |name: &str| {
+                req.headers()
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .ok_or_else(|| Status::unauthenticated("Missing request signature"))
+            };
+            let signature = STANDARD
+                .decode(header(SIGNATURE_HEADER)?)
+                .map_err(|_| Status::unauthenticated("Invalid request signature"))?;
+            let timestamp: u64 = header(SIGNATURE_TIMESTAMP_HEADER)?
+                .parse()
+                .map_err(|_| Status::unauthenticated("Invalid request signature"))?;
+            (
+                signature,
+                timestamp,
+                header(SIGNATURE_NONCE_HEADER)?.to_string(),
+            )
+        };
+        if nonce.is_empty() || nonce.len() > MAX_NONCE_LENGTH {
+            return Err(Status::unauthenticated("Invalid request signature"));
+        }
+        if unix_time().abs_diff(timestamp) > self.max_skew.as_secs() {
+            return Err(Status::unauthenticated("Request signature expired"));
+        }
+
+        let (req, body) = if signs_body(&req) {
+            let req = BufferedRequest::collect(req, self.max_body_size).await?;
+            let body = req.body().clone();
+            (req.into_request(), body)
+        } else {
+            (req, Default::default())
+        };
+        let valid = self.secrets.iter().any(|secret| {
+            sign(secret, method_path(&req), timestamp, &nonce, &body)
+                .ct_eq(&signature)
+                .into()
+        });
+        if !valid {
+            return Err(Status::unauthenticated("Invalid request signature"));
+        }
+
+        // Timestamps are accepted on both sides of the server time
+        if !self.nonces.insert(&nonce, self.max_skew * 2).await? {
+            return Err(Status::unauthenticated("Replayed request signature"));
+        }
+        Ok(req)
+    }
+}
+
+/// `SigningMiddleware` signs outgoing calls for a server verifying them with
+/// [SignatureInterceptor], when wrapping a client channel with [crate::MiddlewareFor].
+///
+/// # Example
+///
+/// ```
+/// use tonic::transport::Channel;
+/// use tonic_middleware::{MiddlewareFor, SigningMiddleware};
+///
+/// # fn build() {
+/// let channel = Channel::from_static("http://[::1]:50051").connect_lazy();
+/// let channel = MiddlewareFor::new(channel, SigningMiddleware::new(b"shared-secret"));
+/// // let client = OrderServiceClient::new(channel);
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SigningMiddleware {
+    secret: Arc<Vec<u8>>,
+    max_body_size: usize,
+}
+
+impl SigningMiddleware {
+    /// Creates a new `SigningMiddleware` signing calls with `secret`, with bodies up to 4 MiB.
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        SigningMiddleware {
+            secret: Arc::new(secret.into()),
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Sets the maximum size of request bodies, including gRPC frame headers. Larger calls fail
+    /// with `Status::resource_exhausted`.
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+}
+
+impl fmt::Debug for SigningMiddleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SigningMiddleware")
+            .field("max_body_size", &self.max_body_size)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<S> Middleware<S> for SigningMiddleware
+where
+    S: ServiceBound,
+    S::Future: Send,
+{
+    async fn call(&self, req: Request<Body>, mut service: S) -> Result<Response<Body>, S::Error> {
+        let (mut req, body) = if signs_body(&req) {
+            match BufferedRequest::collect(req, self.max_body_size).await {
+                Ok(req) => {
+                    let body = req.body().clone();
+                    (req.into_request(), body)
+                }
+                Err(status) => return Ok(status.into_http()),
+            }
+        } else {
+            (req, Default::default())
+        };
+
+        let timestamp = unix_time();
+        let mut nonce = [0u8; 16];
+        getrandom::getrandom(&mut nonce).expect("Failed to generate signature nonce");
+        let nonce = STANDARD.encode(nonce);
+        let signature = sign(&self.secret, method_path(&req), timestamp, &nonce, &body);
+
+        let headers = req.headers_mut();
+        for (name, value) in [
+            (SIGNATURE_HEADER, STANDARD.encode(signature)),
+            (SIGNATURE_TIMESTAMP_HEADER, timestamp.to_string()),
+            (SIGNATURE_NONCE_HEADER, nonce),
+        ] {
+            let value = HeaderValue::try_from(value).expect("Base64 and digits are valid");
+            headers.insert(name, value);
+        }
+        service.call(req).await
+    }
+}
+
+/// Returns `true` unless the client streams the body of the called method.
+fn signs_body(req: &Request<Body>) -> bool {
+    !matches!(
+        req.grpc_method().and_then(|method| method.streaming_kind()),
+        Some(StreamingKind::ClientStreaming | StreamingKind::Bidirectional)
+    )
+}
+
+/// Computes the HMAC-SHA256 of a call, over its length-prefixed path and nonce, its timestamp
+/// and its body.
+fn sign(secret: &[u8], path: &str, timestamp: u64, nonce: &str, body: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    for field in [path.as_bytes(), nonce.as_bytes()] {
+        mac.update(&(field.len() as u64).to_be_bytes());
+        mac.update(field);
+    }
+    mac.update(&timestamp.to_be_bytes());
+    mac.update(body);
+    mac.finalize().into_bytes().into()
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
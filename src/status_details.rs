@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::grpc_method::method_path;
+use crate::request_interceptor::InterceptorRejection;
+use crate::{Middleware, ServiceBound};
+use async_trait::async_trait;
+use tonic::body::Body;
+use tonic::codegen::http::{Request, Response};
+use tonic::{Code, Status};
+use tonic_types::{ErrorDetails, StatusExt};
+
+/// Extension methods attaching `google.rpc` rich error details to a [Status].
+///
+/// Details are encoded into the `grpc-status-details-bin` metadata, from which clients can read
+/// them with `tonic_types::StatusExt`. Each method keeps the code, message, metadata and details
+/// already attached to the status, so calls can be chained.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use tonic::Status;
+/// use tonic_middleware::StatusDetailsExt;
+///
+/// let status = Status::resource_exhausted("Too many requests")
+///     .with_quota_failure("client:42", "Limit of 100 requests per minute exceeded")
+///     .with_retry_info(Duration::from_secs(30));
+/// ```
+pub trait StatusDetailsExt {
+    /// Applies `update` to the rich error details of the status.
+    fn update_details(self, update: impl FnOnce(&mut ErrorDetails)) -> Status;
+
+    /// Sets the `ErrorInfo` details of the status.
+    ///
+    /// # Parameters
+    ///
+    /// * `reason`: The reason of the error, as an `UPPER_SNAKE_CASE` constant.
+    /// * `domain`: The logical grouping the reason belongs to, e.g. the service name.
+    /// * `metadata`: Additional structured details about the error.
+    fn with_error_info(
+        self,
+        reason: impl Into<String>,
+        domain: impl Into<String>,
+        metadata: HashMap<String, String>,
+    ) -> Status;
+
+    /// Sets the `RetryInfo` details of the status, telling clients how long to wait before
+    /// retrying.
+    fn with_retry_info(self, retry_delay: Duration) -> Status;
+
+    /// Adds a `QuotaFailure` violation to the status.
+    fn with_quota_failure(
+        self,
+        subject: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Status;
+
+    /// Adds a `BadRequest` field violation to the status.
+    fn with_bad_request(self, field: impl Into<String>, description: impl Into<String>) -> Status;
+}
+
+impl StatusDetailsExt for Status {
+    fn update_details(self, update: impl FnOnce(&mut ErrorDetails)) -> Status {
+        let mut details = self.get_error_details();
+        update(&mut details);
+        Status::with_error_details_and_metadata(
+            self.code(),
+            self.message(),
+            details,
+            self.metadata().clone(),
+        )
+    }
+
+    fn with_error_info(
+        self,
+        reason: impl Into<String>,
+        domain: impl Into<String>,
+        metadata: HashMap<String, String>,
+    ) -> Status {
+        self.update_details(|details| {
+            details.set_error_info(reason, domain, metadata);
+        })
+    }
+
+    fn with_retry_info(self, retry_delay: Duration) -> Status {
+        self.update_details(|details| {
+            details.set_retry_info(Some(retry_delay));
+        })
+    }
+
+    fn with_quota_failure(
+        self,
+        subject: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Status {
+        self.update_details(|details| {
+            details.add_quota_failure_violation(subject, description);
+        })
+    }
+
+    fn with_bad_request(self, field: impl Into<String>, description: impl Into<String>) -> Status {
+        self.update_details(|details| {
+            details.add_bad_request_violation(field, description);
+        })
+    }
+}
+
+type Enricher = Arc<dyn Fn(&Status, &mut ErrorDetails) + Send + Sync>;
+
+/// `StatusEnricherMiddleware` attaches `google.rpc` rich error details to error statuses
+/// returned by the services and interceptors it wraps.
+///
+/// Every enriched status gets an `ErrorInfo` with the status code as reason (e.g.
+/// `RESOURCE_EXHAUSTED`), the configured domain and the called method as `method` metadata,
+/// unless it already has one. `RetryInfo` is added for codes configured with
+/// [StatusEnricherMiddleware::retry_after], and custom details with
+/// [StatusEnricherMiddleware::enrich].
+///
+/// By default only statuses of requests rejected by a [crate::RequestInterceptor] are enriched;
+/// enable [StatusEnricherMiddleware::handler_errors] to enrich errors returned by the services as
+/// well. Only errors returned before any response message are enriched. The middleware must be
+/// applied outside of the interceptors whose rejections it enriches. Requires the `rich-errors`
+/// feature.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use tonic::Code;
+/// use tonic_middleware::{MiddlewareLayer, StatusEnricherMiddleware};
+///
+/// let enricher = StatusEnricherMiddleware::new("estore.example.com")
+///     .retry_after(Code::ResourceExhausted, Duration::from_secs(1))
+///     .enrich(Code::InvalidArgument, |status, details| {
+///         details.add_bad_request_violation("metadata", status.message());
+///     });
+/// let layer = MiddlewareLayer::new(enricher);
+/// ```
+#[derive(Clone)]
+pub struct StatusEnricherMiddleware {
+    domain: String,
+    retry_delays: HashMap<Code, Duration>,
+    enrichers: Vec<(Code, Enricher)>,
+    handler_errors: bool,
+}
+
+impl StatusEnricherMiddleware {
+    /// Creates a new `StatusEnricherMiddleware`.
+    ///
+    /// # Parameters
+    ///
+    /// * `domain`: The domain of the attached `ErrorInfo`, typically the name of the service.
+    pub fn new(domain: impl Into<String>) -> Self {
+        StatusEnricherMiddleware {
+            domain: domain.into(),
+            retry_delays: HashMap::new(),
+            enrichers: Vec::new(),
+            handler_errors: false,
+        }
+    }
+
+    /// Attaches `RetryInfo` with the given delay to statuses with `code`.
+    pub fn retry_after(mut self, code: Code, retry_delay: Duration) -> Self {
+        self.retry_delays.insert(code, retry_delay);
+        self
+    }
+
+    /// Calls `enrich` with statuses with `code`, so it can attach custom details.
+    pub fn enrich<F>(mut self, code: Code, enrich: F) -> Self
+    where
+        F: Fn(&Status, &mut ErrorDetails) + Send + Sync + 'static,
+    {
+        self.enrichers.push((code, Arc::new(enrich)));
+        self
+    }
+
+    /// Enables or disables enrichment of errors returned by the services. Disabled by default.
+    pub fn handler_errors(mut self, handler_errors: bool) -> Self {
+        self.handler_errors = handler_errors;
+        self
+    }
+
+    fn enriched(&self, status: &Status, method: &str) -> Status {
+        let mut details = status.get_error_details();
+        if details.error_info().is_none() {
+            details.set_error_info(
+                reason(status.code()),
+                self.domain.clone(),
+                HashMap::from([("method".to_string(), method.to_string())]),
+            );
+        }
+        if details.retry_info().is_none() {
+            if let Some(retry_delay) = self.retry_delays.get(&status.code()) {
+                details.set_retry_info(Some(*retry_delay));
+            }
+        }
+        for (_, enrich) in self
+            .enrichers
+            .iter()
+            .filter(|(code, _)| *code == status.code())
+        {
+            enrich(status, &mut details);
+        }
+        Status::with_error_details(status.code(), status.message(), details)
+    }
+}
+
+/// Converts the code to the `UPPER_SNAKE_CASE` name used by `google.rpc.Code`.
+fn reason(code: Code) -> String {
+    let mut reason = String::new();
+    for (i, c) in format!("{:?}", code).chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            reason.push('_');
+        }
+        reason.push(c.to_ascii_uppercase());
+    }
+    reason
+}
+
+#[async_trait]
+impl<S> Middleware<S> for StatusEnricherMiddleware
+where
+    S: ServiceBound,
+    S::Future: Send,
+{
+    async fn call(&self, req: Request<Body>, mut service: S) -> Result<Response<Body>, S::Error> {
+        let method = method_path(&req).to_string();
+        let mut response = service.call(req).await?;
+
+        let rejected = response
+            .extensions()
+            .get::<InterceptorRejection>()
+            .is_some();
+        if !rejected && !self.handler_errors {
+            return Ok(response);
+        }
+        let status = match Status::from_header_map(response.headers()) {
+            Some(status) if status.code() != Code::Ok => status,
+            _ => return Ok(response),
+        };
+
+        let enriched = self.enriched(&status, &method);
+        if enriched.add_header(response.headers_mut()).is_ok()
+            && response.extensions().get::<Status>().is_some()
+        {
+            response.extensions_mut().insert(enriched);
+        }
+        Ok(response)
+    }
+}
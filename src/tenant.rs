@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::grpc_method::method_path;
+use crate::method_map::MethodMap;
+use crate::{FromRequest, Middleware, OptionValue, Principal, RateLimit, ServiceBound};
+use async_trait::async_trait;
+use tonic::body::Body;
+use tonic::codegen::http::{Request, Response};
+use tonic::Status;
+
+/// Default metadata key carrying the tenant id.
+pub const TENANT_HEADER: &str = "x-tenant-id";
+/// Default [Principal] attribute carrying the tenant id, e.g. copied from a token claim.
+pub const TENANT_ATTRIBUTE: &str = "tenant";
+
+/// `TenantPolicy` holds the per-tenant settings read by the built-in interceptors and
+/// middlewares:
+///
+/// * The enabled methods, enforced by [TenantMiddleware].
+/// * The message size and call byte quotas, which take precedence over the limits of
+///   [crate::MaxMessageSizeInterceptor].
+/// * The rate limit of all calls of the tenant, enforced by [crate::RateLimitInterceptor]
+///   in addition to the limits of the methods.
+///
+/// Other settings can be attached as options, which are only read by custom interceptors and
+/// middlewares.
+///
+/// # Example
+///
+/// ```
+/// use tonic_middleware::{RateLimit, TenantPolicy};
+///
+/// let policy = TenantPolicy::new()
+///     .with_enabled_methods(["/estore.OrderService/*"])
+///     .with_disabled_method("/estore.OrderService/DeleteOrder")
+///     .with_max_message_size(64 * 1024)
+///     .with_rate_limit(RateLimit::per_second(10.0))
+///     .with_option("support.plan", "gold");
+///
+/// assert!(policy.is_method_enabled("/estore.OrderService/GetMyOrders"));
+/// assert!(!policy.is_method_enabled("/estore.OrderService/DeleteOrder"));
+/// assert!(!policy.is_method_enabled("/estore.ProductService/ListProducts"));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TenantPolicy {
+    methods: MethodMap<bool>,
+    max_message_size: Option<usize>,
+    max_call_bytes: Option<usize>,
+    rate_limit: Option<RateLimit>,
+    options: HashMap<String, OptionValue>,
+}
+
+impl TenantPolicy {
+    /// Creates a new `TenantPolicy` enabling all methods, without quotas or options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restricts the tenant to the given methods and services, and those enabled by other
+    /// calls.
+    ///
+    /// # Parameters
+    ///
+    /// * `methods`: Full method paths (`/package.Service/Method`) or service wildcards
+    ///   (`/package.Service/*`).
+    pub fn with_enabled_methods<I, T>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        if self.methods.get("*").is_none() {
+            self.methods.insert("*", false);
+        }
+        for method in methods {
+            self.methods.insert(method, true);
+        }
+        self
+    }
+
+    /// Disables a method or service for the tenant, taking precedence over less specific
+    /// enabled methods.
+    ///
+    /// # Parameters
+    ///
+    /// * `method`: A full method path (`/package.Service/Method`) or a service wildcard
+    ///   (`/package.Service/*`).
+    pub fn with_disabled_method(mut self, method: impl Into<String>) -> Self {
+        self.methods.insert(method, false);
+        self
+    }
+
+    /// Returns `true` if the tenant may call the method at `path`.
+    pub fn is_method_enabled(&self, path: &str) -> bool {
+        self.methods.get(path).copied().unwrap_or(true)
+    }
+
+    /// Sets the maximum size of a single gRPC message sent by the tenant.
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = Some(max_message_size);
+        self
+    }
+
+    /// Returns the maximum size of a single gRPC message sent by the tenant, if set.
+    pub fn max_message_size(&self) -> Option<usize> {
+        self.max_message_size
+    }
+
+    /// Sets the maximum number of bytes the tenant may send during a single call.
+    pub fn with_max_call_bytes(mut self, max_call_bytes: usize) -> Self {
+        self.max_call_bytes = Some(max_call_bytes);
+        self
+    }
+
+    /// Returns the maximum number of bytes the tenant may send during a single call, if set.
+    pub fn max_call_bytes(&self) -> Option<usize> {
+        self.max_call_bytes
+    }
+
+    /// Sets the rate limit of all calls of the tenant.
+    pub fn with_rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// Returns the rate limit of all calls of the tenant, if set.
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        self.rate_limit
+    }
+
+    /// Sets a custom option of the tenant, e.g. `support.plan`, read by custom interceptors
+    /// and middlewares.
+    pub fn with_option(mut self, name: impl Into<String>, value: impl Into<OptionValue>) -> Self {
+        self.options.insert(name.into(), value.into());
+        self
+    }
+
+    /// Returns the value of the custom option `name`, if set.
+    pub fn option(&self, name: &str) -> Option<&OptionValue> {
+        self.options.get(name)
+    }
+}
+
+/// `Tenant` is the tenant a request belongs to, inserted into the request extensions by
+/// [TenantMiddleware], where it can be read by subsequent interceptors, middlewares and by the
+/// service through `tonic::Request::extensions().get::<Tenant>()`. It is also an extractor.
+#[derive(Clone, Debug)]
+pub struct Tenant {
+    /// Identifier of the tenant.
+    pub id: String,
+    /// Policy of the tenant.
+    pub policy: Arc<TenantPolicy>,
+}
+
+impl Tenant {
+    /// Creates a new `Tenant` with the given id and the default policy, enabling all methods.
+    pub fn new(id: impl Into<String>) -> Self {
+        Tenant {
+            id: id.into(),
+            policy: Arc::default(),
+        }
+    }
+
+    /// Sets the policy of the tenant.
+    pub fn with_policy(mut self, policy: TenantPolicy) -> Self {
+        self.policy = Arc::new(policy);
+        self
+    }
+}
+
+impl FromRequest for Tenant {
+    fn from_request(req: &Request<Body>) -> Result<Self, Status> {
+        req.extensions()
+            .get::<Tenant>()
+            .cloned()
+            .ok_or_else(|| Status::internal("Missing tenant, is `TenantMiddleware` applied?"))
+    }
+}
+
+/// `TenantResolver` validates tenant ids and resolves them to their [Tenant].
+#[async_trait]
+pub trait TenantResolver: Send + Sync + 'static {
+    /// Returns the tenant `tenant_id`, or `None` if the tenant is unknown or disabled.
+    ///
+    /// Returning a `Status` error rejects the request with that status, e.g. when the backing
+    /// storage is unavailable.
+    async fn resolve(&self, tenant_id: &str) -> Result<Option<Tenant>, Status>;
+}
+
+/// `InMemoryTenantResolver` is a [TenantResolver] over a fixed set of tenants.
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryTenantResolver {
+    tenants: HashMap<String, Tenant>,
+}
+
+impl InMemoryTenantResolver {
+    /// Creates a new empty `InMemoryTenantResolver`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `tenant`, replacing the tenant with the same id, if any.
+    pub fn insert(&mut self, tenant: Tenant) {
+        self.tenants.insert(tenant.id.clone(), tenant);
+    }
+}
+
+#[async_trait]
+impl TenantResolver for InMemoryTenantResolver {
+    async fn resolve(&self, tenant_id: &str) -> Result<Option<Tenant>, Status> {
+        Ok(self.tenants.get(tenant_id).cloned())
+    }
+}
+
+/// `TenantMiddleware` resolves the tenant of every request and inserts it into the request
+/// extensions as [Tenant], isolating tenants from each other.
+///
+/// The tenant id is read from the `x-tenant-id` metadata, or from the `tenant` attribute of the
+/// [Principal] inserted by an authentication interceptor, e.g. from a token claim. When both are
+/// present they must match, so that callers cannot act on behalf of another tenant. The id is
+/// validated through a [TenantResolver].
+///
+/// Requests without a tenant are rejected with `Status::invalid_argument`, requests for an unknown
+/// tenant, for another tenant than the one of the principal, or for a method not enabled by the
+/// [TenantPolicy] with `Status::permission_denied`.
+///
+/// Built-ins applied after this middleware read the tenant: [crate::MaxMessageSizeInterceptor]
+/// applies its quotas, [crate::RateLimitInterceptor] its rate limit, and
+/// [crate::IdempotencyMiddleware] scopes its keys by tenant.
+///
+/// # Example
+///
+/// ```
+/// use tonic_middleware::{InMemoryTenantResolver, Tenant, TenantMiddleware, TenantPolicy};
+///
+/// let mut tenants = InMemoryTenantResolver::new();
+/// tenants.insert(Tenant::new("acme").with_policy(TenantPolicy::new().with_max_call_bytes(1 << 20)));
+///
+/// let tenant_middleware = TenantMiddleware::new(tenants)
+///     .allow_missing("/estore.ProductService/ListProducts");
+/// ```
+pub struct TenantMiddleware<R: TenantResolver> {
+    resolver: Arc<R>,
+    header: Option<String>,
+    principal_attribute: Option<String>,
+    optional: MethodMap<bool>,
+}
+
+impl<R: TenantResolver> Clone for TenantMiddleware<R> {
+    fn clone(&self) -> Self {
+        TenantMiddleware {
+            resolver: self.resolver.clone(),
+            header: self.header.clone(),
+            principal_attribute: self.principal_attribute.clone(),
+            optional: self.optional.clone(),
+        }
+    }
+}
+
+impl<R: TenantResolver> TenantMiddleware<R> {
+    /// Creates a new `TenantMiddleware` validating tenants through the given resolver.
+    pub fn new(resolver: R) -> Self {
+        TenantMiddleware {
+            resolver: Arc::new(resolver),
+            header: Some(TENANT_HEADER.to_string()),
+            principal_attribute: Some(TENANT_ATTRIBUTE.to_string()),
+            optional: MethodMap::default(),
+        }
+    }
+
+    /// Reads the tenant id from the given metadata key instead of `x-tenant-id`, or not from
+    /// metadata if `None`.
+    pub fn header(mut self, header: Option<&str>) -> Self {
+        self.header = header.map(str::to_ascii_lowercase);
+        self
+    }
+
+    /// Reads the tenant id from the given [Principal] attribute instead of `tenant`, or not from
+    /// the principal if `None`.
+    pub fn principal_attribute(mut self, attribute: Option<&str>) -> Self {
+        self.principal_attribute = attribute.map(str::to_string);
+        self
+    }
+
+    /// Lets requests to the given method or service without a tenant id through, without
+    /// [Tenant]. Requests with a tenant id are still validated.
+    ///
+    /// # Parameters
+    ///
+    /// * `method`: A full method path (`/package.Service/Method`), a service wildcard
+    ///   (`/package.Service/*`) or `*` for all methods.
+    pub fn allow_missing(mut self, method: impl Into<String>) -> Self {
+        self.optional.insert(method, true);
+        self
+    }
+
+    /// Returns the tenant id of `req`, `None` if it has none and may proceed without.
+    fn tenant_id(&self, req: &Request<Body>) -> Result<Option<String>, Status> {
+        let from_header = match &self.header {
+            Some(header) => match req.headers().get(header.as_str()).map(|v| v.to_str()) {
+                Some(Ok(tenant_id)) => Some(tenant_id),
+                Some(Err(_)) => return Err(Status::invalid_argument("Invalid tenant id")),
+                None => None,
+            },
+            None => None,
+        };
+        let from_principal = self.principal_attribute.as_deref().and_then(|attribute| {
+            req.extensions()
+                .get::<Principal>()
+                .and_then(|principal| principal.attribute(attribute))
+        });
+        let tenant_id = match (from_header, from_principal) {
+            (Some(requested), Some(own)) if requested != own => {
+                return Err(Status::permission_denied(
+                    "Principal does not belong to the requested tenant",
+                ))
+            }
+            (_, Some(tenant_id)) | (Some(tenant_id), None) => tenant_id,
+            (None, None) if self.optional.get(method_path(req)) == Some(&true) => return Ok(None),
+            (None, None) => return Err(Status::invalid_argument("Missing tenant id")),
+        };
+        Ok(Some(tenant_id.to_string()))
+    }
+
+    /// Resolves the tenant `tenant_id`, checking that it may call the method at `path`.
+    async fn resolve(&self, tenant_id: &str, path: &str) -> Result<Tenant, Status> {
+        let tenant = self
+            .resolver
+            .resolve(tenant_id)
+            .await?
+            .ok_or_else(|| Status::permission_denied("Unknown tenant"))?;
+        if !tenant.policy.is_method_enabled(path) {
+            return Err(Status::permission_denied(format!(
+                "Method {} is not enabled for the tenant",
+                path
+            )));
+        }
+        Ok(tenant)
+    }
+}
+
+impl<R: TenantResolver> fmt::Debug for TenantMiddleware<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TenantMiddleware")
+            .field("header", &self.header)
+            .field("principal_attribute", &self.principal_attribute)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<R, S> Middleware<S> for TenantMiddleware<R>
+where
+    R: TenantResolver,
+    S: ServiceBound,
+    S::Future: Send,
+{
+    async fn call(
+        &self,
+        mut req: Request<Body>,
+        mut service: S,
+    ) -> Result<Response<Body>, S::Error> {
+        let tenant_id = match self.tenant_id(&req) {
+            Ok(Some(tenant_id)) => tenant_id,
+            Ok(None) => return service.call(req).await,
+            Err(status) => return Ok(status.into_http()),
+        };
+        let path = method_path(&req).to_string();
+        match self.resolve(&tenant_id, &path).await {
+            Ok(tenant) => {
+                req.extensions_mut().insert(tenant);
+                service.call(req).await
+            }
+            Err(status) => Ok(status.into_http()),
+        }
+    }
+}
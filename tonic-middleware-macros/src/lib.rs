@@ -0,0 +1,376 @@
+//! Attribute macros turning async functions into `tonic-middleware` interceptors and
+//! middlewares. Use them through the `macros` feature of `tonic-middleware`, which re-exports
+//! them, as the generated code refers to its traits. The generated code names the crate
+//! `::tonic_middleware`; crates renaming or re-exporting it pass its path with
+//! `crate = path`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::{
+    parse_macro_input, Attribute, FnArg, GenericArgument, Ident, ItemFn, Pat, Path, PathArguments,
+    ReturnType, Token, Type,
+};
+
+/// Turns an `async fn(req: Request<Body>) -> Result<Request<Body>, Status>` into a type
+/// implementing `RequestInterceptor`.
+///
+/// The type is named after the function in `PascalCase`, or as given with
+/// `#[interceptor(name = MyInterceptor)]`, and gets the visibility and doc comments of the
+/// function, which stays callable on its own. Parameters after the request are state: they
+/// become fields of the type, set with its `new` constructor. State parameters taken by
+/// reference (`&T`) borrow the field, others get a clone of it for every call.
+///
+/// The path of the `tonic-middleware` crate is set with `crate = path`, e.g.
+/// `#[interceptor(crate = my_framework::tonic_middleware)]`.
+///
+/// # Example
+///
+/// ```
+/// use std::collections::HashSet;
+/// use tonic::body::Body;
+/// use tonic::codegen::http::Request;
+/// use tonic::Status;
+/// use tonic_middleware::interceptor;
+///
+/// /// Rejects calls of unknown tenants.
+/// #[interceptor]
+/// async fn require_tenant(
+///     req: Request<Body>,
+///     tenants: &HashSet<String>,
+/// ) -> Result<Request<Body>, Status> {
+///     match req.headers().get("x-tenant-id").and_then(|v| v.to_str().ok()) {
+///         Some(tenant) if tenants.contains(tenant) => Ok(req),
+///         _ => Err(Status::permission_denied("Unknown tenant")),
+///     }
+/// }
+///
+/// let interceptor = RequireTenant::new(HashSet::from(["acme".to_string()]));
+/// ```
+#[proc_macro_attribute]
+pub fn interceptor(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr as MacroArgs);
+    let function = parse_macro_input!(item as ItemFn);
+    expand(args, function, Kind::Interceptor)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Turns an `async fn(req: Request<Body>, next: Next) -> Result<Response<Body>, E>` into a type
+/// implementing `Middleware<S>` for every service `S` with the error type `E`, `Infallible` for
+/// tonic services.
+///
+/// The type is named after the function in `PascalCase`, or as given with
+/// `#[middleware(name = MyMiddleware)]`, and gets the visibility and doc comments of the
+/// function, which stays callable on its own. Parameters after `next` are state: they become
+/// fields of the type, set with its `new` constructor. State parameters taken by reference
+/// (`&T`) borrow the field, others get a clone of it for every call.
+///
+/// The path of the `tonic-middleware` crate is set with `crate = path`, e.g.
+/// `#[middleware(crate = my_framework::tonic_middleware)]`.
+///
+/// # Example
+///
+/// ```
+/// use std::convert::Infallible;
+/// use tonic::body::Body;
+/// use tonic::codegen::http::{Request, Response};
+/// use tonic_middleware::{middleware, Next};
+///
+/// /// Tells clients which instance served the call.
+/// #[middleware]
+/// async fn served_by(
+///     req: Request<Body>,
+///     next: Next,
+///     instance: &str,
+/// ) -> Result<Response<Body>, Infallible> {
+///     let mut response = next.run(req).await?;
+///     if let Ok(value) = instance.parse() {
+///         response.headers_mut().insert("x-served-by", value);
+///     }
+///     Ok(response)
+/// }
+///
+/// let middleware = ServedBy::new("orders-1".into());
+/// ```
+#[proc_macro_attribute]
+pub fn middleware(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr as MacroArgs);
+    let function = parse_macro_input!(item as ItemFn);
+    expand(args, function, Kind::Middleware)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Interceptor,
+    Middleware,
+}
+
+impl Kind {
+    /// Number of parameters passed by the pipeline, before the state parameters.
+    fn pipeline_params(self) -> usize {
+        match self {
+            Kind::Interceptor => 1,
+            Kind::Middleware => 2,
+        }
+    }
+
+    fn signature(self) -> &'static str {
+        match self {
+            Kind::Interceptor => {
+                "async fn(req: Request<Body>, ..) -> Result<Request<Body>, Status>"
+            }
+            Kind::Middleware => {
+                "async fn(req: Request<Body>, next: Next, ..) -> Result<Response<Body>, E>"
+            }
+        }
+    }
+}
+
+/// Arguments of the attribute: `name = Ident` and `crate = Path`, both optional.
+struct MacroArgs {
+    name: Option<Ident>,
+    krate: Option<Path>,
+}
+
+impl Parse for MacroArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = MacroArgs {
+            name: None,
+            krate: None,
+        };
+        while !input.is_empty() {
+            if input.peek(Token![crate]) {
+                input.parse::<Token![crate]>()?;
+                input.parse::<Token![=]>()?;
+                args.krate = Some(input.parse()?);
+            } else {
+                let key: Ident = input.parse()?;
+                if key != "name" {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        "expected `name = TypeName` or `crate = path`",
+                    ));
+                }
+                input.parse::<Token![=]>()?;
+                args.name = Some(input.parse()?);
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// A state parameter, stored as a field of the generated type.
+struct StateParam {
+    ident: Ident,
+    ty: Type,
+    by_ref: bool,
+}
+
+fn expand(args: MacroArgs, function: ItemFn, kind: Kind) -> syn::Result<TokenStream2> {
+    let sig = &function.sig;
+    if sig.asyncness.is_none() {
+        return Err(syn::Error::new_spanned(
+            sig.fn_token,
+            format!("expected {}", kind.signature()),
+        ));
+    }
+    if !sig.generics.params.is_empty() || sig.generics.where_clause.is_some() {
+        return Err(syn::Error::new_spanned(
+            &sig.generics,
+            "generic functions are not supported",
+        ));
+    }
+    if sig.inputs.len() < kind.pipeline_params() {
+        return Err(syn::Error::new_spanned(
+            &sig.inputs,
+            format!("expected {}", kind.signature()),
+        ));
+    }
+
+    let state = sig
+        .inputs
+        .iter()
+        .skip(kind.pipeline_params())
+        .map(state_param)
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    let fn_ident = &sig.ident;
+    let vis = &function.vis;
+    let name = args
+        .name
+        .unwrap_or_else(|| format_ident!("{}", pascal_case(&fn_ident.to_string())));
+    let docs = function
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .collect::<Vec<&Attribute>>();
+    let krate = args
+        .krate
+        .map_or_else(|| quote!(::tonic_middleware), |krate| quote!(#krate));
+
+    let field_idents = state.iter().map(|p| &p.ident).collect::<Vec<_>>();
+    let field_types = state.iter().map(|p| &p.ty).collect::<Vec<_>>();
+    let state_args = state.iter().map(|p| {
+        let ident = &p.ident;
+        if p.by_ref {
+            quote!(&self.#ident)
+        } else {
+            quote!(::core::clone::Clone::clone(&self.#ident))
+        }
+    });
+
+    let definition = if state.is_empty() {
+        quote! {
+            #(#docs)*
+            #[derive(Clone, Copy, Debug, Default)]
+            #vis struct #name;
+
+            impl #name {
+                #[doc = concat!("Creates a new `", stringify!(#name), "`.")]
+                #vis fn new() -> Self {
+                    #name
+                }
+            }
+        }
+    } else {
+        quote! {
+            #(#docs)*
+            #[derive(Clone)]
+            #vis struct #name {
+                #(#field_idents: #field_types,)*
+            }
+
+            impl #name {
+                #[doc = concat!("Creates a new `", stringify!(#name), "` with the given state.")]
+                #vis fn new(#(#field_idents: #field_types),*) -> Self {
+                    #name { #(#field_idents),* }
+                }
+            }
+        }
+    };
+
+    let private = quote!(#krate::__private);
+    let implementation = match kind {
+        Kind::Interceptor => quote! {
+            #[#private::async_trait]
+            impl #krate::RequestInterceptor for #name {
+                async fn intercept(
+                    &self,
+                    req: #private::Request<#private::Body>,
+                ) -> ::core::result::Result<#private::Request<#private::Body>, #private::Status> {
+                    #fn_ident(req, #(#state_args),*).await
+                }
+            }
+        },
+        Kind::Middleware => {
+            let error = error_type(&sig.output).ok_or_else(|| {
+                syn::Error::new_spanned(&sig.output, format!("expected {}", kind.signature()))
+            })?;
+            quote! {
+                #[#private::async_trait]
+                impl<S> #krate::Middleware<S> for #name
+                where
+                    S: #krate::ServiceBound<Error = #error>,
+                    S::Future: ::core::marker::Send,
+                {
+                    async fn call(
+                        &self,
+                        req: #private::Request<#private::Body>,
+                        service: S,
+                    ) -> ::core::result::Result<#private::Response<#private::Body>, S::Error> {
+                        #fn_ident(req, #krate::Next::new(service), #(#state_args),*).await
+                    }
+                }
+            }
+        }
+    };
+
+    Ok(quote! {
+        #function
+        #definition
+        #implementation
+    })
+}
+
+fn state_param(arg: &FnArg) -> syn::Result<StateParam> {
+    let FnArg::Typed(arg) = arg else {
+        return Err(syn::Error::new_spanned(arg, "methods are not supported"));
+    };
+    let Pat::Ident(pat) = &*arg.pat else {
+        return Err(syn::Error::new_spanned(
+            &arg.pat,
+            "state parameters must be plain identifiers",
+        ));
+    };
+    match &*arg.ty {
+        Type::Reference(reference) if reference.mutability.is_some() => {
+            Err(syn::Error::new_spanned(
+                reference,
+                "state is shared between calls and cannot be borrowed mutably",
+            ))
+        }
+        Type::Reference(reference) => Ok(StateParam {
+            ident: pat.ident.clone(),
+            ty: owned_type(&reference.elem),
+            by_ref: true,
+        }),
+        ty => Ok(StateParam {
+            ident: pat.ident.clone(),
+            ty: ty.clone(),
+            by_ref: false,
+        }),
+    }
+}
+
+/// Returns the type stored for a state parameter borrowed as `&ty`.
+fn owned_type(ty: &Type) -> Type {
+    match ty {
+        Type::Path(path) if path.path.is_ident("str") => syn::parse_quote!(::std::string::String),
+        Type::Slice(slice) => {
+            let elem = &slice.elem;
+            syn::parse_quote!(::std::vec::Vec<#elem>)
+        }
+        ty => ty.clone(),
+    }
+}
+
+/// Returns `E` of a `Result<_, E>` return type.
+fn error_type(output: &ReturnType) -> Option<&Type> {
+    let ReturnType::Type(_, ty) = output else {
+        return None;
+    };
+    let Type::Path(path) = &**ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Result" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.iter().nth(1)? {
+        GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    }
+}
+
+fn pascal_case(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}